@@ -0,0 +1,458 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::io::{self, BufRead, Read, Write};
+
+use super::helpers::EncryptionError;
+
+/// Label of cipher's own armor lines
+pub const CIPHER_LABEL: &str = "CIPHER MESSAGE";
+/// Label of OpenPGP messages, e.g. from `gpg --symmetric --armor`
+pub const PGP_LABEL: &str = "PGP MESSAGE";
+
+/// Base64 characters per armored line
+const LINE_WIDTH: usize = 64;
+/// Binary bytes encoded on one full line
+const LINE_BYTES: usize = LINE_WIDTH / 4 * 3;
+
+const CRC24_INIT: u32 = 0x00B7_04CE;
+const CRC24_POLY: u32 = 0x0186_4CFB;
+
+/// Updates an OpenPGP-style CRC-24 with `data`
+fn crc24_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc & 0x00FF_FFFF
+}
+
+/// Encodes a CRC-24 as the `=XXXX` checksum line
+fn checksum_line(crc: u32) -> String {
+    format!("={}", STANDARD.encode(&crc.to_be_bytes()[1..]))
+}
+
+/// Streaming writer that wraps everything written to it in an ASCII armor block
+///
+/// Layout:
+/// ```text
+/// -----BEGIN CIPHER MESSAGE-----
+/// Version: cipher 0.2.0
+///
+/// <base64, 64 characters per line>
+/// =<base64 CRC-24 of the binary data>
+/// -----END CIPHER MESSAGE-----
+/// ```
+/// [`ArmorWriter::finish`] must be called to write the last line, the checksum and the footer.
+pub struct ArmorWriter<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+    crc: u32,
+    label: &'static str,
+}
+
+impl<W: Write> ArmorWriter<W> {
+    /// Writes the armor header to `inner`
+    pub fn new(inner: W) -> io::Result<Self> {
+        Self::with_label(inner, CIPHER_LABEL)
+    }
+
+    /// Writes an armor header with another label, e.g. [`PGP_LABEL`]
+    pub fn with_label(mut inner: W, label: &'static str) -> io::Result<Self> {
+        writeln!(inner, "-----BEGIN {}-----", label)?;
+        writeln!(inner, "Version: cipher {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(inner)?;
+        Ok(Self {
+            inner,
+            pending: Vec::with_capacity(LINE_BYTES),
+            crc: CRC24_INIT,
+            label,
+        })
+    }
+
+    fn write_line(&mut self, data: &[u8]) -> io::Result<()> {
+        writeln!(self.inner, "{}", STANDARD.encode(data))
+    }
+
+    /// Writes the remaining data, the checksum line and the footer
+    ///
+    /// # Returns
+    /// The inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.write_line(&pending)?;
+        }
+        writeln!(self.inner, "{}", checksum_line(self.crc))?;
+        writeln!(self.inner, "-----END {}-----", self.label)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ArmorWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.crc = crc24_update(self.crc, buf);
+        self.pending.extend_from_slice(buf);
+
+        let full_lines = self.pending.len() / LINE_BYTES * LINE_BYTES;
+        if full_lines > 0 {
+            let pending = std::mem::take(&mut self.pending);
+            for line in pending[..full_lines].chunks(LINE_BYTES) {
+                self.write_line(line)?;
+            }
+            self.pending = pending[full_lines..].to_vec();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Streaming reader that decodes an ASCII armor block
+///
+/// Text before the header (e.g. an email body) is skipped, lines may use CRLF
+/// endings and any line width, and the checksum line is verified when present.
+pub struct ArmorReader<R: BufRead> {
+    inner: R,
+    end: String,
+    decoded: Vec<u8>,
+    position: usize,
+    /// Base64 characters not yet forming a complete 4-character group
+    pending: String,
+    crc: u32,
+    expected_crc: Option<u32>,
+    verify_checksum: bool,
+    /// Decode damaged armor as far as possible instead of failing
+    recover: bool,
+    finished: bool,
+}
+
+impl<R: BufRead> ArmorReader<R> {
+    /// Skips to the armor header and past the header lines
+    pub fn new(inner: R) -> io::Result<Self> {
+        Self::with_label(inner, CIPHER_LABEL)
+    }
+
+    /// Reads an armor block with another label, e.g. [`PGP_LABEL`]
+    pub fn with_label(mut inner: R, label: &str) -> io::Result<Self> {
+        let begin = format!("-----BEGIN {}-----", label);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if inner.read_line(&mut line)? == 0 {
+                return Err(invalid_data("Missing armor header"));
+            }
+            if line.trim() == begin {
+                break;
+            }
+        }
+
+        let mut reader = Self {
+            inner,
+            end: format!("-----END {}-----", label),
+            decoded: Vec::new(),
+            position: 0,
+            pending: String::new(),
+            crc: CRC24_INIT,
+            expected_crc: None,
+            verify_checksum: true,
+            recover: false,
+            finished: false,
+        };
+
+        // Header lines ("Key: value") end at the first blank line; tolerate
+        // armor without headers by treating the first other line as body
+        loop {
+            line.clear();
+            if reader.inner.read_line(&mut line)? == 0 {
+                return Err(invalid_data("Missing armor footer"));
+            }
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if !trimmed.contains(": ") {
+                reader.process_line(trimmed)?;
+                break;
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Accepts a checksum mismatch, e.g. when recovering a damaged file
+    pub fn without_checksum(mut self) -> Self {
+        self.verify_checksum = false;
+        self
+    }
+
+    /// Decodes damaged armor as far as possible, for recovery mode
+    ///
+    /// Besides skipping the checksum, a line that isn't valid base64 decodes to
+    /// zeros of the same length, so only the chunks it covers fail to decrypt
+    /// and everything after it keeps its offset. A missing footer ends the data.
+    pub fn recovering(mut self) -> Self {
+        self.verify_checksum = false;
+        self.recover = true;
+        self
+    }
+
+    /// Decodes one armored line into `decoded`
+    fn process_line(&mut self, line: &str) -> io::Result<()> {
+        if line == self.end {
+            self.finish()?;
+            return Ok(());
+        }
+        if let Some(checksum) = line.strip_prefix('=') {
+            match STANDARD.decode(checksum).ok().filter(|bytes| bytes.len() == 3) {
+                Some(bytes) => self.expected_crc = Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]])),
+                None if self.recover => {}
+                None => return Err(invalid_data("Malformed armor checksum")),
+            }
+            return Ok(());
+        }
+
+        self.pending.push_str(line);
+        let complete = self.pending.len() / 4 * 4;
+        let decoded = match STANDARD.decode(&self.pending[..complete]) {
+            Ok(decoded) => decoded,
+            Err(_) if self.recover => vec![0u8; complete / 4 * 3],
+            Err(_) => return Err(invalid_data("Armor body is not valid base64")),
+        };
+        self.pending = self.pending[complete..].to_string();
+
+        self.crc = crc24_update(self.crc, &decoded);
+        self.decoded.extend_from_slice(&decoded);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() && !self.recover {
+            return Err(invalid_data("Armor body is not valid base64"));
+        }
+        if self.verify_checksum && self.expected_crc.is_some_and(|expected| expected != self.crc) {
+            return Err(invalid_data("Armor checksum mismatch"));
+        }
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Reads lines until there is decoded data available or the footer is reached
+    fn fill(&mut self) -> io::Result<()> {
+        let mut line = String::new();
+        while self.position >= self.decoded.len() && !self.finished {
+            self.decoded.clear();
+            self.position = 0;
+
+            line.clear();
+            if self.inner.read_line(&mut line)? == 0 {
+                if self.recover {
+                    return self.finish();
+                }
+                return Err(invalid_data("Missing armor footer"));
+            }
+            let trimmed = line.trim();
+            if !trimmed.is_empty() {
+                self.process_line(trimmed)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ArmorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let available = &self.decoded[self.position..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Wraps binary data in an ASCII armor block
+pub fn armor(data: &[u8]) -> String {
+    // Writing to a Vec cannot fail and armor output is pure ASCII
+    let output = ArmorWriter::new(Vec::new())
+        .and_then(|mut writer| {
+            writer.write_all(data)?;
+            writer.finish()
+        })
+        .unwrap_or_default();
+    String::from_utf8(output).unwrap_or_default()
+}
+
+/// Extracts the binary data from an ASCII armor block
+pub fn dearmor(text: &str) -> Result<Vec<u8>, EncryptionError> {
+    let mut reader = ArmorReader::new(text.as_bytes())
+        .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+    let mut output = Vec::new();
+    reader
+        .read_to_end(&mut output)
+        .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+    Ok(output)
+}
+
+/// Returns true if `data` starts with an armor header, ignoring leading whitespace
+pub fn is_armored(data: &[u8]) -> bool {
+    is_armored_with(data, CIPHER_LABEL)
+}
+
+/// Like [`is_armored`] for an armor header with another label
+pub fn is_armored_with(data: &[u8], label: &str) -> bool {
+    let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+    data[start..].starts_with(format!("-----BEGIN {}-----", label).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lines between the blank line after the headers and the checksum line
+    fn body_lines(armored: &str) -> Vec<&str> {
+        armored
+            .lines()
+            .skip_while(|line| !line.is_empty())
+            .skip(1)
+            .take_while(|line| !line.starts_with('='))
+            .collect()
+    }
+
+    #[test]
+    fn wraps_lines_at_the_line_width() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let armored = armor(&data);
+        let lines = body_lines(&armored);
+        let (last, full) = lines.split_last().unwrap();
+        assert!(full.iter().all(|line| line.len() == LINE_WIDTH));
+        assert!(!last.is_empty() && last.len() <= LINE_WIDTH);
+        assert_eq!(lines.len(), data.len().div_ceil(LINE_BYTES));
+    }
+
+    #[test]
+    fn writes_header_checksum_and_footer() {
+        let armored = armor(b"hello armor");
+        let lines: Vec<&str> = armored.lines().collect();
+        assert_eq!(lines[0], "-----BEGIN CIPHER MESSAGE-----");
+        assert!(lines[1].starts_with("Version: cipher "));
+        assert_eq!(lines[2], "");
+        assert_eq!(lines[lines.len() - 2], checksum_line(crc24_update(CRC24_INIT, b"hello armor")));
+        assert_eq!(lines[lines.len() - 1], "-----END CIPHER MESSAGE-----");
+        assert!(is_armored(armored.as_bytes()));
+        assert!(!is_armored_with(armored.as_bytes(), PGP_LABEL));
+    }
+
+    #[test]
+    fn round_trips_all_lengths_around_a_line() {
+        for len in [0, 1, 2, 3, LINE_BYTES - 1, LINE_BYTES, LINE_BYTES + 1, 10 * LINE_BYTES] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7) as u8).collect();
+            assert_eq!(dearmor(&armor(&data)).unwrap(), data, "{len}");
+        }
+    }
+
+    #[test]
+    fn parses_surrounding_text_crlf_and_missing_headers() {
+        let armored = armor(b"embedded in an email");
+        let email = format!("Hi,\r\n\r\nhere it is:\r\n\r\n{}\r\nBye\r\n", armored.replace('\n', "\r\n"));
+        assert_eq!(dearmor(&email).unwrap(), b"embedded in an email");
+
+        // No header lines and no blank line, the body starts right away
+        let bare: String = armored
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with("Version:"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert_eq!(dearmor(&bare).unwrap(), b"embedded in an email");
+    }
+
+    #[test]
+    fn accepts_other_line_widths() {
+        let data = vec![42u8; 300];
+        let encoded = STANDARD.encode(&data);
+        let body: Vec<&str> = encoded.as_bytes().chunks(76).map(|line| std::str::from_utf8(line).unwrap()).collect();
+        let armored = format!(
+            "-----BEGIN CIPHER MESSAGE-----\n\n{}\n-----END CIPHER MESSAGE-----\n",
+            body.join("\n")
+        );
+        assert_eq!(dearmor(&armored).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_bad_armor() {
+        let armored = armor(b"some secret data that spans a line or two, long enough to matter");
+        let lines: Vec<&str> = armored.lines().collect();
+        let footer = lines.len() - 1;
+        let checksum = lines.len() - 2;
+
+        let without_footer = lines[..footer].join("\n");
+        let bad_checksum = armored.replace(lines[checksum], "=AAAA");
+        let bad_base64 = armored.replace(lines[3], &lines[3].replacen(|c: char| c.is_ascii_alphanumeric(), "*", 1));
+        let odd_length = armored.replace(lines[3], &lines[3][1..]);
+        let malformed_checksum = armored.replace(lines[checksum], "=A");
+
+        for (name, text) in [
+            ("no header", "just some text".to_string()),
+            ("no footer", without_footer),
+            ("checksum mismatch", bad_checksum),
+            ("invalid base64", bad_base64),
+            ("truncated base64", odd_length),
+            ("malformed checksum", malformed_checksum),
+        ] {
+            assert!(matches!(dearmor(&text), Err(EncryptionError::InvalidFormat(_))), "{name}");
+        }
+    }
+
+    #[test]
+    fn skips_checksum_only_when_asked() {
+        let armored = armor(b"checksum test");
+        let checksum = armored.lines().rev().nth(1).unwrap().to_string();
+        let tampered = armored.replace(&checksum, "=AAAA");
+        assert!(dearmor(&tampered).is_err());
+
+        let mut reader = ArmorReader::new(tampered.as_bytes()).unwrap().without_checksum();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"checksum test");
+    }
+
+    #[test]
+    fn recovery_zero_fills_bad_lines_and_keeps_offsets() {
+        let data: Vec<u8> = (0..=255).cycle().take(LINE_BYTES * 4).collect();
+        let armored = armor(&data);
+        let bad_line = body_lines(&armored)[1].to_string();
+        let damaged = armored.replace(&bad_line, &format!("!{}", &bad_line[1..]));
+        assert!(dearmor(&damaged).is_err());
+
+        let mut reader = ArmorReader::new(damaged.as_bytes()).unwrap().recovering();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        let mut expected = data.clone();
+        expected[LINE_BYTES..LINE_BYTES * 2].fill(0);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn recovery_ends_truncated_armor_at_the_last_line() {
+        let data: Vec<u8> = (0..=255).cycle().take(LINE_BYTES * 3).collect();
+        let armored = armor(&data);
+        let lines = body_lines(&armored);
+        let truncated = armored[..armored.find(lines[2]).unwrap()].to_string();
+        assert!(dearmor(&truncated).is_err());
+
+        let mut reader = ArmorReader::new(truncated.as_bytes()).unwrap().recovering();
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data[..LINE_BYTES * 2]);
+    }
+}
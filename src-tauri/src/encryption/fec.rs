@@ -0,0 +1,356 @@
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::{
+    fs::{remove_file, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    format::{ChunkCipher, ChunkScheme},
+    helpers::{read_chunk, EncryptionError, CHUNK_SIZE},
+};
+
+/// Largest group, data and parity chunks together
+///
+/// Encoding buffers the data chunks of a group and repairs read the whole group,
+/// so a group costs up to this many encrypted chunks (about 1 MiB each) of memory.
+pub const MAX_GROUP_CHUNKS: usize = 64;
+
+/// Reed-Solomon parity over groups of encrypted chunks
+///
+/// Every `data_chunks` encrypted chunks form a group with `parity_chunks` parity
+/// shards. The parity of all groups is appended after the last chunk:
+/// ```text
+/// header | chunk 0 .. chunk n-1 | parity of group 0 | parity of group 1 | ...
+/// ```
+/// Shards are as long as the first (largest) chunk of their group, shorter
+/// chunks are zero-padded. Chunk offsets are unchanged, so files stay readable
+/// chunk by chunk and the parity is only read when a chunk fails to authenticate.
+/// Authentication locates the damaged chunks, which makes them erasures: up to
+/// `parity_chunks` damaged chunks per group can be rebuilt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecParams {
+    pub data_chunks: u8,
+    pub parity_chunks: u8,
+}
+
+impl FecParams {
+    /// Validates the ratio; a group holds at most [`MAX_GROUP_CHUNKS`] chunks
+    pub fn new(data_chunks: u8, parity_chunks: u8) -> Result<Self, EncryptionError> {
+        if data_chunks == 0 || parity_chunks == 0 || data_chunks as usize + parity_chunks as usize > MAX_GROUP_CHUNKS {
+            return Err(EncryptionError::InvalidFormat(format!(
+                "Invalid error correction ratio {}:{}",
+                data_chunks, parity_chunks
+            )));
+        }
+        Ok(Self { data_chunks, parity_chunks })
+    }
+
+    /// Parses the header extension value
+    pub fn from_extension(value: &[u8]) -> Result<Self, EncryptionError> {
+        match value {
+            [data_chunks, parity_chunks] => Self::new(*data_chunks, *parity_chunks),
+            _ => Err(EncryptionError::InvalidFormat("Malformed error correction extension".to_string())),
+        }
+    }
+
+    /// Encodes the parameters as a header extension value
+    pub fn to_extension(self) -> Vec<u8> {
+        vec![self.data_chunks, self.parity_chunks]
+    }
+
+    fn codec(self) -> Result<ReedSolomon, EncryptionError> {
+        ReedSolomon::new(self.data_chunks as usize, self.parity_chunks as usize)
+            .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))
+    }
+}
+
+/// Size of the chunk section holding `plaintext_len` bytes, i.e. the offset of the parity
+pub fn data_section_len(plaintext_len: u64, scheme: ChunkScheme) -> u64 {
+    let full_chunks = plaintext_len / CHUNK_SIZE as u64;
+    let remainder = plaintext_len % CHUNK_SIZE as u64;
+    let last_chunk = if remainder > 0 { remainder + scheme.overhead() as u64 } else { 0 };
+    full_chunks * scheme.encrypted_chunk_size() as u64 + last_chunk
+}
+
+/// Size of the parity appended for `plaintext_len` bytes
+pub fn parity_len(plaintext_len: u64, scheme: ChunkScheme, params: FecParams) -> u64 {
+    let chunk_size = scheme.encrypted_chunk_size() as u64;
+    let group_len = chunk_size * params.data_chunks as u64;
+    let data_len = data_section_len(plaintext_len, scheme);
+    // Shards are as long as the first chunk of their group
+    let last_shard = (data_len % group_len).min(chunk_size);
+    (data_len / group_len * chunk_size + last_shard) * params.parity_chunks as u64
+}
+
+/// Computes parity while chunks are written and stores it in a side file
+///
+/// The side file sits next to the output and is removed when the encoder is
+/// dropped, so failed encryptions don't leave it behind.
+pub struct ParityEncoder {
+    params: FecParams,
+    codec: ReedSolomon,
+    group: Vec<Vec<u8>>,
+    path: PathBuf,
+    parity: BufWriter<File>,
+}
+
+impl ParityEncoder {
+    /// Creates the side file `<output>.parity`
+    pub fn new(params: FecParams, output_path: &Path) -> Result<Self, EncryptionError> {
+        let mut path = output_path.as_os_str().to_os_string();
+        path.push(".parity");
+        let path = PathBuf::from(path);
+        let parity = BufWriter::new(File::create(&path)?);
+        Ok(Self {
+            params,
+            codec: params.codec()?,
+            group: Vec::with_capacity(params.data_chunks as usize),
+            path,
+            parity,
+        })
+    }
+
+    /// Adds the next encrypted chunk
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), EncryptionError> {
+        self.group.push(chunk.to_vec());
+        if self.group.len() == self.params.data_chunks as usize {
+            self.flush_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush_group(&mut self) -> Result<(), EncryptionError> {
+        if self.group.is_empty() {
+            return Ok(());
+        }
+        let shard_len = self.group[0].len();
+        let mut shards = std::mem::take(&mut self.group);
+        for shard in shards.iter_mut() {
+            shard.resize(shard_len, 0);
+        }
+        shards.resize(self.params.data_chunks as usize, vec![0u8; shard_len]);
+        shards.extend((0..self.params.parity_chunks).map(|_| vec![0u8; shard_len]));
+
+        self.codec
+            .encode(&mut shards)
+            .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+        for parity in &shards[self.params.data_chunks as usize..] {
+            self.parity.write_all(parity)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the last partial group and appends all parity to `writer`
+    pub fn finish<W: Write>(mut self, writer: &mut W) -> Result<(), EncryptionError> {
+        self.flush_group()?;
+        self.parity.flush()?;
+        let mut parity = File::open(&self.path)?;
+        io::copy(&mut parity, writer)?;
+        Ok(())
+    }
+}
+
+impl Drop for ParityEncoder {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// Rebuilds chunk `index` from the other chunks of its group and the parity
+///
+/// # Arguments
+/// * `source` - The encrypted file
+/// * `cipher` - Used to find out which chunks of the group are damaged
+/// * `data_start` - Offset of the first chunk
+/// * `plaintext_len` - Size of the decrypted content, from the file metadata
+///
+/// # Returns
+/// The decrypted chunk, or `CorruptedChunk` if the group has more damaged
+/// chunks than parity shards
+pub fn repair_chunk<R: Read + Seek>(
+    source: &mut R,
+    params: FecParams,
+    cipher: &ChunkCipher,
+    data_start: u64,
+    plaintext_len: u64,
+    index: u64,
+) -> Result<Vec<u8>, EncryptionError> {
+    let scheme = cipher.scheme();
+    let chunk_size = scheme.encrypted_chunk_size() as u64;
+    let data_len = data_section_len(plaintext_len, scheme);
+    let chunk_count = data_len.div_ceil(chunk_size);
+    let data_chunks = params.data_chunks as u64;
+    let parity_chunks = params.parity_chunks as u64;
+
+    let group = index / data_chunks;
+    let first = group * data_chunks;
+    let last = (first + data_chunks).min(chunk_count);
+    let chunk_len = |i: u64| (data_len - i * chunk_size).min(chunk_size) as usize;
+    let shard_len = chunk_len(first);
+
+    // Only the last group can have shorter shards, so earlier parity is full size
+    let parity_offset = data_start + data_len + group * parity_chunks * chunk_size;
+
+    let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity((data_chunks + parity_chunks) as usize);
+    for i in first..first + data_chunks {
+        if i >= last {
+            shards.push(Some(vec![0u8; shard_len]));
+            continue;
+        }
+        let mut buffer = vec![0u8; chunk_len(i)];
+        source.seek(SeekFrom::Start(data_start + i * chunk_size))?;
+        let n = read_chunk(source, &mut buffer)?;
+        let intact = n == buffer.len() && cipher.open_chunk(i, &buffer).is_ok();
+        shards.push(intact.then(|| {
+            buffer.resize(shard_len, 0);
+            buffer
+        }));
+    }
+
+    source.seek(SeekFrom::Start(parity_offset))?;
+    for _ in 0..parity_chunks {
+        let mut buffer = vec![0u8; shard_len];
+        let n = read_chunk(source, &mut buffer)?;
+        shards.push((n == shard_len).then_some(buffer));
+    }
+
+    params
+        .codec()?
+        .reconstruct_data(&mut shards)
+        .map_err(|_| EncryptionError::CorruptedChunk { index })?;
+
+    let mut chunk = shards[(index - first) as usize]
+        .take()
+        .ok_or(EncryptionError::CorruptedChunk { index })?;
+    chunk.truncate(chunk_len(index));
+    // Damaged parity reconstructs garbage, which fails authentication here
+    cipher
+        .open_chunk(index, &chunk)
+        .map_err(|_| EncryptionError::CorruptedChunk { index })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{format::FileHeader, helpers::NONCE_SIZE, test_util::test_dir};
+    use orion::hazardous::aead::xchacha20poly1305::SecretKey;
+    use std::io::Cursor;
+
+    /// Two full groups of 3 chunks and a partial one whose last chunk is short
+    const PLAINTEXT_LEN: usize = 7 * CHUNK_SIZE + 1000;
+
+    fn cipher() -> ChunkCipher {
+        let key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        FileHeader::new([1u8; NONCE_SIZE]).cipher_with_key(key)
+    }
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i.wrapping_mul(13) ^ (i >> 12)) as u8).collect()
+    }
+
+    /// Encrypts `plaintext` into the chunk section followed by the parity, as `encrypt_file` lays it out
+    fn protected(cipher: &ChunkCipher, params: FecParams, plaintext: &[u8], name: &str) -> Vec<u8> {
+        let dir = test_dir(name);
+        let mut encoder = ParityEncoder::new(params, &dir.join("file.enc")).unwrap();
+        let mut file = Vec::new();
+        for (index, chunk) in plaintext.chunks(CHUNK_SIZE).enumerate() {
+            let sealed = cipher.seal_chunk(index as u64, chunk).unwrap();
+            file.extend_from_slice(&sealed);
+            encoder.push(&sealed).unwrap();
+        }
+        encoder.finish(&mut file).unwrap();
+        file
+    }
+
+    fn damage(file: &mut [u8], cipher: &ChunkCipher, index: u64) {
+        let offset = index as usize * cipher.scheme().encrypted_chunk_size();
+        file[offset + 100] ^= 0xff;
+    }
+
+    fn repair(file: &[u8], cipher: &ChunkCipher, params: FecParams, index: u64) -> Result<Vec<u8>, EncryptionError> {
+        repair_chunk(&mut Cursor::new(file), params, cipher, 0, PLAINTEXT_LEN as u64, index)
+    }
+
+    #[test]
+    fn appends_the_expected_parity_length() {
+        let cipher = cipher();
+        let params = FecParams::new(3, 2).unwrap();
+        let file = protected(&cipher, params, &content(PLAINTEXT_LEN), "length");
+        let data_len = data_section_len(PLAINTEXT_LEN as u64, cipher.scheme());
+        assert_eq!(file.len() as u64, data_len + parity_len(PLAINTEXT_LEN as u64, cipher.scheme(), params));
+    }
+
+    #[test]
+    fn repairs_up_to_parity_chunks_per_group() {
+        let cipher = cipher();
+        let params = FecParams::new(3, 2).unwrap();
+        let plaintext = content(PLAINTEXT_LEN);
+        let mut file = protected(&cipher, params, &plaintext, "repair");
+
+        // Two chunks of the first group, and the short last chunk of the partial group
+        for index in [0, 2, 7] {
+            damage(&mut file, &cipher, index);
+        }
+        for index in [0, 2, 7] {
+            let start = index as usize * CHUNK_SIZE;
+            let end = (start + CHUNK_SIZE).min(PLAINTEXT_LEN);
+            assert_eq!(repair(&file, &cipher, params, index).unwrap(), plaintext[start..end], "chunk {index}");
+        }
+    }
+
+    #[test]
+    fn repairs_erased_chunks() {
+        let cipher = cipher();
+        let params = FecParams::new(3, 2).unwrap();
+        let plaintext = content(PLAINTEXT_LEN);
+        let mut file = protected(&cipher, params, &plaintext, "erase");
+
+        let chunk_size = cipher.scheme().encrypted_chunk_size();
+        file[3 * chunk_size..5 * chunk_size].fill(0);
+        assert_eq!(repair(&file, &cipher, params, 4).unwrap(), plaintext[4 * CHUNK_SIZE..5 * CHUNK_SIZE]);
+    }
+
+    #[test]
+    fn one_more_damaged_chunk_fails_cleanly() {
+        let cipher = cipher();
+        let params = FecParams::new(3, 2).unwrap();
+        let mut file = protected(&cipher, params, &content(PLAINTEXT_LEN), "too-many");
+
+        for index in [3, 4, 5] {
+            damage(&mut file, &cipher, index);
+        }
+        for index in [3, 4, 5] {
+            assert!(matches!(
+                repair(&file, &cipher, params, index),
+                Err(EncryptionError::CorruptedChunk { index: i }) if i == index
+            ));
+        }
+    }
+
+    #[test]
+    fn damaged_parity_fails_cleanly() {
+        let cipher = cipher();
+        let params = FecParams::new(3, 2).unwrap();
+        let mut file = protected(&cipher, params, &content(PLAINTEXT_LEN), "parity");
+        damage(&mut file, &cipher, 1);
+
+        // Both parity shards of the first group
+        let parity_start = data_section_len(PLAINTEXT_LEN as u64, cipher.scheme()) as usize;
+        let chunk_size = cipher.scheme().encrypted_chunk_size();
+        file[parity_start + 10] ^= 0xff;
+        file[parity_start + chunk_size + 10] ^= 0xff;
+        assert!(matches!(repair(&file, &cipher, params, 1), Err(EncryptionError::CorruptedChunk { index: 1 })));
+    }
+
+    #[test]
+    fn rejects_groups_above_the_memory_cap() {
+        assert!(FecParams::new(10, 2).is_ok());
+        assert!(FecParams::new(60, 4).is_ok());
+        assert!(FecParams::new(60, 5).is_err());
+        assert!(FecParams::new(255, 1).is_err());
+        assert!(FecParams::new(0, 2).is_err());
+        assert!(FecParams::new(2, 0).is_err());
+        assert!(FecParams::from_extension(&[200, 50]).is_err());
+    }
+}
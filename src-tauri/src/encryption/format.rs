@@ -0,0 +1,656 @@
+use orion::hazardous::{
+    aead::xchacha20poly1305::{open, seal, Nonce, SecretKey},
+    mac::poly1305::POLY1305_OUTSIZE,
+};
+use std::io::{Read, Write};
+
+use super::{
+    helpers::{
+        create_key, derive_file_key, open_chunk, seal_chunk, EncryptionError, CHUNK_OVERHEAD, CHUNK_SIZE, NONCE_SIZE,
+    },
+    metadata::FileMetadata,
+    volume::is_volume,
+};
+
+/// Marks files written with a header; legacy files start directly with the nonce
+pub const MAGIC: &[u8; 4] = b"CPHR";
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Upper bound for the sealed metadata block, guards against allocating garbage lengths
+const MAX_METADATA_SIZE: usize = 1024 * 1024;
+
+/// Header extension holding the error correction ratio, see [`super::fec::FecParams`]
+pub const EXT_ERROR_CORRECTION: u8 = 1;
+/// Header extension holding the id of a password remembered in the OS keyring
+pub const EXT_KEY_ID: u8 = 2;
+/// Header extension holding the Ed25519 public key of the signer, see [`super::signature`]
+pub const EXT_SIGNER: u8 = 3;
+/// Header extension marking files that end with the sealed plaintext hash, see [`super::hash`]
+pub const EXT_CONTENT_HASH: u8 = 4;
+/// Header extension holding the salt of the root key the file key is derived from, see [`FileHeader::cipher`]
+pub const EXT_KEY_SALT: u8 = 5;
+
+/// Nonce counter reserved for the metadata block; chunk `i` uses counter `i + 1`
+const METADATA_COUNTER: u64 = 0;
+
+/// Nonce counters of volume tags start here, far above any chunk counter
+const VOLUME_COUNTER_BASE: u64 = 1 << 63;
+
+/// Nonce counter of the sealed signature, below the volume tags and above any chunk counter
+const SIGNATURE_COUNTER: u64 = VOLUME_COUNTER_BASE - 1;
+/// Nonce counter of the sealed content hash
+const CONTENT_HASH_COUNTER: u64 = VOLUME_COUNTER_BASE - 2;
+
+/// How chunks are sealed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkScheme {
+    /// Headerless files: 32 random bytes of associated data per chunk, one nonce for all chunks
+    Legacy,
+    /// Header files: a distinct nonce per chunk derived from the file nonce and the chunk index
+    V2,
+}
+
+impl ChunkScheme {
+    /// Bytes added to every chunk on disk
+    pub fn overhead(self) -> usize {
+        match self {
+            ChunkScheme::Legacy => CHUNK_OVERHEAD,
+            ChunkScheme::V2 => POLY1305_OUTSIZE,
+        }
+    }
+
+    /// On-disk size of a full encrypted chunk
+    pub fn encrypted_chunk_size(self) -> usize {
+        CHUNK_SIZE + self.overhead()
+    }
+}
+
+/// Key, nonce and scheme needed to seal or open the chunks of one file
+pub struct ChunkCipher {
+    key: SecretKey,
+    nonce: [u8; NONCE_SIZE],
+    scheme: ChunkScheme,
+}
+
+impl ChunkCipher {
+    pub fn new(key: SecretKey, nonce: [u8; NONCE_SIZE], scheme: ChunkScheme) -> Self {
+        Self { key, nonce, scheme }
+    }
+
+    pub fn scheme(&self) -> ChunkScheme {
+        self.scheme
+    }
+
+    /// Derives the nonce for `counter` by XOR-ing it into the last 8 bytes of the file nonce
+    fn nonce_for(&self, counter: u64) -> Result<Nonce, EncryptionError> {
+        let mut nonce = self.nonce;
+        for (byte, counter_byte) in nonce[NONCE_SIZE - 8..].iter_mut().zip(counter.to_le_bytes()) {
+            *byte ^= counter_byte;
+        }
+        Nonce::from_slice(&nonce).map_err(|_| EncryptionError::NonceCreation)
+    }
+
+    fn seal_with(&self, counter: u64, plaintext: &[u8], ad: Option<&[u8]>) -> Result<Vec<u8>, EncryptionError> {
+        let mut output = vec![0u8; plaintext.len() + POLY1305_OUTSIZE];
+        seal(&self.key, &self.nonce_for(counter)?, plaintext, ad, &mut output)
+            .map_err(|_| EncryptionError::Encryption)?;
+        Ok(output)
+    }
+
+    fn open_with(&self, counter: u64, sealed: &[u8], ad: Option<&[u8]>) -> Result<Vec<u8>, EncryptionError> {
+        if sealed.len() < POLY1305_OUTSIZE {
+            return Err(EncryptionError::Decryption);
+        }
+        let mut output = vec![0u8; sealed.len() - POLY1305_OUTSIZE];
+        open(&self.key, &self.nonce_for(counter)?, sealed, ad, &mut output)
+            .map_err(|_| EncryptionError::Decryption)?;
+        Ok(output)
+    }
+
+    /// Encrypts chunk `index`
+    pub fn seal_chunk(&self, index: u64, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        match self.scheme {
+            ChunkScheme::Legacy => seal_chunk(&self.key, &self.nonce_for(0)?, chunk),
+            ChunkScheme::V2 => self.seal_with(index + 1, chunk, None),
+        }
+    }
+
+    /// Creates the tag authenticating the `digest` of volume `index`
+    pub fn seal_volume_tag(&self, index: u32, digest: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.seal_with(VOLUME_COUNTER_BASE + index as u64, &[], Some(digest))
+    }
+
+    /// Checks a tag created by [`ChunkCipher::seal_volume_tag`]
+    pub fn verify_volume_tag(&self, index: u32, digest: &[u8], tag: &[u8]) -> Result<(), EncryptionError> {
+        self.open_with(VOLUME_COUNTER_BASE + index as u64, tag, Some(digest)).map(|_| ())
+    }
+
+    /// Encrypts the signature appended to signed files
+    pub fn seal_signature(&self, signature: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.seal_with(SIGNATURE_COUNTER, signature, None)
+    }
+
+    /// Decrypts a signature sealed by [`ChunkCipher::seal_signature`]
+    pub fn open_signature(&self, sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.open_with(SIGNATURE_COUNTER, sealed, None)
+            .map_err(|_| EncryptionError::InvalidSignature)
+    }
+
+    /// Encrypts the plaintext hash appended to files encrypted with `record_hash`
+    pub fn seal_content_hash(&self, hash: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.seal_with(CONTENT_HASH_COUNTER, hash, None)
+    }
+
+    /// Decrypts a hash sealed by [`ChunkCipher::seal_content_hash`]
+    pub fn open_content_hash(&self, sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        self.open_with(CONTENT_HASH_COUNTER, sealed, None)
+            .map_err(|_| EncryptionError::HashMismatch)
+    }
+
+    /// Decrypts chunk `index`
+    ///
+    /// Chunks too short to hold any plaintext are reported as truncation and
+    /// failed authentication as corruption. Legacy files have no key check, so
+    /// a failure on their first chunk most likely means a wrong password.
+    pub fn open_chunk(&self, index: u64, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if chunk.len() <= self.scheme.overhead() {
+            return Err(EncryptionError::TruncatedFile);
+        }
+        let opened = match self.scheme {
+            ChunkScheme::Legacy => open_chunk(&self.key, &self.nonce_for(0)?, chunk),
+            ChunkScheme::V2 => self.open_with(index + 1, chunk, None),
+        };
+        opened.map_err(|e| match e {
+            EncryptionError::Decryption if self.scheme == ChunkScheme::Legacy && index == 0 => {
+                EncryptionError::WrongPassword
+            }
+            EncryptionError::Decryption => EncryptionError::CorruptedChunk { index },
+            e => e,
+        })
+    }
+}
+
+/// Parsed file header
+///
+/// Layout of the current format:
+/// ```text
+/// magic "CPHR" | version u8 | nonce (24) | extensions length u16 LE | extensions
+/// | metadata length u32 LE | sealed metadata | chunks...
+/// ```
+/// Each extension is `tag u8 | length u16 LE | value`. The bytes from the magic up
+/// to the end of the extensions are the associated data of the metadata block, so
+/// any change to the header fails authentication.
+///
+/// Legacy files have no header: they start with the nonce followed by the chunks.
+#[derive(Debug, Clone)]
+pub struct FileHeader {
+    pub scheme: ChunkScheme,
+    pub nonce: [u8; NONCE_SIZE],
+    pub extensions: Vec<(u8, Vec<u8>)>,
+    sealed_metadata: Vec<u8>,
+}
+
+impl FileHeader {
+    /// Creates a header for a new file in the current format
+    pub fn new(nonce: [u8; NONCE_SIZE]) -> Self {
+        Self {
+            scheme: ChunkScheme::V2,
+            nonce,
+            extensions: Vec::new(),
+            sealed_metadata: Vec::new(),
+        }
+    }
+
+    /// Returns the value of extension `tag`
+    pub fn extension(&self, tag: u8) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Sets extension `tag`, replacing any previous value
+    pub fn set_extension(&mut self, tag: u8, value: Vec<u8>) {
+        self.extensions.retain(|(t, _)| *t != tag);
+        self.extensions.push((tag, value));
+    }
+
+    /// Derives the key from `password`
+    ///
+    /// Files with a key salt derive a root key from the password and that salt,
+    /// then their own key from the root key and the nonce. Files without one
+    /// derive their key from the password and the nonce directly.
+    pub fn cipher(&self, password: &str) -> Result<ChunkCipher, EncryptionError> {
+        let key = create_key(password, self.key_salt().to_vec())?;
+        self.cipher_with_password_key(&key)
+    }
+
+    /// Salt of the password KDF: the key salt extension, or the nonce for files without one
+    pub fn key_salt(&self) -> &[u8] {
+        self.extension(EXT_KEY_SALT).unwrap_or(&self.nonce)
+    }
+
+    /// Builds the cipher from the key derived from the password and [`FileHeader::key_salt`]
+    pub fn cipher_with_password_key(&self, key: &SecretKey) -> Result<ChunkCipher, EncryptionError> {
+        let key = match self.extension(EXT_KEY_SALT) {
+            Some(_) => derive_file_key(key, &self.nonce)?,
+            None => SecretKey::from_slice(key.unprotected_as_bytes()).map_err(|_| EncryptionError::SecretKeyCreation)?,
+        };
+        Ok(self.cipher_with_key(key))
+    }
+
+    /// Uses a key derived earlier from the header nonce
+    pub fn cipher_with_key(&self, key: SecretKey) -> ChunkCipher {
+        ChunkCipher::new(key, self.nonce, self.scheme)
+    }
+
+    /// Serializes everything the metadata block authenticates
+    fn prefix_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.nonce);
+
+        let mut extensions = Vec::new();
+        for (tag, value) in &self.extensions {
+            extensions.push(*tag);
+            extensions.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            extensions.extend_from_slice(value);
+        }
+        bytes.extend_from_slice(&(extensions.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&extensions);
+        bytes
+    }
+
+    /// Encrypts `metadata` into the header
+    pub fn seal_metadata(&mut self, cipher: &ChunkCipher, metadata: &FileMetadata) -> Result<(), EncryptionError> {
+        let json = serde_json::to_vec(metadata)
+            .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+        self.sealed_metadata = cipher.seal_with(METADATA_COUNTER, &json, Some(&self.prefix_bytes()))?;
+        Ok(())
+    }
+
+    /// Decrypts the metadata block, which also authenticates the header
+    ///
+    /// The metadata block doubles as the key check: it is opened before any
+    /// chunk, so a failure here means a wrong password (or a damaged header)
+    /// rather than damaged data. Legacy files carry no metadata and yield the default.
+    pub fn open_metadata(&self, cipher: &ChunkCipher) -> Result<FileMetadata, EncryptionError> {
+        if self.scheme == ChunkScheme::Legacy {
+            return Ok(FileMetadata::default());
+        }
+        let json = cipher
+            .open_with(METADATA_COUNTER, &self.sealed_metadata, Some(&self.prefix_bytes()))
+            .map_err(|e| match e {
+                EncryptionError::Decryption => EncryptionError::WrongPassword,
+                e => e,
+            })?;
+        serde_json::from_slice(&json).map_err(|e| EncryptionError::InvalidFormat(e.to_string()))
+    }
+
+    /// Size of the header on disk, i.e. the offset of the first chunk
+    pub fn encoded_len(&self) -> u64 {
+        match self.scheme {
+            ChunkScheme::Legacy => NONCE_SIZE as u64,
+            ChunkScheme::V2 => (self.prefix_bytes().len() + 4 + self.sealed_metadata.len()) as u64,
+        }
+    }
+
+    /// Writes the header; the metadata must have been sealed first
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), EncryptionError> {
+        match self.scheme {
+            ChunkScheme::Legacy => writer.write_all(&self.nonce)?,
+            ChunkScheme::V2 => {
+                writer.write_all(&self.prefix_bytes())?;
+                writer.write_all(&(self.sealed_metadata.len() as u32).to_le_bytes())?;
+                writer.write_all(&self.sealed_metadata)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the header, leaving `reader` positioned at the first chunk
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, EncryptionError> {
+        let too_short = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => EncryptionError::TruncatedFile,
+            _ => EncryptionError::Io(e),
+        };
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        reader.read_exact(&mut nonce[..MAGIC.len()]).map_err(too_short)?;
+
+        if is_volume(&nonce[..MAGIC.len()]) {
+            return Err(EncryptionError::UnsupportedFormat("volume of a split file".to_string()));
+        }
+        if &nonce[..MAGIC.len()] != MAGIC {
+            reader.read_exact(&mut nonce[MAGIC.len()..]).map_err(too_short)?;
+            return Ok(Self {
+                scheme: ChunkScheme::Legacy,
+                nonce,
+                extensions: Vec::new(),
+                sealed_metadata: Vec::new(),
+            });
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version).map_err(too_short)?;
+        if version[0] != FORMAT_VERSION {
+            return Err(EncryptionError::UnsupportedFormat(format!("format version {}", version[0])));
+        }
+
+        reader.read_exact(&mut nonce).map_err(too_short)?;
+
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).map_err(too_short)?;
+        let mut raw_extensions = vec![0u8; u16::from_le_bytes(length) as usize];
+        reader.read_exact(&mut raw_extensions).map_err(too_short)?;
+        let extensions = parse_extensions(&raw_extensions)?;
+
+        let mut length = [0u8; 4];
+        reader.read_exact(&mut length).map_err(too_short)?;
+        let metadata_len = u32::from_le_bytes(length) as usize;
+        if metadata_len > MAX_METADATA_SIZE {
+            return Err(EncryptionError::InvalidFormat("Metadata block is too large".to_string()));
+        }
+        let mut sealed_metadata = vec![0u8; metadata_len];
+        reader.read_exact(&mut sealed_metadata).map_err(too_short)?;
+
+        Ok(Self {
+            scheme: ChunkScheme::V2,
+            nonce,
+            extensions,
+            sealed_metadata,
+        })
+    }
+}
+
+/// Splits the raw extension area into `(tag, value)` pairs
+fn parse_extensions(mut raw: &[u8]) -> Result<Vec<(u8, Vec<u8>)>, EncryptionError> {
+    let malformed = || EncryptionError::InvalidFormat("Malformed header extension".to_string());
+    let mut extensions = Vec::new();
+    while !raw.is_empty() {
+        if raw.len() < 3 {
+            return Err(malformed());
+        }
+        let tag = raw[0];
+        let len = u16::from_le_bytes([raw[1], raw[2]]) as usize;
+        let value = raw.get(3..3 + len).ok_or_else(malformed)?;
+        extensions.push((tag, value.to_vec()));
+        raw = &raw[3 + len..];
+    }
+    Ok(extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            hash::to_hex,
+            progress::ProgressEmitter,
+            range::{unwrap_io_error, DecryptReader},
+            test_util::{test_dir, PASSWORD},
+            xchacha20::{decrypt_cipher_file, encrypt_cipher_file},
+        },
+        session::SessionState,
+        types::{AppResponse, DecryptOptions, EncryptOptions, ResponseTextCode},
+    };
+    use proptest::prelude::*;
+    use std::{
+        fs,
+        io::Cursor,
+        path::{Path, PathBuf},
+    };
+
+    const NONCE: [u8; NONCE_SIZE] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
+    ];
+
+    /// Fixed key for the known answers
+    fn key() -> SecretKey {
+        SecretKey::from_slice(&(0..32).collect::<Vec<u8>>()).unwrap()
+    }
+
+    /// Deterministic content that differs from chunk to chunk
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i.wrapping_mul(31) ^ (i >> 11)) as u8).collect()
+    }
+
+    /// An encrypted file and its key, derived once so repeated reads skip the KDF
+    struct Encrypted {
+        bytes: Vec<u8>,
+        key: Vec<u8>,
+    }
+
+    /// Encrypts `plaintext` with `encrypt_file`, or lays out a legacy file by hand, as nothing writes those any more
+    fn encrypt(dir: &Path, plaintext: &[u8], scheme: ChunkScheme) -> Encrypted {
+        encrypt_with(dir, plaintext, scheme, None)
+    }
+
+    fn encrypt_with(dir: &Path, plaintext: &[u8], scheme: ChunkScheme, key_id: Option<Vec<u8>>) -> Encrypted {
+        match scheme {
+            ChunkScheme::Legacy => {
+                let key = create_key(PASSWORD, NONCE.to_vec()).unwrap();
+                let key_bytes = key.unprotected_as_bytes().to_vec();
+                let cipher = ChunkCipher::new(key, NONCE, scheme);
+                let mut bytes = NONCE.to_vec();
+                for (index, chunk) in plaintext.chunks(CHUNK_SIZE).enumerate() {
+                    bytes.extend(cipher.seal_chunk(index as u64, chunk).unwrap());
+                }
+                Encrypted { bytes, key: key_bytes }
+            }
+            ChunkScheme::V2 => {
+                let source = dir.join("plain.bin");
+                fs::write(&source, plaintext).unwrap();
+                let response = encrypt_cipher_file(
+                    &mut ProgressEmitter::silent(),
+                    &SessionState::default(),
+                    &source.display().to_string(),
+                    PASSWORD,
+                    key_id,
+                    true,
+                    &EncryptOptions::default(),
+                )
+                .unwrap();
+                let path = PathBuf::from(response.file_path.unwrap());
+                let bytes = fs::read(&path).unwrap();
+                fs::remove_file(&path).unwrap();
+                let header = FileHeader::read(&mut bytes.as_slice()).unwrap();
+                let root_key = create_key(PASSWORD, header.key_salt().to_vec()).unwrap();
+                let key = derive_file_key(&root_key, &header.nonce).unwrap();
+                Encrypted { bytes, key: key.unprotected_as_bytes().to_vec() }
+            }
+        }
+    }
+
+    /// Decrypts through `DecryptReader`, the reader behind previews, hashing and upgrades
+    fn read(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut reader = DecryptReader::with_cipher(Cursor::new(bytes), |header| {
+            SecretKey::from_slice(key)
+                .map(|key| header.cipher_with_key(key))
+                .map_err(|_| EncryptionError::SecretKeyCreation)
+        })?;
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).map_err(unwrap_io_error)?;
+        Ok(plaintext)
+    }
+
+    /// Decrypts through `decrypt_file`'s chunk loop
+    fn decrypt_file(dir: &Path, bytes: &[u8]) -> Result<Vec<u8>, AppResponse> {
+        let path = dir.join("file.enc");
+        fs::write(&path, bytes).unwrap();
+        let response = decrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &path.display().to_string(),
+            PASSWORD,
+            false,
+            &DecryptOptions::default(),
+        )?;
+        let output = PathBuf::from(response.file_path.unwrap());
+        let plaintext = fs::read(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+        Ok(plaintext)
+    }
+
+    /// Offset of the first chunk
+    fn data_start(encrypted: &[u8]) -> usize {
+        FileHeader::read(&mut &encrypted[..]).unwrap().encoded_len() as usize
+    }
+
+    #[test]
+    fn seals_chunks_known_answer() {
+        let cipher = ChunkCipher::new(key(), NONCE, ChunkScheme::V2);
+        // Chunk `i` uses the file nonce with `i + 1` XOR-ed into its last 8 bytes
+        assert_eq!(
+            to_hex(&cipher.seal_chunk(0, b"chunk zero").unwrap()),
+            "a561200b736e05a6b34482127165d17cf6f2f7d5d72bb12d008d"
+        );
+        assert_eq!(
+            to_hex(&cipher.seal_chunk(1, b"chunk one").unwrap()),
+            "5fdaf46d6edb0b3a282a0d228d41629ef09ea28a18d25edd7a"
+        );
+    }
+
+    #[test]
+    fn round_trips_at_chunk_boundaries() {
+        let dir = test_dir("boundaries");
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE] {
+            let plaintext = content(len);
+            for scheme in [ChunkScheme::Legacy, ChunkScheme::V2] {
+                let encrypted = encrypt(&dir, &plaintext, scheme);
+                let bytes = &encrypted.bytes;
+                let chunks = len.div_ceil(CHUNK_SIZE);
+                assert_eq!(bytes.len(), data_start(bytes) + len + chunks * scheme.overhead(), "{len} {scheme:?}");
+                assert_eq!(read(bytes, &encrypted.key).unwrap(), plaintext, "{len} {scheme:?}");
+                assert_eq!(decrypt_file(&dir, bytes).unwrap(), plaintext, "{len} {scheme:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn detects_tampering_in_every_chunk() {
+        let dir = test_dir("tampering");
+        let plaintext = content(2 * CHUNK_SIZE + 100);
+        for scheme in [ChunkScheme::Legacy, ChunkScheme::V2] {
+            let encrypted = encrypt(&dir, &plaintext, scheme);
+            let start = data_start(&encrypted.bytes);
+            let chunk_size = scheme.encrypted_chunk_size();
+            for index in 0..3u64 {
+                let chunk_start = start + index as usize * chunk_size;
+                let chunk_end = (chunk_start + chunk_size).min(encrypted.bytes.len());
+                for position in [chunk_start, (chunk_start + chunk_end) / 2, chunk_end - 1] {
+                    let mut tampered = encrypted.bytes.clone();
+                    tampered[position] ^= 0x01;
+                    let result = read(&tampered, &encrypted.key);
+                    // Legacy files have no key check, a bad first chunk looks like a wrong password
+                    match (scheme, index) {
+                        (ChunkScheme::Legacy, 0) => assert!(matches!(result, Err(EncryptionError::WrongPassword))),
+                        _ => assert!(
+                            matches!(result, Err(EncryptionError::CorruptedChunk { index: i }) if i == index),
+                            "{scheme:?} chunk {index} at {position}: {result:?}"
+                        ),
+                    }
+                }
+
+                let mut tampered = encrypted.bytes.clone();
+                tampered[chunk_start + 10] ^= 0x01;
+                let response = decrypt_file(&dir, &tampered).unwrap_err();
+                match (scheme, index) {
+                    (ChunkScheme::Legacy, 0) => assert!(matches!(response.text_code, ResponseTextCode::WrongPassword)),
+                    _ => assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk), "{scheme:?} chunk {index}"),
+                }
+                assert!(!dir.join("plain.bin").exists(), "{scheme:?} chunk {index}");
+            }
+        }
+    }
+
+    #[test]
+    fn detects_reordered_chunks() {
+        let dir = test_dir("reordered");
+        let encrypted = encrypt(&dir, &content(2 * CHUNK_SIZE), ChunkScheme::V2);
+        let bytes = &encrypted.bytes;
+        let start = data_start(bytes);
+        let chunk_size = ChunkScheme::V2.encrypted_chunk_size();
+        let mut reordered = bytes[..start].to_vec();
+        reordered.extend_from_slice(&bytes[start + chunk_size..]);
+        reordered.extend_from_slice(&bytes[start..start + chunk_size]);
+
+        assert!(matches!(read(&reordered, &encrypted.key), Err(EncryptionError::CorruptedChunk { index: 0 })));
+        let response = decrypt_file(&dir, &reordered).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk));
+    }
+
+    #[test]
+    fn detects_truncated_chunks() {
+        let dir = test_dir("truncated");
+        let encrypted = encrypt(&dir, &content(CHUNK_SIZE + 100), ChunkScheme::V2);
+        let bytes = &encrypted.bytes;
+
+        // Only the tag of the last chunk is left
+        let short = &bytes[..bytes.len() - 100];
+        assert!(matches!(read(short, &encrypted.key), Err(EncryptionError::TruncatedFile)));
+        assert!(matches!(decrypt_file(&dir, short).unwrap_err().text_code, ResponseTextCode::TruncatedFile));
+
+        // The reader checks the size up front, decryption compares the cut chunk with the stored size
+        let cut = &bytes[..bytes.len() - 1];
+        assert!(matches!(read(cut, &encrypted.key), Err(EncryptionError::TruncatedFile)));
+        assert!(matches!(decrypt_file(&dir, cut).unwrap_err().text_code, ResponseTextCode::TruncatedFile));
+
+        // A damaged chunk of the right length is still reported as corrupted
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(decrypt_file(&dir, &flipped).unwrap_err().text_code, ResponseTextCode::CorruptedChunk));
+
+        // A missing last chunk only shows in the size stored in the metadata
+        let without_last = &bytes[..data_start(bytes) + ChunkScheme::V2.encrypted_chunk_size()];
+        assert!(matches!(read(without_last, &encrypted.key), Err(EncryptionError::TruncatedFile)));
+        assert!(matches!(decrypt_file(&dir, without_last).unwrap_err().text_code, ResponseTextCode::TruncatedFile));
+        assert!(!dir.join("plain.bin").exists());
+    }
+
+    #[test]
+    fn detects_tampering_in_the_header() {
+        let dir = test_dir("header");
+        let encrypted = encrypt_with(&dir, b"header test", ChunkScheme::V2, Some(vec![7; 16]));
+        let header = FileHeader::read(&mut encrypted.bytes.as_slice()).unwrap();
+        assert_eq!(header.extension(EXT_KEY_ID), Some(&[7u8; 16][..]));
+
+        assert_eq!(read(&encrypted.bytes, &encrypted.key).unwrap(), b"header test");
+        for position in 0..data_start(&encrypted.bytes) {
+            let mut tampered = encrypted.bytes.clone();
+            tampered[position] ^= 0x01;
+            assert!(read(&tampered, &encrypted.key).is_err(), "header byte {position}");
+        }
+    }
+
+    #[test]
+    fn wrong_password_fails_the_metadata_check() {
+        let dir = test_dir("wrong");
+        let encrypted = encrypt(&dir, b"secret", ChunkScheme::V2);
+        let result = DecryptReader::new(Cursor::new(&encrypted.bytes), "Wrong-Password-1!");
+        assert!(matches!(result, Err(EncryptionError::WrongPassword)));
+    }
+
+    /// Sizes around the chunk boundaries, where off-by-one errors live
+    fn plaintext_len() -> impl Strategy<Value = usize> {
+        prop_oneof![
+            0..64usize,
+            (CHUNK_SIZE - 64)..(CHUNK_SIZE + 64),
+            (2 * CHUNK_SIZE - 64)..(2 * CHUNK_SIZE + 64),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn round_trips_arbitrary_content(len in plaintext_len(), seed in any::<u8>(), legacy in any::<bool>()) {
+            let dir = test_dir("arbitrary");
+            let plaintext: Vec<u8> = content(len).into_iter().map(|byte| byte ^ seed).collect();
+            let scheme = if legacy { ChunkScheme::Legacy } else { ChunkScheme::V2 };
+            let encrypted = encrypt(&dir, &plaintext, scheme);
+            prop_assert_eq!(read(&encrypted.bytes, &encrypted.key).unwrap(), plaintext);
+        }
+    }
+}
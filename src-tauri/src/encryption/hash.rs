@@ -0,0 +1,362 @@
+use chrono::Local;
+use orion::hazardous::{
+    hash::{blake2::blake2b::Blake2b, sha2::sha256::Sha256},
+    mac::poly1305::POLY1305_OUTSIZE,
+};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    time::Instant,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    error::AppError,
+    log::add_log_internal,
+    session::SessionState,
+    types::{AppResponse, HashAlgorithm, HashResponse, LogLevel, ProcessingStats, ResponseTextCode, Status},
+};
+
+use super::{
+    helpers::{read_chunk, validate_password, EncryptionError, CHUNK_SIZE},
+    progress::ProgressEmitter,
+    range::{unwrap_io_error, DecryptReader},
+    xchacha20::{check_random_access, create_error_response, decryption_password},
+};
+
+/// Length of the BLAKE2b content hash stored in encrypted files
+pub const CONTENT_HASH_LEN: usize = 32;
+/// Size of the sealed content hash at the end of files encrypted with `record_hash`
+///
+/// ```text
+/// header | chunks | parity (optional) | sealed content hash (32 + 16) | signature (optional)
+/// ```
+pub const CONTENT_HASH_BLOCK_LEN: u64 = (CONTENT_HASH_LEN + POLY1305_OUTSIZE) as u64;
+
+/// Streaming hash over one of the supported algorithms
+pub enum ContentHasher {
+    Blake2b(Blake2b),
+    Sha256(Sha256),
+}
+
+impl ContentHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Result<Self, EncryptionError> {
+        match algorithm {
+            HashAlgorithm::Blake2b => Blake2b::new(CONTENT_HASH_LEN)
+                .map(ContentHasher::Blake2b)
+                .map_err(|_| EncryptionError::Encryption),
+            HashAlgorithm::Sha256 => Ok(ContentHasher::Sha256(Sha256::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<(), EncryptionError> {
+        let updated = match self {
+            ContentHasher::Blake2b(hasher) => hasher.update(data),
+            ContentHasher::Sha256(hasher) => hasher.update(data),
+        };
+        updated.map_err(|_| EncryptionError::Encryption)
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, EncryptionError> {
+        let digest = match &mut self {
+            ContentHasher::Blake2b(hasher) => hasher.finalize().map(|digest| digest.as_ref().to_vec()),
+            ContentHasher::Sha256(hasher) => hasher.finalize().map(|digest| digest.as_ref().to_vec()),
+        };
+        digest.map_err(|_| EncryptionError::Encryption)
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks the hash decrypted from the file against the hash of the decrypted content
+///
+/// # Returns
+/// The verified hash as hex
+pub fn verify_content_hash(stored: &[u8], computed: ContentHasher) -> Result<String, EncryptionError> {
+    let computed = computed.finish()?;
+    orion::util::secure_cmp(stored, &computed).map_err(|_| EncryptionError::HashMismatch)?;
+    Ok(to_hex(&computed))
+}
+
+/// Hashes a file, or the decrypted content of an encrypted file if `password` is given
+///
+/// Encrypted files are decrypted in memory chunk by chunk and nothing is
+/// written to disk. The hash of a decrypted file matches the hash of the
+/// original, so this confirms a round trip without keeping a plaintext copy.
+/// An empty `password` is resolved like `decrypt_file` does: the file's
+/// remembered key, then the unlocked session's password.
+///
+/// # Events
+/// `hash_progress_<path>` with a [`ProgressInfo`] while hashing
+#[tauri::command]
+pub async fn hash_file(
+    app: AppHandle,
+    file_path: &str,
+    algorithm: Option<HashAlgorithm>,
+    password: Option<String>,
+) -> Result<HashResponse, AppResponse> {
+    let password = password
+        .map(|password| decryption_password(&app, Path::new(file_path), &password))
+        .transpose()?;
+    let mut progress = ProgressEmitter::new(&app, "hash", file_path, 0);
+    let session = app.state::<SessionState>();
+    hash_path(&mut progress, &session, file_path, algorithm.unwrap_or_default(), password.as_deref())
+}
+
+/// Hashes `file_path` with `algorithm`, decrypting it first if `password` is given
+///
+/// # Arguments
+/// * `session` - Supplies cached keys if `password` is the session password
+fn hash_path(
+    progress: &mut ProgressEmitter,
+    session: &SessionState,
+    file_path: &str,
+    algorithm: HashAlgorithm,
+    password: Option<&str>,
+) -> Result<HashResponse, AppResponse> {
+    let start_time = Instant::now();
+
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
+    })?;
+
+    let (mut reader, total_bytes): (Box<dyn Read>, usize) = match password {
+        Some(password) => {
+            validate_password(password).map_err(|_| {
+                create_error_response(ResponseTextCode::InvalidPassword, None)
+            })?;
+            let reader = check_random_access(Path::new(file_path))
+                .and_then(|_| DecryptReader::with_cipher(source_file, |header| session.cipher(header, password)))
+                .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+            let total_bytes = reader.plaintext_len() as usize;
+            (Box::new(reader), total_bytes)
+        }
+        None => {
+            let total_bytes = source_file.metadata().map(|m| m.len()).unwrap_or(0) as usize;
+            (Box::new(BufReader::new(source_file)), total_bytes)
+        }
+    };
+
+    let mut hasher = ContentHasher::new(algorithm)
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    progress.set_total(total_bytes);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut bytes_processed = 0;
+    loop {
+        let n = read_chunk(&mut reader, &mut buffer).map_err(|e| {
+            AppError::from(unwrap_io_error(e)).into_response(Some(file_path.to_string()))
+        })?;
+        if n == 0 { break; }
+        hasher.update(&buffer[..n])
+            .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+        bytes_processed += n;
+
+        progress.update(bytes_processed);
+    }
+
+    let hash = hasher.finish()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    let total_time = start_time.elapsed().as_secs_f64();
+
+    add_log_internal(LogLevel::Info, ResponseTextCode::HashComputed, Some(file_path.to_string())).ok();
+    Ok(HashResponse {
+        status: Status::Success,
+        text_code: ResponseTextCode::HashComputed,
+        algorithm,
+        hash: to_hex(&hash),
+        file_path: file_path.to_string(),
+        stats: ProcessingStats {
+            total_size_bytes: bytes_processed,
+            processing_time_seconds: total_time,
+            average_speed_mbps: bytes_processed as f64 / (1024.0 * 1024.0) / total_time,
+            content_hash: None,
+        },
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            format::FileHeader,
+            test_util::{test_dir, PASSWORD},
+            xchacha20::{decrypt_cipher_file, encrypt_cipher_file},
+        },
+        session::SessionState,
+        types::{DecryptOptions, EncryptOptions},
+    };
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    /// BLAKE2b-256 and SHA-256 of `large_content()`
+    const LARGE_BLAKE2B: &str = "74f0f017d0e1b62fccd8fa4bcafa62aa8df9f74209f6912ceb5252df5b3aed5a";
+    const LARGE_SHA256: &str = "d93ce1e5e4bc2a2f83d885cb36fd0bc08dc33e4e5e68458e0a8a908931a56530";
+
+    /// Spans two chunks, so the streamed hash is checked across a chunk boundary
+    fn large_content() -> Vec<u8> {
+        (0..CHUNK_SIZE + CHUNK_SIZE / 2).map(|i| (i.wrapping_mul(31) ^ (i >> 11)) as u8).collect()
+    }
+
+    fn hash(path: &Path, algorithm: HashAlgorithm, password: Option<&str>) -> String {
+        hash_path(&mut ProgressEmitter::silent(), &SessionState::default(), &path.display().to_string(), algorithm, password)
+            .unwrap()
+            .hash
+    }
+
+    /// Encrypts `large_content()` with the content hash recorded and removes the original
+    fn encrypted_with_hash(dir: &Path) -> PathBuf {
+        let source = dir.join("hashed.bin");
+        fs::write(&source, large_content()).unwrap();
+        let options = EncryptOptions { record_hash: true, ..EncryptOptions::default() };
+        let response = encrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &source.display().to_string(),
+            PASSWORD,
+            None,
+            true,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(response.stats.unwrap().content_hash.as_deref(), Some(LARGE_BLAKE2B));
+        PathBuf::from(response.file_path.unwrap())
+    }
+
+    fn decrypt(path: &Path) -> Result<AppResponse, AppResponse> {
+        decrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &path.display().to_string(),
+            PASSWORD,
+            false,
+            &DecryptOptions::default(),
+        )
+    }
+
+    #[test]
+    fn matches_known_answers() {
+        let dir = test_dir("known");
+        let empty = dir.join("empty");
+        fs::write(&empty, b"").unwrap();
+        let abc = dir.join("abc");
+        fs::write(&abc, b"abc").unwrap();
+        let large = dir.join("large");
+        fs::write(&large, large_content()).unwrap();
+
+        assert_eq!(hash(&empty, HashAlgorithm::Blake2b, None), "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8");
+        assert_eq!(hash(&abc, HashAlgorithm::Blake2b, None), "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319");
+        assert_eq!(hash(&abc, HashAlgorithm::Sha256, None), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hash(&large, HashAlgorithm::Blake2b, None), LARGE_BLAKE2B);
+        assert_eq!(hash(&large, HashAlgorithm::Sha256, None), LARGE_SHA256);
+    }
+
+    #[test]
+    fn hashes_the_plaintext_of_encrypted_files() {
+        let dir = test_dir("encrypted");
+        let encrypted = encrypted_with_hash(&dir);
+
+        assert_eq!(hash(&encrypted, HashAlgorithm::Blake2b, Some(PASSWORD)), LARGE_BLAKE2B);
+        assert_eq!(hash(&encrypted, HashAlgorithm::Sha256, Some(PASSWORD)), LARGE_SHA256);
+        assert_ne!(hash(&encrypted, HashAlgorithm::Blake2b, None), LARGE_BLAKE2B);
+    }
+
+    #[test]
+    fn rejects_formats_that_are_only_decrypted_as_a_stream() {
+        let dir = test_dir("streams");
+        let source = dir.join("armored.bin");
+        fs::write(&source, large_content()).unwrap();
+        let options = EncryptOptions { armored: true, ..EncryptOptions::default() };
+        let response = encrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &source.display().to_string(),
+            PASSWORD,
+            None,
+            true,
+            &options,
+        )
+        .unwrap();
+        let armored = PathBuf::from(response.file_path.unwrap());
+        let age = dir.join("file.age");
+        fs::write(&age, b"age-encryption.org/v1\n-> scrypt c2FsdA 18\n").unwrap();
+        let openpgp = dir.join("file.gpg");
+        fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/openpgp/aes256.gpg"), &openpgp).unwrap();
+
+        for path in [armored, age, openpgp] {
+            let response = hash_path(
+                &mut ProgressEmitter::silent(),
+                &SessionState::default(),
+                &path.display().to_string(),
+                HashAlgorithm::Blake2b,
+                Some(PASSWORD),
+            )
+            .unwrap_err();
+            assert!(matches!(response.text_code, ResponseTextCode::UnsupportedFormat), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn verifies_the_recorded_hash_on_decryption() {
+        let dir = test_dir("verify");
+        let encrypted = encrypted_with_hash(&dir);
+
+        let response = decrypt(&encrypted).unwrap();
+        assert_eq!(response.stats.unwrap().content_hash.as_deref(), Some(LARGE_BLAKE2B));
+        assert_eq!(fs::read(response.file_path.unwrap()).unwrap(), large_content());
+    }
+
+    #[test]
+    fn rejects_a_recorded_hash_that_does_not_match() {
+        let dir = test_dir("mismatch");
+        let encrypted = encrypted_with_hash(&dir);
+
+        // A validly sealed hash of other content, so only the comparison can catch it
+        let mut bytes = fs::read(&encrypted).unwrap();
+        let cipher = FileHeader::read(&mut bytes.as_slice()).unwrap().cipher(PASSWORD).unwrap();
+        let trailer = bytes.len() - CONTENT_HASH_BLOCK_LEN as usize;
+        bytes.truncate(trailer);
+        bytes.extend(cipher.seal_content_hash(&[0u8; CONTENT_HASH_LEN]).unwrap());
+        fs::write(&encrypted, bytes).unwrap();
+
+        let response = decrypt(&encrypted).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::HashMismatch));
+        assert!(!dir.join("hashed.bin").exists());
+    }
+
+    #[test]
+    fn rejects_a_damaged_hash_block() {
+        let dir = test_dir("damaged");
+        let encrypted = encrypted_with_hash(&dir);
+        let mut bytes = fs::read(&encrypted).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        fs::write(&encrypted, bytes).unwrap();
+
+        let response = decrypt(&encrypted).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::HashMismatch));
+        assert!(!dir.join("hashed.bin").exists());
+    }
+
+    #[test]
+    fn compares_hashes_in_full() {
+        let mut hasher = ContentHasher::new(HashAlgorithm::Blake2b).unwrap();
+        hasher.update(b"abc").unwrap();
+        let mut stored = hasher.finish().unwrap();
+
+        let mut hasher = ContentHasher::new(HashAlgorithm::Blake2b).unwrap();
+        hasher.update(b"abc").unwrap();
+        assert_eq!(verify_content_hash(&stored, hasher).unwrap(), "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319");
+
+        stored[CONTENT_HASH_LEN - 1] ^= 0x01;
+        let mut hasher = ContentHasher::new(HashAlgorithm::Blake2b).unwrap();
+        hasher.update(b"abc").unwrap();
+        assert!(matches!(verify_content_hash(&stored, hasher), Err(EncryptionError::HashMismatch)));
+    }
+}
@@ -1,10 +1,34 @@
-use orion::hazardous::aead::xchacha20poly1305::SecretKey;
+use orion::hazardous::aead::xchacha20poly1305::{open, seal, Nonce, SecretKey};
 use orion::{
-    hazardous::stream::chacha20::CHACHA_KEYSIZE,
+    hazardous::{kdf::hkdf, mac::poly1305::POLY1305_OUTSIZE, stream::chacha20::CHACHA_KEYSIZE},
     kdf::{derive_key, Password, Salt},
 };
 use rand_core::{OsRng, RngCore};
+use std::{
+    fs::{remove_file, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+};
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Size of the nonce stored at the start of every encrypted file
+pub const NONCE_SIZE: usize = 24;
+
+/// Size of the salt a root key is derived with, see [`derive_file_key`]
+pub const KEY_SALT_SIZE: usize = 16;
+
+/// HKDF context of file keys, so a root key never derives keys for another purpose
+const FILE_KEY_INFO: &[u8] = b"cipher file key";
+
+/// Plaintext bytes per chunk (1MB chunks for better progress tracking)
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Bytes added to every legacy chunk: random associated data plus the Poly1305 tag
+pub const CHUNK_OVERHEAD: usize = CHACHA_KEYSIZE + POLY1305_OUTSIZE;
+
+/// On-disk size of a full legacy encrypted chunk
+pub const ENCRYPTED_CHUNK_SIZE: usize = CHUNK_SIZE + CHUNK_OVERHEAD;
 
 /// Custom error type for encryption operations
 #[derive(Debug, Error)]
@@ -19,16 +43,63 @@ pub enum EncryptionError {
     SecretKeyCreation,
     #[error("Password validation failed: {0}")]
     PasswordValidation(String),
+    #[error("Failed to create nonce")]
+    NonceCreation,
+    #[error("Failed to encrypt chunk")]
+    Encryption,
+    #[error("Failed to decrypt chunk")]
+    Decryption,
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("Chunk {index} is corrupted")]
+    CorruptedChunk { index: u64 },
+    #[error("File is truncated")]
+    TruncatedFile,
+    #[error("Volume {index} is missing")]
+    MissingVolume { index: u32 },
+    #[error("Volume {index} is corrupted or belongs to another file")]
+    CorruptedVolume { index: u32 },
+    #[error("Vault entry {0} not found")]
+    EntryNotFound(u64),
+    #[error("Unsupported file format: {0}")]
+    UnsupportedFormat(String),
+    #[error("Invalid encrypted file: {0}")]
+    InvalidFormat(String),
+    #[error("Signature verification failed")]
+    InvalidSignature,
+    #[error("Decrypted content does not match the stored hash")]
+    HashMismatch,
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
 }
 
 /// Generates a cryptographically secure nonce for encryption
 /// 
-/// Returns a 24-byte vector suitable for XChaCha20-Poly1305
+/// Returns 24 random bytes suitable for XChaCha20-Poly1305
 #[inline]
-pub fn generate_nonce() -> Vec<u8> {
-    let mut randoms = [0u8; 24];
+pub fn generate_nonce() -> [u8; NONCE_SIZE] {
+    let mut randoms = [0u8; NONCE_SIZE];
     OsRng.fill_bytes(&mut randoms);
-    randoms.to_vec()
+    randoms
+}
+
+/// Generates the salt of a new root key
+pub fn generate_key_salt() -> [u8; KEY_SALT_SIZE] {
+    let mut randoms = [0u8; KEY_SALT_SIZE];
+    OsRng.fill_bytes(&mut randoms);
+    randoms
+}
+
+/// Derives the key of one file from a root key and the file nonce with HKDF-SHA512
+///
+/// The root key comes from [`create_key`] with a key salt that many files can
+/// share. Unlike Argon2 this step is cheap, so files sharing a salt run the
+/// password KDF once between them while each still gets its own key.
+pub fn derive_file_key(root_key: &SecretKey, nonce: &[u8]) -> Result<SecretKey, EncryptionError> {
+    let mut key = Zeroizing::new([0u8; CHACHA_KEYSIZE]);
+    hkdf::sha512::derive_key(nonce, root_key.unprotected_as_bytes(), Some(FILE_KEY_INFO), key.as_mut())
+        .map_err(|_| EncryptionError::KeyDerivation)?;
+    SecretKey::from_slice(key.as_ref()).map_err(|_| EncryptionError::SecretKeyCreation)
 }
 
 /// Creates an encryption key from a password and nonce
@@ -123,6 +194,94 @@ pub fn split_encrypted(cipher_text: &[u8]) -> (Vec<u8>, Vec<u8>) {
     )
 }
 
+/// Encrypts a single chunk in the legacy layout
+/// 
+/// # Returns
+/// The random associated data followed by the ciphertext and tag
+pub fn seal_chunk(key: &SecretKey, nonce: &Nonce, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let ad = generate_auth_tag();
+    let mut output = vec![0u8; chunk.len() + CHUNK_OVERHEAD];
+    output[..CHACHA_KEYSIZE].copy_from_slice(ad.as_ref());
+    seal(key, nonce, chunk, Some(ad.as_slice()), &mut output[CHACHA_KEYSIZE..])
+        .map_err(|_| EncryptionError::Encryption)?;
+    Ok(output)
+}
+
+/// Decrypts a single chunk produced by [`seal_chunk`]
+pub fn open_chunk(key: &SecretKey, nonce: &Nonce, chunk: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    if chunk.len() < CHUNK_OVERHEAD {
+        return Err(EncryptionError::Decryption);
+    }
+    let (ad, encrypted_data) = chunk.split_at(CHACHA_KEYSIZE);
+    let mut decrypted = vec![0u8; encrypted_data.len() - POLY1305_OUTSIZE];
+    open(key, nonce, encrypted_data, Some(ad), &mut decrypted)
+        .map_err(|_| EncryptionError::Decryption)?;
+    Ok(decrypted)
+}
+
+/// Reads until `buffer` is full or the reader is exhausted
+/// 
+/// Unlike a single `read` call this never returns a short count mid-stream,
+/// so every chunk except the last one is exactly `buffer.len()` bytes
+pub fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads exactly `len` bytes, reporting a short read as a truncated file
+pub fn read_block<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>, EncryptionError> {
+    let mut block = vec![0u8; len as usize];
+    reader.read_exact(&mut block).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => EncryptionError::TruncatedFile,
+        _ => EncryptionError::Io(e),
+    })?;
+    Ok(block)
+}
+
+/// Syncs the directory containing `path`, so a rename into it survives a crash
+///
+/// Windows has no way to sync a directory; there this does nothing.
+pub fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Overwrites a file with random data, syncs it to disk and deletes it
+///
+/// This only defeats recovery from the same blocks: copy-on-write filesystems,
+/// SSD wear leveling and backups may still hold the original content.
+pub fn shred_file(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    while remaining > 0 {
+        let n = remaining.min(CHUNK_SIZE as u64) as usize;
+        get_random(&mut buffer[..n]);
+        file.write_all(&buffer[..n])?;
+        remaining -= n as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    remove_file(path)
+}
+
 /// Fills a buffer with cryptographically secure random bytes
 /// 
 /// # Arguments
@@ -131,3 +290,75 @@ pub fn split_encrypted(cipher_text: &[u8]) -> (Vec<u8>, Vec<u8>) {
 fn get_random(dest: &mut [u8]) {
     RngCore::fill_bytes(&mut OsRng, dest);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn counting_bytes(len: usize) -> Vec<u8> {
+        (0..len as u8).collect()
+    }
+
+    #[test]
+    fn create_key_known_answer() {
+        // Argon2i, 15 passes over 1 MiB, with the nonce as salt
+        let key = create_key("Golden-Test-1!", counting_bytes(NONCE_SIZE)).unwrap();
+        assert_eq!(
+            key.unprotected_as_bytes(),
+            from_hex("624a2546b19d0d65eeeacc27e64c0b422bf0bbfd53992ff2c4e90a2f29aa0d53").as_slice()
+        );
+    }
+
+    #[test]
+    fn create_key_depends_on_password_and_nonce() {
+        let key = create_key("Golden-Test-1!", counting_bytes(NONCE_SIZE)).unwrap();
+        let other_password = create_key("Golden-Test-2!", counting_bytes(NONCE_SIZE)).unwrap();
+        let other_nonce = create_key("Golden-Test-1!", vec![0u8; NONCE_SIZE]).unwrap();
+        assert_ne!(key.unprotected_as_bytes(), other_password.unprotected_as_bytes());
+        assert_ne!(key.unprotected_as_bytes(), other_nonce.unprotected_as_bytes());
+    }
+
+    #[test]
+    fn opens_legacy_chunk_known_answer() {
+        let key = SecretKey::from_slice(&counting_bytes(32)).unwrap();
+        let nonce = Nonce::from_slice(&counting_bytes(NONCE_SIZE)).unwrap();
+        let sealed = from_hex(concat!(
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "f2a7681ef3abadcd5b3148a5847ec45a48528362b3a9fd8f99bb1494",
+        ));
+        assert_eq!(open_chunk(&key, &nonce, &sealed).unwrap(), b"legacy chunk");
+    }
+
+    #[test]
+    fn legacy_chunks_get_fresh_associated_data() {
+        let key = SecretKey::from_slice(&counting_bytes(32)).unwrap();
+        let nonce = Nonce::from_slice(&counting_bytes(NONCE_SIZE)).unwrap();
+        let first = seal_chunk(&key, &nonce, b"same plaintext").unwrap();
+        let second = seal_chunk(&key, &nonce, b"same plaintext").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.len(), b"same plaintext".len() + CHUNK_OVERHEAD);
+        assert_eq!(open_chunk(&key, &nonce, &first).unwrap(), b"same plaintext");
+        assert_eq!(open_chunk(&key, &nonce, &second).unwrap(), b"same plaintext");
+    }
+
+    #[test]
+    fn legacy_chunks_reject_tampering() {
+        let key = SecretKey::from_slice(&counting_bytes(32)).unwrap();
+        let nonce = Nonce::from_slice(&counting_bytes(NONCE_SIZE)).unwrap();
+        let sealed = seal_chunk(&key, &nonce, b"legacy chunk").unwrap();
+        // Associated data, ciphertext and tag are all authenticated
+        for position in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[position] ^= 0x01;
+            assert!(matches!(open_chunk(&key, &nonce, &tampered), Err(EncryptionError::Decryption)), "{position}");
+        }
+        assert!(matches!(open_chunk(&key, &nonce, &sealed[..CHUNK_OVERHEAD - 1]), Err(EncryptionError::Decryption)));
+    }
+}
@@ -0,0 +1,268 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Original file properties stored encrypted inside the file
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Original file name without any directory components
+    pub name: Option<String>,
+    /// Original name as base64 bytes when it isn't valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<String>,
+    /// Plaintext size in bytes, used to detect truncated files
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: Option<i64>,
+    /// Nanoseconds within `modified`, absent in files written by earlier versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_nanos: Option<u32>,
+    /// Unix mode as read from the file; only the permission bits are applied
+    pub mode: Option<u32>,
+    /// Extended attributes, values base64-encoded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+}
+
+impl FileMetadata {
+    /// Collects the metadata of the file at `path`
+    /// 
+    /// Extended attributes are only read when `include_xattrs` is set, as they
+    /// may hold data the user doesn't expect to travel with the file.
+    pub fn from_path(path: &Path, include_xattrs: bool) -> Self {
+        let fs_metadata = fs::metadata(path).ok();
+        let modified = fs_metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            fs_metadata.as_ref().map(|m| m.permissions().mode())
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        let file_name = path.file_name();
+        let name = file_name.and_then(|name| name.to_str()).map(str::to_string);
+        let raw_name = match (&name, file_name) {
+            (None, Some(file_name)) => os_str_to_bytes(file_name).map(|bytes| STANDARD.encode(bytes)),
+            _ => None,
+        };
+
+        Self {
+            name,
+            raw_name,
+            size: fs_metadata.as_ref().map(|m| m.len()),
+            modified: modified.map(|duration| duration.as_secs() as i64),
+            modified_nanos: modified.map(|duration| duration.subsec_nanos()),
+            mode,
+            xattrs: if include_xattrs { read_xattrs(path) } else { BTreeMap::new() },
+        }
+    }
+
+    /// Returns the stored name if it is a plain file name that is safe to create
+    /// 
+    /// Names with directory components, `.` and `..` are rejected.
+    pub fn safe_name(&self) -> Option<OsString> {
+        let name = match (&self.name, &self.raw_name) {
+            (Some(name), _) => OsString::from(name),
+            (None, Some(raw_name)) => os_string_from_bytes(STANDARD.decode(raw_name).ok()?)?,
+            (None, None) => return None,
+        };
+        let is_plain = Path::new(&name).file_name() == Some(name.as_os_str());
+        is_plain.then_some(name)
+    }
+
+    /// Reapplies modification time, permissions and extended attributes to a decrypted file
+    /// 
+    /// Every property is attempted; the first error is returned afterwards. The
+    /// mode comes last, as a read-only mode would make the other writes fail.
+    pub fn apply(&self, file: &File, path: &Path) -> io::Result<()> {
+        let mut result = Ok(());
+
+        if let Some(modified) = self.modified.and_then(|secs| u64::try_from(secs).ok()) {
+            // Build tools compare times below a second, so the nanoseconds are kept when stored
+            let nanos = self.modified_nanos.filter(|&nanos| nanos < 1_000_000_000).unwrap_or(0);
+            result = result.and(file.set_modified(UNIX_EPOCH + Duration::new(modified, nanos)));
+        }
+
+        for (name, value) in &self.xattrs {
+            let Ok(value) = STANDARD.decode(value) else {
+                continue;
+            };
+            result = result.and(write_xattr(path, name, &value));
+        }
+
+        if let Some(mode) = self.mode {
+            result = result.and(set_mode(path, mode));
+        }
+
+        result
+    }
+}
+
+#[cfg(unix)]
+fn os_str_to_bytes(name: &OsStr) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(name.as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn os_str_to_bytes(name: &OsStr) -> Option<Vec<u8>> {
+    name.to_str().map(|name| name.as_bytes().to_vec())
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
+
+/// Read, write and execute bits for owner, group and others
+#[cfg(unix)]
+const PERMISSION_BITS: u32 = 0o777;
+
+/// Applies the permission bits of `mode`
+///
+/// Setuid, setgid and sticky bits are dropped, so a crafted file can't create
+/// privileged executables.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & PERMISSION_BITS))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    let Ok(names) = xattr::list(path) else {
+        return BTreeMap::new();
+    };
+    names
+        .filter_map(|name| {
+            let value = xattr::get(path, &name).ok().flatten()?;
+            Some((name.to_str()?.to_string(), STANDARD.encode(value)))
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_path: &Path) -> BTreeMap<String, String> {
+    BTreeMap::new()
+}
+
+#[cfg(unix)]
+fn write_xattr(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    xattr::set(path, name, value)
+}
+
+#[cfg(not(unix))]
+fn write_xattr(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::test_dir;
+    use std::time::SystemTime;
+
+    #[test]
+    fn collects_name_size_and_mtime() {
+        let dir = test_dir("metadata");
+        let path = dir.join("collect.txt");
+        fs::write(&path, b"twelve bytes").unwrap();
+        let metadata = FileMetadata::from_path(&path, false);
+
+        assert_eq!(metadata.name.as_deref(), path.file_name().and_then(OsStr::to_str));
+        assert_eq!(metadata.size, Some(12));
+        assert!(metadata.modified.is_some());
+        assert!(metadata.xattrs.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restores_xattrs_and_mtime_before_a_read_only_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("metadata");
+        let path = dir.join("read-only");
+        let file = File::create(&path).unwrap();
+        // Not every temp filesystem supports user xattrs
+        if xattr::set(&path, "user.probe", b"1").is_err() {
+            return;
+        }
+        xattr::remove(&path, "user.probe").unwrap();
+
+        let metadata = FileMetadata {
+            modified: Some(1_600_000_000),
+            mode: Some(0o100444),
+            xattrs: BTreeMap::from([("user.origin".to_string(), STANDARD.encode(b"golden"))]),
+            ..FileMetadata::default()
+        };
+        let result = metadata.apply(&file, &path);
+        let restored = fs::metadata(&path).unwrap();
+        let origin = xattr::get(&path, "user.origin").unwrap();
+
+        result.unwrap();
+        assert_eq!(restored.permissions().mode() & 0o777, 0o444);
+        assert_eq!(origin.as_deref(), Some(&b"golden"[..]));
+        assert_eq!(restored.modified().unwrap(), SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    }
+
+    #[test]
+    fn keeps_the_mtime_below_a_second() {
+        let dir = test_dir("metadata");
+        let source = dir.join("source");
+        let modified = SystemTime::UNIX_EPOCH + Duration::new(1_600_000_000, 123_456_789);
+        File::create(&source).unwrap().set_modified(modified).unwrap();
+        let metadata = FileMetadata::from_path(&source, false);
+        assert_eq!(metadata.modified, Some(1_600_000_000));
+        assert_eq!(metadata.modified_nanos, Some(123_456_789));
+
+        let restored = dir.join("restored");
+        metadata.apply(&File::create(&restored).unwrap(), &restored).unwrap();
+        assert_eq!(fs::metadata(&restored).unwrap().modified().unwrap(), modified);
+
+        // Files written before the nanoseconds were stored restore whole seconds
+        let metadata: FileMetadata = serde_json::from_str(r#"{"name":"a","modified":1600000000,"mode":null}"#).unwrap();
+        metadata.apply(&File::create(&restored).unwrap(), &restored).unwrap();
+        let restored = fs::metadata(&restored).unwrap().modified().unwrap();
+        assert_eq!(restored, SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_setuid_setgid_and_sticky_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("metadata");
+        let path = dir.join("setuid");
+        let file = File::create(&path).unwrap();
+        let metadata = FileMetadata {
+            mode: Some(0o107755),
+            ..FileMetadata::default()
+        };
+        metadata.apply(&file, &path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o755);
+    }
+}
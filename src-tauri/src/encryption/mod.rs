@@ -1,4 +1,30 @@
+pub mod age_format;
+pub mod armor;
+pub mod fec;
+pub mod format;
+pub mod hash;
 pub mod helpers;
+pub mod metadata;
+pub mod openpgp;
+pub mod output;
+pub mod preview;
+pub mod progress;
+pub mod range;
+pub mod recovery;
+pub mod signature;
+pub mod text;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod upgrade;
+pub mod vault;
+pub mod volume;
 pub mod xchacha20;
 
-pub use xchacha20::{decrypt_file, encrypt_file};
+pub use hash::hash_file;
+pub use preview::preview_file;
+pub use range::DecryptReader;
+pub use signature::generate_signing_key;
+pub use text::{decrypt_clipboard, decrypt_text, encrypt_clipboard, encrypt_text};
+pub use upgrade::upgrade_file;
+pub use vault::{add_to_vault, create_vault, delete_from_vault, extract_from_vault, list_vault};
+pub use xchacha20::{decrypt_file, decrypt_range, encrypt_file};
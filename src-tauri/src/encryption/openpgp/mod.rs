@@ -0,0 +1,842 @@
+pub mod packet;
+pub mod s2k;
+
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use orion::util::secure_cmp;
+use rand_core::{OsRng, RngCore};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::{remove_file, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Instant, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    error::AppError,
+    types::{AppResponse, DecryptOptions, EncryptOptions, ProcessingStats, ResponseTextCode},
+};
+
+use super::{
+    armor::{is_armored_with, ArmorReader, ArmorWriter, PGP_LABEL},
+    helpers::{read_chunk, validate_password, EncryptionError, CHUNK_SIZE},
+    output::{
+        decrypted_name, finish_output, resolve_output_path, OutputTarget, DEFAULT_DECRYPTION_TEMPLATE, DEFAULT_PGP_ARMOR_TEMPLATE,
+        DEFAULT_PGP_TEMPLATE,
+    },
+    progress::ProgressEmitter,
+    xchacha20::{create_error_response, create_skipped_response, create_success_response},
+};
+use packet::{
+    BodyLength, PacketReader, PacketWriter, TAG_AEAD, TAG_COMPRESSED, TAG_LITERAL, TAG_MARKER,
+    TAG_ONE_PASS_SIGNATURE, TAG_PKESK, TAG_SED, TAG_SEIPD, TAG_SIGNATURE, TAG_SKESK,
+};
+use s2k::{CfbDecryptor, CfbEncryptor, S2k, SymmetricAlgorithm, BLOCK_SIZE};
+
+const SKESK_VERSION: u8 = 4;
+const SEIPD_VERSION: u8 = 1;
+/// Random block plus a repeat of its last two bytes, used to detect a wrong key early
+const PREFIX_LEN: usize = BLOCK_SIZE + 2;
+/// Modification detection code packet: header `0xD3 0x14` and a SHA-1 hash
+const MDC_HEADER: [u8; 2] = [0xD3, 0x14];
+const MDC_LEN: usize = 22;
+const LITERAL_BINARY: u8 = b'b';
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZIP: u8 = 1;
+const COMPRESSION_ZLIB: u8 = 2;
+/// Compressed packets inside compressed packets, at most
+const MAX_NESTING: usize = 8;
+
+/// True if `head` starts like a password-encrypted OpenPGP message, binary or armored
+pub fn is_openpgp(head: &[u8]) -> bool {
+    if is_armored_with(head, PGP_LABEL) {
+        return true;
+    }
+    // Binary messages from `gpg --symmetric` start with a symmetric-key encrypted session key packet
+    let mut rest = head;
+    matches!(packet::read_header(&mut rest), Ok(Some((TAG_SKESK, _))))
+        && matches!(rest, [4, 1..=13, 0 | 1 | 3, ..] | [5 | 6, ..])
+}
+
+/// Reads the start of the file at `path` and checks it with [`is_openpgp`]
+pub fn is_openpgp_file(path: &Path) -> bool {
+    let mut head = [0u8; 64];
+    File::open(path)
+        .and_then(|mut file| read_chunk(&mut file, &mut head))
+        .map(|n| is_openpgp(&head[..n]))
+        .unwrap_or(false)
+}
+
+/// Maps errors from reading the decrypted message
+fn read_error(e: io::Error) -> EncryptionError {
+    match e.kind() {
+        // Failed integrity checks, bad armor or a corrupt compressed stream
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => EncryptionError::Decryption,
+        io::ErrorKind::UnexpectedEof => EncryptionError::TruncatedFile,
+        _ => EncryptionError::Io(e),
+    }
+}
+
+/// Reads a packet header with [`packet::read_header`], mapping read errors like [`read_error`]
+fn read_header<R: Read + ?Sized>(reader: &mut R) -> Result<Option<(u8, BodyLength)>, EncryptionError> {
+    packet::read_header(reader).map_err(|e| match e {
+        EncryptionError::Io(e) => read_error(e),
+        e => e,
+    })
+}
+
+fn read_byte<R: Read + ?Sized>(reader: &mut R) -> Result<u8, EncryptionError> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte).map_err(read_error)?;
+    Ok(byte[0])
+}
+
+/// Binary or armored input
+enum MessageReader<R: Read> {
+    Binary(BufReader<R>),
+    Armored(ArmorReader<BufReader<R>>),
+}
+
+impl<R: Read> Read for MessageReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MessageReader::Binary(reader) => reader.read(buf),
+            MessageReader::Armored(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Decrypts the SEIPD body and checks the modification detection code at its end
+///
+/// The last 22 bytes of the plaintext are the MDC packet, so they are held back
+/// until the end of the body is reached. A mismatch fails the final read.
+struct SeipdReader<R: Read> {
+    inner: R,
+    cipher: CfbDecryptor,
+    mdc: Sha1,
+    buffer: Vec<u8>,
+    pending: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> SeipdReader<R> {
+    /// `prefix` is the already decrypted prefix at the start of the body
+    fn new(inner: R, cipher: CfbDecryptor, prefix: &[u8]) -> Self {
+        let mut mdc = Sha1::new();
+        mdc.update(prefix);
+        Self {
+            inner,
+            cipher,
+            mdc,
+            buffer: vec![0u8; 64 * 1024],
+            pending: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn verify(&mut self) -> io::Result<()> {
+        let tail = &self.pending[self.position..];
+        if tail.len() != MDC_LEN || tail[..2] != MDC_HEADER {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "OpenPGP message has no integrity check"));
+        }
+        let mut mdc = self.mdc.clone();
+        mdc.update(MDC_HEADER);
+        secure_cmp(&mdc.finalize(), &tail[2..])
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "OpenPGP integrity check failed"))
+    }
+}
+
+impl<R: Read> Read for SeipdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.finished {
+            return Ok(0);
+        }
+        loop {
+            let available = self.pending.len() - self.position;
+            if available > MDC_LEN {
+                let n = (available - MDC_LEN).min(buf.len());
+                let released = &self.pending[self.position..self.position + n];
+                buf[..n].copy_from_slice(released);
+                self.mdc.update(released);
+                self.position += n;
+                return Ok(n);
+            }
+
+            let n = self.inner.read(&mut self.buffer)?;
+            if n == 0 {
+                self.verify()?;
+                self.finished = true;
+                return Ok(0);
+            }
+            self.cipher.decrypt(&mut self.buffer[..n]);
+            self.pending.drain(..self.position);
+            self.position = 0;
+            self.pending.extend_from_slice(&self.buffer[..n]);
+        }
+    }
+}
+
+/// Encrypts the SEIPD body and appends the modification detection code
+///
+/// [`SeipdWriter::finish`] must be called to write the MDC.
+struct SeipdWriter<W: Write> {
+    inner: W,
+    cipher: CfbEncryptor,
+    mdc: Sha1,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SeipdWriter<W> {
+    /// Writes the encrypted random prefix
+    fn new(mut inner: W, key: &[u8]) -> Result<Self, EncryptionError> {
+        let mut cipher = CfbEncryptor::new(key)?;
+        let mut prefix = [0u8; PREFIX_LEN];
+        OsRng.fill_bytes(&mut prefix[..BLOCK_SIZE]);
+        prefix.copy_within(BLOCK_SIZE - 2..BLOCK_SIZE, BLOCK_SIZE);
+
+        let mut mdc = Sha1::new();
+        mdc.update(prefix);
+        cipher.encrypt(&mut prefix);
+        inner.write_all(&prefix)?;
+        Ok(Self { inner, cipher, mdc, buffer: Vec::new() })
+    }
+
+    /// Writes the MDC packet
+    ///
+    /// # Returns
+    /// The inner writer
+    fn finish(mut self) -> io::Result<W> {
+        self.mdc.update(MDC_HEADER);
+        let mut packet = MDC_HEADER.to_vec();
+        packet.extend_from_slice(&self.mdc.finalize());
+        self.cipher.encrypt(&mut packet);
+        self.inner.write_all(&packet)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SeipdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.mdc.update(buf);
+        self.buffer.clear();
+        self.buffer.extend_from_slice(buf);
+        self.cipher.encrypt(&mut self.buffer);
+        self.inner.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Derives the session key from one symmetric-key encrypted session key packet
+fn session_key(skesk: &[u8], password: &str) -> Result<(SymmetricAlgorithm, Zeroizing<Vec<u8>>), EncryptionError> {
+    match skesk {
+        [SKESK_VERSION, _, ..] => {}
+        [version, ..] => {
+            return Err(EncryptionError::UnsupportedFormat(format!(
+                "OpenPGP symmetric key packet version {}",
+                version
+            )))
+        }
+        [] => return Err(EncryptionError::InvalidFormat("Empty OpenPGP symmetric key packet".to_string())),
+    }
+    let algorithm = SymmetricAlgorithm::from_id(skesk[1])?;
+    let (s2k, s2k_len) = S2k::parse(&skesk[2..])?;
+    let key = s2k.derive_key(password.as_bytes(), algorithm.key_len());
+
+    // Without an encrypted session key the derived key is the session key
+    let encrypted_key = &skesk[2 + s2k_len..];
+    if encrypted_key.is_empty() {
+        return Ok((algorithm, key));
+    }
+    let mut session = Zeroizing::new(encrypted_key.to_vec());
+    CfbDecryptor::new(algorithm, &key)?.decrypt(&mut session);
+    let session_algorithm = SymmetricAlgorithm::from_id(session[0]).map_err(|_| EncryptionError::WrongPassword)?;
+    if session.len() - 1 != session_algorithm.key_len() {
+        return Err(EncryptionError::WrongPassword);
+    }
+    Ok((session_algorithm, Zeroizing::new(session[1..].to_vec())))
+}
+
+/// Finds the session key that decrypts `prefix` and checks its repeated bytes
+///
+/// # Returns
+/// The cipher positioned after the prefix and the decrypted prefix
+fn open_prefix(
+    skesks: &[Vec<u8>],
+    password: &str,
+    prefix: &[u8; PREFIX_LEN],
+) -> Result<(CfbDecryptor, [u8; PREFIX_LEN]), EncryptionError> {
+    let mut error = EncryptionError::UnsupportedFormat("OpenPGP message is encrypted to a public key".to_string());
+    for skesk in skesks {
+        let (algorithm, key) = match session_key(skesk, password) {
+            Ok(session) => session,
+            Err(EncryptionError::WrongPassword) => {
+                error = EncryptionError::WrongPassword;
+                continue;
+            }
+            Err(e) => {
+                if !matches!(error, EncryptionError::WrongPassword) {
+                    error = e;
+                }
+                continue;
+            }
+        };
+        let mut cipher = CfbDecryptor::new(algorithm, &key)?;
+        let mut decrypted = *prefix;
+        cipher.decrypt(&mut decrypted);
+        if decrypted[BLOCK_SIZE - 2..BLOCK_SIZE] == decrypted[BLOCK_SIZE..] {
+            return Ok((cipher, decrypted));
+        }
+        error = EncryptionError::WrongPassword;
+    }
+    Err(error)
+}
+
+/// Receives the content of the literal data packet
+struct LiteralOutput<'a, W: Write, P: FnMut(usize)> {
+    writer: &'a mut W,
+    progress: P,
+    bytes_processed: usize,
+    found: bool,
+}
+
+impl<W: Write, P: FnMut(usize)> LiteralOutput<'_, W, P> {
+    /// Skips the literal header (format, file name, date) and copies the data
+    fn copy<R: Read>(&mut self, body: &mut R) -> Result<(), EncryptionError> {
+        if self.found {
+            return Err(EncryptionError::InvalidFormat("OpenPGP message has more than one literal packet".to_string()));
+        }
+        self.found = true;
+        let _format = read_byte(body)?;
+        let name_len = read_byte(body)? as usize;
+        let mut header = vec![0u8; name_len + 4];
+        body.read_exact(&mut header).map_err(read_error)?;
+
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = read_chunk(body, &mut buffer).map_err(read_error)?;
+            if n == 0 { break; }
+            self.writer.write_all(&buffer[..n])?;
+            self.bytes_processed += n;
+            (self.progress)(self.bytes_processed);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the packets of the decrypted message, writing the literal data to `output`
+fn read_message<W: Write, P: FnMut(usize)>(
+    reader: &mut dyn Read,
+    output: &mut LiteralOutput<'_, W, P>,
+    depth: usize,
+) -> Result<(), EncryptionError> {
+    // Reading past the last packet reaches the end of the encrypted data, which verifies the MDC
+    while let Some((tag, length)) = read_header(&mut *reader)? {
+        let mut body = PacketReader::new(&mut *reader, length);
+        match tag {
+            TAG_COMPRESSED if depth < MAX_NESTING => match read_byte(&mut body)? {
+                COMPRESSION_NONE => read_message(&mut body, output, depth + 1)?,
+                COMPRESSION_ZIP => read_message(&mut DeflateDecoder::new(&mut body), output, depth + 1)?,
+                COMPRESSION_ZLIB => read_message(&mut ZlibDecoder::new(&mut body), output, depth + 1)?,
+                algorithm => {
+                    return Err(EncryptionError::UnsupportedFormat(format!(
+                        "OpenPGP compression algorithm {}",
+                        algorithm
+                    )))
+                }
+            },
+            TAG_LITERAL => output.copy(&mut body)?,
+            // Signatures can't be checked without the signer's public key
+            TAG_ONE_PASS_SIGNATURE | TAG_SIGNATURE | TAG_MARKER => {}
+            _ => return Err(EncryptionError::InvalidFormat(format!("Unexpected OpenPGP packet {}", tag))),
+        }
+        body.skip().map_err(read_error)?;
+    }
+    Ok(())
+}
+
+/// Decrypts a password-encrypted OpenPGP message (SKESK and SEIPD) from `reader` into `writer`
+///
+/// Supports AES with simple, salted and iterated S2K, and ZIP or ZLIB compression.
+/// The integrity check covers the whole message and is verified at its end, so
+/// `writer` receives the content before it is known to be intact; callers discard
+/// the output on error.
+///
+/// # Arguments
+/// * `progress` - Called with the number of plaintext bytes written so far
+///
+/// # Returns
+/// The number of plaintext bytes
+pub fn decrypt<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    password: &str,
+    progress: impl FnMut(usize),
+) -> Result<usize, EncryptionError> {
+    let mut reader = BufReader::new(reader);
+    let mut reader = if is_armored_with(reader.fill_buf()?, PGP_LABEL) {
+        MessageReader::Armored(
+            ArmorReader::with_label(reader, PGP_LABEL).map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?,
+        )
+    } else {
+        MessageReader::Binary(reader)
+    };
+
+    // Session key packets come first, then the encrypted data
+    let mut skesks = Vec::new();
+    let mut body = loop {
+        let (tag, length) = read_header(&mut reader)?
+            .ok_or_else(|| EncryptionError::InvalidFormat("OpenPGP message has no encrypted data".to_string()))?;
+        let mut body = PacketReader::new(&mut reader, length);
+        match tag {
+            TAG_SEIPD => break body,
+            TAG_SKESK if matches!(length, BodyLength::Fixed(len) if len < 1024) => {
+                let mut skesk = Vec::new();
+                body.read_to_end(&mut skesk).map_err(read_error)?;
+                skesks.push(skesk);
+            }
+            TAG_PKESK | TAG_MARKER => body.skip().map_err(read_error)?,
+            TAG_SED => {
+                return Err(EncryptionError::UnsupportedFormat(
+                    "OpenPGP data without integrity protection".to_string(),
+                ))
+            }
+            TAG_AEAD => return Err(EncryptionError::UnsupportedFormat("OpenPGP AEAD encrypted data".to_string())),
+            _ => return Err(EncryptionError::InvalidFormat(format!("Unexpected OpenPGP packet {}", tag))),
+        }
+    };
+
+    let version = read_byte(&mut body)?;
+    if version != SEIPD_VERSION {
+        return Err(EncryptionError::UnsupportedFormat(format!("OpenPGP encrypted data version {}", version)));
+    }
+    let mut prefix = [0u8; PREFIX_LEN];
+    body.read_exact(&mut prefix).map_err(read_error)?;
+    let (cipher, prefix) = open_prefix(&skesks, password, &prefix)?;
+
+    let mut output = LiteralOutput {
+        writer,
+        progress,
+        bytes_processed: 0,
+        found: false,
+    };
+    // Reads until the end of the encrypted data, which verifies the MDC
+    read_message(&mut SeipdReader::new(body, cipher, &prefix), &mut output, 0)?;
+    if !output.found {
+        return Err(EncryptionError::InvalidFormat("OpenPGP message has no literal data".to_string()));
+    }
+    output.writer.flush()?;
+    Ok(output.bytes_processed)
+}
+
+/// Encrypts `reader` into `writer` as a password-encrypted OpenPGP message
+///
+/// Writes AES-256 with iterated and salted SHA-256 S2K and an MDC, uncompressed,
+/// which `gpg --decrypt` reads.
+///
+/// # Arguments
+/// * `file_name` - Stored in the literal packet, at most 255 bytes are kept
+/// * `modified` - Modification time stored in the literal packet, in seconds since the epoch
+/// * `progress` - Called with the number of plaintext bytes read so far
+///
+/// # Returns
+/// The inner writer
+pub fn encrypt<R: Read, W: Write>(
+    reader: &mut R,
+    mut writer: W,
+    password: &str,
+    file_name: &[u8],
+    modified: u32,
+    mut progress: impl FnMut(usize),
+) -> Result<W, EncryptionError> {
+    let s2k = S2k::iterated();
+    let algorithm = SymmetricAlgorithm::Aes256;
+    let key = s2k.derive_key(password.as_bytes(), algorithm.key_len());
+
+    let mut skesk = vec![SKESK_VERSION, algorithm.id()];
+    skesk.extend_from_slice(&s2k.to_bytes());
+    packet::write_packet(&mut writer, TAG_SKESK, &skesk)?;
+
+    let mut seipd = PacketWriter::new(writer, TAG_SEIPD)?;
+    seipd.write_all(&[SEIPD_VERSION])?;
+    let mut literal = PacketWriter::new(SeipdWriter::new(seipd, &key)?, TAG_LITERAL)?;
+    let file_name = &file_name[..file_name.len().min(255)];
+    literal.write_all(&[LITERAL_BINARY, file_name.len() as u8])?;
+    literal.write_all(file_name)?;
+    literal.write_all(&modified.to_be_bytes())?;
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut bytes_processed = 0;
+    loop {
+        let n = read_chunk(reader, &mut buffer)?;
+        if n == 0 { break; }
+        literal.write_all(&buffer[..n])?;
+        bytes_processed += n;
+        progress(bytes_processed);
+    }
+
+    let seipd = literal.finish()?.finish()?;
+    Ok(seipd.finish()?)
+}
+
+/// `encrypt_file` for [`FileFormat::OpenPgp`](crate::types::FileFormat::OpenPgp)
+pub fn encrypt_openpgp_file(
+    progress: &mut ProgressEmitter,
+    file_path: &str,
+    password: &str,
+    delete_original: bool,
+    options: &EncryptOptions,
+) -> Result<AppResponse, AppResponse> {
+    let start_time = Instant::now();
+    if options.uses_cipher_features() || !options.age_recipients.is_empty() {
+        return Err(AppError::InvalidOptions(
+            "OpenPGP files support only armored output and the output options".to_string(),
+        ).into_response(Some(file_path.to_string())));
+    }
+
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
+    })?;
+    let file_metadata = source_file.metadata().ok();
+    let file_size = file_metadata.as_ref().map(|m| m.len()).unwrap_or(0) as usize;
+    let modified = file_metadata
+        .and_then(|m| m.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| since_epoch.as_secs().min(u32::MAX as u64) as u32);
+
+    let input_name = Path::new(file_path)
+        .file_name()
+        .ok_or_else(|| create_error_response(ResponseTextCode::FileNameExtractionFailed, Some(file_path.to_string())))?;
+    let template = if options.armored { DEFAULT_PGP_ARMOR_TEMPLATE } else { DEFAULT_PGP_TEMPLATE };
+    let (output_path, destination) = match resolve_output_path(Path::new(file_path), input_name, &options.output, template)
+        .map_err(|e| e.into_response(Some(file_path.to_string())))?
+    {
+        OutputTarget::Create(path) => (path.clone(), path),
+        OutputTarget::Replace { temp, destination } => (temp, destination),
+        OutputTarget::Skip(existing_path) => return Ok(create_skipped_response(&existing_path)),
+    };
+    let output_path_str = destination.display().to_string();
+
+    let output_file = BufWriter::new(File::create(&output_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?);
+    progress.set_total(file_size);
+    let mut reader = BufReader::new(source_file);
+    let file_name = input_name.to_string_lossy();
+    let encrypted = if options.armored {
+        ArmorWriter::with_label(output_file, PGP_LABEL)
+            .map_err(EncryptionError::from)
+            .and_then(|writer| encrypt(&mut reader, writer, password, file_name.as_bytes(), modified, |n| progress.update(n)))
+            .and_then(|writer| Ok(writer.finish()?.flush()?))
+    } else {
+        encrypt(&mut reader, output_file, password, file_name.as_bytes(), modified, |n| progress.update(n))
+            .and_then(|mut writer| Ok(writer.flush()?))
+    };
+    encrypted.map_err(|e| {
+        let _ = remove_file(&output_path);
+        AppError::from(e).into_response(Some(output_path_str.clone()))
+    })?;
+
+    finish_output(&output_path, &destination).map_err(|e| {
+        let _ = remove_file(&output_path);
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
+    let total_time = start_time.elapsed().as_secs_f64();
+    if delete_original {
+        remove_file(file_path).map_err(|e| {
+            AppError::io(ResponseTextCode::FileDeleteFailed, e).into_response(Some(file_path.to_string()))
+        })?;
+    }
+
+    Ok(create_success_response(
+        ResponseTextCode::EncryptionSuccessful,
+        output_path_str,
+        ProcessingStats {
+            total_size_bytes: file_size,
+            processing_time_seconds: total_time,
+            average_speed_mbps: file_size as f64 / (1024.0 * 1024.0) / total_time,
+            content_hash: None,
+        },
+    ))
+}
+
+/// `decrypt_file` for files recognized by [`is_openpgp_file`]
+pub fn decrypt_openpgp_file(
+    progress: &mut ProgressEmitter,
+    file_path: &str,
+    password: &str,
+    delete_original: bool,
+    options: &DecryptOptions,
+) -> Result<AppResponse, AppResponse> {
+    let start_time = Instant::now();
+    if options.recovery.is_some() {
+        return Err(AppError::InvalidOptions("OpenPGP files can't be decrypted in recovery mode".to_string())
+            .into_response(Some(file_path.to_string())));
+    }
+    // Passphrases set with gpg need not meet our password policy, so they aren't validated here
+
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
+    })?;
+    let file_size = source_file.metadata().map(|m| m.len()).unwrap_or(0) as usize;
+
+    // Like gpg, the output is named after the input; the name in the literal packet is ignored
+    let output_name = decrypted_name(Path::new(file_path))
+        .map_err(|e| e.into_response(Some(file_path.to_string())))?;
+    let (output_path, destination) = match resolve_output_path(Path::new(file_path), &output_name, &options.output, DEFAULT_DECRYPTION_TEMPLATE)
+        .map_err(|e| e.into_response(Some(file_path.to_string())))?
+    {
+        OutputTarget::Create(path) => (path.clone(), path),
+        OutputTarget::Replace { temp, destination } => (temp, destination),
+        OutputTarget::Skip(existing_path) => return Ok(create_skipped_response(&existing_path)),
+    };
+    let output_path_str = destination.display().to_string();
+
+    let mut writer = BufWriter::new(File::create(&output_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?);
+    // Measured in plaintext bytes against the file size, which differs when the message is compressed
+    progress.set_total(file_size);
+    let decrypted = decrypt(source_file, &mut writer, password, |n| progress.update(n));
+    drop(writer);
+    let plaintext_len = decrypted.map_err(|e| {
+        let _ = remove_file(&output_path);
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
+
+    finish_output(&output_path, &destination).map_err(|e| {
+        let _ = remove_file(&output_path);
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
+    let total_time = start_time.elapsed().as_secs_f64();
+    if delete_original {
+        remove_file(file_path).map_err(|e| {
+            AppError::io(ResponseTextCode::FileDeleteFailed, e).into_response(Some(file_path.to_string()))
+        })?;
+    }
+
+    Ok(create_success_response(
+        ResponseTextCode::DecryptionSuccessful,
+        output_path_str,
+        ProcessingStats {
+            total_size_bytes: plaintext_len,
+            processing_time_seconds: total_time,
+            average_speed_mbps: plaintext_len as f64 / (1024.0 * 1024.0) / total_time,
+            content_hash: None,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::test_util::{test_dir, PASSWORD},
+        types::{FileFormat, Status},
+    };
+    use std::{fs, path::PathBuf, process::Command};
+
+    /// Password of every fixture
+    const FIXTURE_PASSWORD: &str = "OpenPGP-Test-1!";
+    const FIXTURE_PLAINTEXT: &[u8] = b"cipher openpgp fixture\n";
+
+    /// Messages written by `gpg --symmetric`, described in the README next to them
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/openpgp").join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn decrypt_bytes(message: &[u8], password: &str) -> Result<Vec<u8>, EncryptionError> {
+        let mut plaintext = Vec::new();
+        decrypt(message, &mut plaintext, password, |_| {})?;
+        Ok(plaintext)
+    }
+
+    /// The body of the session key packet at the start of a binary message, and the packets after it
+    fn split_skesk(message: &[u8]) -> (&[u8], &[u8]) {
+        let mut rest = message;
+        let Ok(Some((TAG_SKESK, BodyLength::Fixed(len)))) = packet::read_header(&mut rest) else {
+            panic!("message doesn't start with a session key packet");
+        };
+        rest.split_at(len as usize)
+    }
+
+    /// Decrypts the encrypted data packet of a binary message, without the MDC
+    fn decrypted_packets(message: &[u8]) -> Vec<u8> {
+        let (skesk, mut rest) = split_skesk(message);
+        let Ok(Some((TAG_SEIPD, length))) = packet::read_header(&mut rest) else {
+            panic!("session key packet isn't followed by encrypted data");
+        };
+        let mut body = PacketReader::new(rest, length);
+        assert_eq!(read_byte(&mut body).unwrap(), SEIPD_VERSION);
+        let mut prefix = [0u8; PREFIX_LEN];
+        body.read_exact(&mut prefix).unwrap();
+        let (cipher, prefix) = open_prefix(&[skesk.to_vec()], FIXTURE_PASSWORD, &prefix).unwrap();
+        let mut packets = Vec::new();
+        SeipdReader::new(body, cipher, &prefix).read_to_end(&mut packets).unwrap();
+        packets
+    }
+
+    /// Spans several partial body chunks
+    fn content() -> Vec<u8> {
+        (0..200_000usize).map(|i| (i.wrapping_mul(31) ^ (i >> 9)) as u8).collect()
+    }
+
+    /// Encrypts `content` with [`encrypt_openpgp_file`]
+    fn export(dir: &Path, content: &[u8], armored: bool) -> PathBuf {
+        let source = dir.join("export.bin");
+        fs::write(&source, content).unwrap();
+        let options = EncryptOptions { format: FileFormat::OpenPgp, armored, ..EncryptOptions::default() };
+        let response = encrypt_openpgp_file(&mut ProgressEmitter::silent(), &source.display().to_string(), PASSWORD, false, &options)
+            .unwrap();
+        PathBuf::from(response.file_path.unwrap())
+    }
+
+    /// Decrypts `path` with [`decrypt_openpgp_file`]
+    fn import(path: &Path, password: &str) -> Result<Vec<u8>, AppResponse> {
+        let response = decrypt_openpgp_file(
+            &mut ProgressEmitter::silent(),
+            &path.display().to_string(),
+            password,
+            false,
+            &DecryptOptions::default(),
+        )?;
+        Ok(fs::read(response.file_path.unwrap()).unwrap())
+    }
+
+    #[test]
+    fn decrypts_gpg_fixtures() {
+        let fixtures = [
+            ("aes128.gpg", SymmetricAlgorithm::Aes128, 3, None),
+            ("aes256.gpg", SymmetricAlgorithm::Aes256, 3, None),
+            ("s2k-simple.gpg", SymmetricAlgorithm::Aes256, 0, None),
+            ("s2k-salted.gpg", SymmetricAlgorithm::Aes256, 1, None),
+            ("zip.gpg", SymmetricAlgorithm::Aes256, 3, Some(COMPRESSION_ZIP)),
+            ("zlib.gpg", SymmetricAlgorithm::Aes256, 3, Some(COMPRESSION_ZLIB)),
+        ];
+        for (name, algorithm, s2k_type, compression) in fixtures {
+            let message = fixture(name);
+            assert!(is_openpgp(&message), "{name}");
+            // The fixture covers what it is named after
+            let (skesk, _) = split_skesk(&message);
+            assert_eq!(SymmetricAlgorithm::from_id(skesk[1]).unwrap(), algorithm, "{name}");
+            assert_eq!(skesk[2], s2k_type, "{name}");
+            let packets = decrypted_packets(&message);
+            let mut rest = &packets[..];
+            match packet::read_header(&mut rest).unwrap() {
+                Some((TAG_COMPRESSED, _)) => assert_eq!(Some(rest[0]), compression, "{name}"),
+                Some((TAG_LITERAL, _)) => assert_eq!(None, compression, "{name}"),
+                other => panic!("{name}: {:?}", other),
+            }
+
+            let plaintext = decrypt_bytes(&message, FIXTURE_PASSWORD).unwrap_or_else(|e| panic!("{name}: {e}"));
+            assert_eq!(plaintext, FIXTURE_PLAINTEXT, "{name}");
+        }
+    }
+
+    #[test]
+    fn decrypts_armored_gpg_fixture() {
+        let message = fixture("armored.asc");
+        assert!(is_openpgp(&message));
+        assert_eq!(decrypt_bytes(&message, FIXTURE_PASSWORD).unwrap(), FIXTURE_PLAINTEXT);
+    }
+
+    #[test]
+    fn decrypts_partial_body_lengths() {
+        let message = fixture("partial.gpg");
+        let (_, mut rest) = split_skesk(&message);
+        assert!(matches!(packet::read_header(&mut rest), Ok(Some((TAG_SEIPD, BodyLength::Partial(_))))));
+        let packets = decrypted_packets(&message);
+        assert!(matches!(packet::read_header(&mut &packets[..]), Ok(Some((TAG_LITERAL, BodyLength::Partial(_))))));
+
+        let expected: String = (0..600).map(|i| format!("line {:04} of the OpenPGP fixture\n", i)).collect();
+        assert_eq!(decrypt_bytes(&message, FIXTURE_PASSWORD).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn rejects_a_bad_mdc() {
+        let result = decrypt_bytes(&fixture("bad-mdc.gpg"), FIXTURE_PASSWORD);
+        assert!(matches!(result, Err(EncryptionError::Decryption)), "{:?}", result);
+
+        // The content was written before the check failed, so the output is removed
+        let dir = test_dir("bad-mdc");
+        let path = dir.join("bad-mdc.gpg");
+        fs::write(&path, fixture("bad-mdc.gpg")).unwrap();
+        let response = import(&path, FIXTURE_PASSWORD).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk));
+        assert!(!dir.join("bad-mdc").exists());
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        for name in ["aes128.gpg", "aes256.gpg", "s2k-simple.gpg", "s2k-salted.gpg", "zip.gpg", "armored.asc", "partial.gpg"] {
+            let result = decrypt_bytes(&fixture(name), "OpenPGP-Test-2!");
+            assert!(matches!(result, Err(EncryptionError::WrongPassword)), "{name}: {:?}", result);
+        }
+    }
+
+    #[test]
+    fn rejects_a_cut_off_message() {
+        let message = fixture("partial.gpg");
+        let result = decrypt_bytes(&message[..message.len() - 100], FIXTURE_PASSWORD);
+        assert!(matches!(result, Err(EncryptionError::TruncatedFile)), "{:?}", result);
+    }
+
+    #[test]
+    fn exported_files_import_again() {
+        let dir = test_dir("round-trip");
+        let content = content();
+        for armored in [false, true] {
+            let path = export(&dir, &content, armored);
+            let message = fs::read(&path).unwrap();
+            assert!(is_openpgp(&message));
+            assert_eq!(message.starts_with(b"-----BEGIN PGP MESSAGE-----"), armored);
+            assert_eq!(import(&path, PASSWORD).unwrap(), content);
+
+            let response = import(&path, "Wrong-Password-1!").unwrap_err();
+            assert!(matches!(response.status, Status::Error));
+            assert!(matches!(response.text_code, ResponseTextCode::WrongPassword));
+        }
+    }
+
+    #[test]
+    fn exports_partial_body_lengths() {
+        let dir = test_dir("partial");
+        let message = fs::read(export(&dir, &content(), false)).unwrap();
+        let (_, mut rest) = split_skesk(&message);
+        assert!(matches!(packet::read_header(&mut rest), Ok(Some((TAG_SEIPD, BodyLength::Partial(_))))));
+    }
+
+    /// Needs `gpg` on the `PATH`; run with `cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs gpg"]
+    fn gpg_decrypts_exported_files() {
+        let dir = test_dir("gpg");
+        let home = dir.join("gnupg");
+        fs::create_dir(&home).unwrap();
+        let content = content();
+        for armored in [false, true] {
+            let path = export(&dir, &content, armored);
+            let output = Command::new("gpg")
+                .env("GNUPGHOME", &home)
+                .args(["--batch", "--quiet", "--no-permission-warning", "--pinentry-mode", "loopback"])
+                .args(["--passphrase", PASSWORD, "--decrypt"])
+                .arg(&path)
+                .output()
+                .unwrap_or_else(|e| panic!("gpg: {}", e));
+            assert!(output.status.success(), "gpg: {}", String::from_utf8_lossy(&output.stderr));
+            assert!(output.stdout == content, "gpg decrypted different content");
+        }
+    }
+}
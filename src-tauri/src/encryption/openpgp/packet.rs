@@ -0,0 +1,321 @@
+use std::io::{self, Read, Write};
+
+use crate::encryption::helpers::EncryptionError;
+
+pub const TAG_PKESK: u8 = 1;
+pub const TAG_SIGNATURE: u8 = 2;
+pub const TAG_SKESK: u8 = 3;
+pub const TAG_ONE_PASS_SIGNATURE: u8 = 4;
+pub const TAG_COMPRESSED: u8 = 8;
+pub const TAG_SED: u8 = 9;
+pub const TAG_MARKER: u8 = 10;
+pub const TAG_LITERAL: u8 = 11;
+pub const TAG_SEIPD: u8 = 18;
+pub const TAG_AEAD: u8 = 20;
+
+/// Partial body chunks written by [`PacketWriter`] are 2^16 bytes
+const PARTIAL_CHUNK_BITS: u32 = 16;
+const PARTIAL_CHUNK_LEN: usize = 1 << PARTIAL_CHUNK_BITS;
+
+/// How the length of a packet body is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyLength {
+    Fixed(u64),
+    /// A chunk of this length, followed by another length
+    Partial(u64),
+    /// Until the end of the input (old-format packets only)
+    Indeterminate,
+}
+
+/// Reads one byte, or `None` at the end of the input
+fn read_byte<R: Read + ?Sized>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_be<R: Read + ?Sized>(reader: &mut R, len: usize) -> io::Result<u64> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes[..len])?;
+    Ok(bytes[..len].iter().fold(0, |value, &byte| value << 8 | byte as u64))
+}
+
+/// Reads a new-format length starting with `first`
+fn read_length<R: Read + ?Sized>(reader: &mut R, first: u8) -> io::Result<BodyLength> {
+    Ok(match first {
+        0..=191 => BodyLength::Fixed(first as u64),
+        192..=223 => BodyLength::Fixed((((first as u64) - 192) << 8) + read_be(reader, 1)? + 192),
+        224..=254 => BodyLength::Partial(1 << (first & 0x1F)),
+        255 => BodyLength::Fixed(read_be(reader, 4)?),
+    })
+}
+
+/// Reads a packet header in the old or the new format
+///
+/// # Returns
+/// The packet tag and body length, or `None` at the end of the input
+pub fn read_header<R: Read + ?Sized>(reader: &mut R) -> Result<Option<(u8, BodyLength)>, EncryptionError> {
+    let truncated = |_| EncryptionError::TruncatedFile;
+    let Some(first) = read_byte(reader)? else {
+        return Ok(None);
+    };
+    if first & 0x80 == 0 {
+        return Err(EncryptionError::InvalidFormat("Not an OpenPGP packet".to_string()));
+    }
+
+    if first & 0x40 != 0 {
+        let length_start = read_byte(reader)?.ok_or(EncryptionError::TruncatedFile)?;
+        return Ok(Some((first & 0x3F, read_length(reader, length_start).map_err(truncated)?)));
+    }
+    let tag = (first >> 2) & 0x0F;
+    let length = match first & 0x03 {
+        0 => BodyLength::Fixed(read_be(reader, 1).map_err(truncated)?),
+        1 => BodyLength::Fixed(read_be(reader, 2).map_err(truncated)?),
+        2 => BodyLength::Fixed(read_be(reader, 4).map_err(truncated)?),
+        _ => BodyLength::Indeterminate,
+    };
+    Ok(Some((tag, length)))
+}
+
+/// Reads a packet body, joining partial body chunks
+///
+/// Reads end at the end of the body; a body cut short fails with `UnexpectedEof`.
+pub struct PacketReader<R: Read> {
+    inner: R,
+    length: BodyLength,
+    /// Bytes left in the current chunk
+    remaining: u64,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R, length: BodyLength) -> Self {
+        let remaining = match length {
+            BodyLength::Fixed(len) | BodyLength::Partial(len) => len,
+            BodyLength::Indeterminate => 0,
+        };
+        Self { inner, length, remaining }
+    }
+
+    /// Reads the rest of the body, e.g. of a packet that is ignored
+    pub fn skip(&mut self) -> io::Result<()> {
+        io::copy(self, &mut io::sink()).map(|_| ())
+    }
+}
+
+impl<R: Read> Read for PacketReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.length == BodyLength::Indeterminate {
+                return self.inner.read(buf);
+            }
+            if self.remaining > 0 {
+                break;
+            }
+            if !matches!(self.length, BodyLength::Partial(_)) {
+                return Ok(0);
+            }
+            let first = read_byte(&mut self.inner)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "OpenPGP packet is truncated"))?;
+            self.length = read_length(&mut self.inner, first)?;
+            self.remaining = match self.length {
+                BodyLength::Fixed(len) | BodyLength::Partial(len) => len,
+                BodyLength::Indeterminate => 0,
+            };
+        }
+
+        let len = buf.len().min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "OpenPGP packet is truncated"));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Encodes a new-format body length
+fn encode_length(len: usize) -> Vec<u8> {
+    match len {
+        0..=191 => vec![len as u8],
+        192..=8383 => {
+            let len = len - 192;
+            vec![(len >> 8) as u8 + 192, len as u8]
+        }
+        _ => {
+            let mut encoded = vec![255];
+            encoded.extend_from_slice(&(len as u32).to_be_bytes());
+            encoded
+        }
+    }
+}
+
+/// Writes a complete packet with a short body
+pub fn write_packet<W: Write>(writer: &mut W, tag: u8, body: &[u8]) -> io::Result<()> {
+    writer.write_all(&[0xC0 | tag])?;
+    writer.write_all(&encode_length(body.len()))?;
+    writer.write_all(body)
+}
+
+/// Writes a packet body of unknown length as partial body chunks
+///
+/// [`PacketWriter::finish`] must be called to write the last chunk.
+pub struct PacketWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> PacketWriter<W> {
+    /// Writes the new-format header of a packet with `tag`
+    pub fn new(mut inner: W, tag: u8) -> io::Result<Self> {
+        inner.write_all(&[0xC0 | tag])?;
+        Ok(Self {
+            inner,
+            buffer: Vec::with_capacity(PARTIAL_CHUNK_LEN),
+        })
+    }
+
+    /// Writes the rest of the body with a regular length
+    ///
+    /// # Returns
+    /// The inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&encode_length(self.buffer.len()))?;
+        self.inner.write_all(&self.buffer)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for PacketWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // A full chunk is only written once more data follows, the last chunk needs a regular length
+        if self.buffer.len() == PARTIAL_CHUNK_LEN {
+            self.inner.write_all(&[0xE0 | PARTIAL_CHUNK_BITS as u8])?;
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        let n = buf.len().min(PARTIAL_CHUNK_LEN - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(bytes: &[u8]) -> Result<Option<(u8, BodyLength)>, EncryptionError> {
+        read_header(&mut &bytes[..])
+    }
+
+    /// Reads one packet from `bytes`, returning its tag, body and the bytes after it
+    fn read_packet(bytes: &[u8]) -> (u8, Vec<u8>, &[u8]) {
+        let mut rest = bytes;
+        let (tag, length) = read_header(&mut rest).unwrap().unwrap();
+        let mut body = Vec::new();
+        let mut reader = PacketReader::new(&mut rest, length);
+        reader.read_to_end(&mut body).unwrap();
+        (tag, body, rest)
+    }
+
+    #[test]
+    fn reads_new_format_lengths() {
+        // The examples of RFC 4880, section 4.2.3
+        assert!(matches!(header(&[0xCB, 0x64]), Ok(Some((TAG_LITERAL, BodyLength::Fixed(100))))));
+        assert!(matches!(header(&[0xCB, 0xC5, 0xFB]), Ok(Some((TAG_LITERAL, BodyLength::Fixed(1723))))));
+        assert!(matches!(header(&[0xCB, 0xFF, 0x00, 0x01, 0x86, 0xA0]), Ok(Some((TAG_LITERAL, BodyLength::Fixed(100_000))))));
+        assert!(matches!(header(&[0xD2, 0xEF]), Ok(Some((TAG_SEIPD, BodyLength::Partial(32768))))));
+
+        assert_eq!(encode_length(100), [0x64]);
+        assert_eq!(encode_length(1723), [0xC5, 0xFB]);
+        assert_eq!(encode_length(100_000), [0xFF, 0x00, 0x01, 0x86, 0xA0]);
+    }
+
+    #[test]
+    fn reads_old_format_lengths() {
+        assert!(matches!(header(&[0x8C, 0x0D]), Ok(Some((TAG_SKESK, BodyLength::Fixed(13))))));
+        assert!(matches!(header(&[0xA5, 0x01, 0x00]), Ok(Some((9, BodyLength::Fixed(256))))));
+        assert!(matches!(header(&[0xAE, 0x00, 0x01, 0x86, 0xA0]), Ok(Some((TAG_LITERAL, BodyLength::Fixed(100_000))))));
+        assert!(matches!(header(&[0xA3]), Ok(Some((TAG_COMPRESSED, BodyLength::Indeterminate)))));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(matches!(header(&[]), Ok(None)));
+        assert!(matches!(header(&[0x3C, 0x64]), Err(EncryptionError::InvalidFormat(_))));
+        assert!(matches!(header(&[0xCB]), Err(EncryptionError::TruncatedFile)));
+        assert!(matches!(header(&[0xCB, 0xFF, 0x00]), Err(EncryptionError::TruncatedFile)));
+        assert!(matches!(header(&[0x8D, 0x01]), Err(EncryptionError::TruncatedFile)));
+    }
+
+    #[test]
+    fn joins_partial_body_chunks() {
+        // Chunks of 2 and 1 bytes, then the last 2 bytes, then the next packet
+        let bytes = [0xCB, 0xE1, b'a', b'b', 0xE0, b'c', 0x02, b'd', b'e', 0xCA];
+        let (tag, body, rest) = read_packet(&bytes);
+        assert_eq!(tag, TAG_LITERAL);
+        assert_eq!(body, b"abcde");
+        assert_eq!(rest, [0xCA]);
+    }
+
+    #[test]
+    fn reads_indeterminate_bodies_to_the_end() {
+        let (tag, body, rest) = read_packet(&[0xAF, 1, 2, 3]);
+        assert_eq!(tag, TAG_LITERAL);
+        assert_eq!(body, [1, 2, 3]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn fails_on_truncated_bodies() {
+        for bytes in [&[0xCB, 0x05, b'a', b'b'][..], &[0xCB, 0xE2, b'a', b'b', b'c', b'd'], &[0xCB, 0xE1, b'a', b'b']] {
+            let mut rest = bytes;
+            let (_, length) = read_header(&mut rest).unwrap().unwrap();
+            let error = PacketReader::new(rest, length).read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn skips_bodies() {
+        let bytes = [0xCB, 0xE1, b'a', b'b', 0x01, b'c', 0xCA];
+        let mut rest = &bytes[..];
+        let (_, length) = read_header(&mut rest).unwrap().unwrap();
+        PacketReader::new(&mut rest, length).skip().unwrap();
+        assert_eq!(rest, [0xCA]);
+    }
+
+    #[test]
+    fn writes_packets_that_read_back() {
+        for len in [0, 1, 191, 192, 8383, 8384, PARTIAL_CHUNK_LEN, PARTIAL_CHUNK_LEN + 1, 3 * PARTIAL_CHUNK_LEN + 5] {
+            let body: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let mut writer = PacketWriter::new(Vec::new(), TAG_LITERAL).unwrap();
+            // Uneven writes, so chunks fill across calls
+            for piece in body.chunks(10_000) {
+                writer.write_all(piece).unwrap();
+            }
+            let mut bytes = writer.finish().unwrap();
+            write_packet(&mut bytes, TAG_MARKER, b"PGP").unwrap();
+
+            let (tag, read_body, rest) = read_packet(&bytes);
+            assert_eq!(tag, TAG_LITERAL, "{len}");
+            assert!(read_body == body, "{len}: body differs");
+            assert_eq!(read_packet(rest), (TAG_MARKER, b"PGP".to_vec(), &[][..]), "{len}");
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use aes::{Aes128, Aes192, Aes256};
+use cfb_mode::{cipher::KeyIvInit, BufDecryptor, BufEncryptor};
+use rand_core::{OsRng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+use zeroize::Zeroizing;
+
+use crate::encryption::helpers::EncryptionError;
+
+/// AES block size; OpenPGP's CFB prefix is one block plus two check bytes
+pub const BLOCK_SIZE: usize = 16;
+
+const S2K_SIMPLE: u8 = 0;
+const S2K_SALTED: u8 = 1;
+const S2K_ITERATED: u8 = 3;
+const SALT_LEN: usize = 8;
+/// Coded count for new files: 65011712 bytes, the most OpenPGP can express
+const ITERATED_COUNT: u8 = 0xFF;
+
+/// Symmetric ciphers with their OpenPGP algorithm ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetricAlgorithm {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl SymmetricAlgorithm {
+    pub fn from_id(id: u8) -> Result<Self, EncryptionError> {
+        match id {
+            7 => Ok(Self::Aes128),
+            8 => Ok(Self::Aes192),
+            9 => Ok(Self::Aes256),
+            _ => Err(EncryptionError::UnsupportedFormat(format!("OpenPGP cipher algorithm {}", id))),
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Aes128 => 7,
+            Self::Aes192 => 8,
+            Self::Aes256 => 9,
+        }
+    }
+
+    pub fn key_len(self) -> usize {
+        match self {
+            Self::Aes128 => 16,
+            Self::Aes192 => 24,
+            Self::Aes256 => 32,
+        }
+    }
+}
+
+/// OpenPGP CFB decryption with a zero IV and without resynchronization
+pub enum CfbDecryptor {
+    Aes128(BufDecryptor<Aes128>),
+    Aes192(BufDecryptor<Aes192>),
+    Aes256(BufDecryptor<Aes256>),
+}
+
+impl CfbDecryptor {
+    pub fn new(algorithm: SymmetricAlgorithm, key: &[u8]) -> Result<Self, EncryptionError> {
+        let iv = [0u8; BLOCK_SIZE];
+        let cipher = match algorithm {
+            SymmetricAlgorithm::Aes128 => BufDecryptor::new_from_slices(key, &iv).map(Self::Aes128),
+            SymmetricAlgorithm::Aes192 => BufDecryptor::new_from_slices(key, &iv).map(Self::Aes192),
+            SymmetricAlgorithm::Aes256 => BufDecryptor::new_from_slices(key, &iv).map(Self::Aes256),
+        };
+        cipher.map_err(|_| EncryptionError::SecretKeyCreation)
+    }
+
+    /// Decrypts `data` in place, continuing where the previous call stopped
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        match self {
+            Self::Aes128(cipher) => cipher.decrypt(data),
+            Self::Aes192(cipher) => cipher.decrypt(data),
+            Self::Aes256(cipher) => cipher.decrypt(data),
+        }
+    }
+}
+
+/// OpenPGP CFB encryption with AES-256, a zero IV and without resynchronization
+pub struct CfbEncryptor(BufEncryptor<Aes256>);
+
+impl CfbEncryptor {
+    pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+        BufEncryptor::new_from_slices(key, &[0u8; BLOCK_SIZE])
+            .map(Self)
+            .map_err(|_| EncryptionError::SecretKeyCreation)
+    }
+
+    /// Encrypts `data` in place, continuing where the previous call stopped
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        self.0.encrypt(data)
+    }
+}
+
+/// Hash algorithms usable in a string-to-key specifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum S2kHash {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha224,
+}
+
+impl S2kHash {
+    fn from_id(id: u8) -> Result<Self, EncryptionError> {
+        match id {
+            2 => Ok(Self::Sha1),
+            8 => Ok(Self::Sha256),
+            9 => Ok(Self::Sha384),
+            10 => Ok(Self::Sha512),
+            11 => Ok(Self::Sha224),
+            _ => Err(EncryptionError::UnsupportedFormat(format!("OpenPGP hash algorithm {}", id))),
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Self::Sha1 => 2,
+            Self::Sha256 => 8,
+            Self::Sha384 => 9,
+            Self::Sha512 => 10,
+            Self::Sha224 => 11,
+        }
+    }
+}
+
+/// String-to-key specifier: how a passphrase becomes a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S2k {
+    hash: S2kHash,
+    salt: Option<[u8; SALT_LEN]>,
+    /// Coded number of bytes to hash, for iterated and salted S2K
+    count: Option<u8>,
+}
+
+impl S2k {
+    /// Iterated and salted SHA-256 with a random salt, used for new files
+    pub fn iterated() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            hash: S2kHash::Sha256,
+            salt: Some(salt),
+            count: Some(ITERATED_COUNT),
+        }
+    }
+
+    /// Parses a specifier from the start of `data`
+    ///
+    /// # Returns
+    /// The specifier and the number of bytes it takes up
+    pub fn parse(data: &[u8]) -> Result<(Self, usize), EncryptionError> {
+        let truncated = || EncryptionError::InvalidFormat("Truncated OpenPGP string-to-key specifier".to_string());
+        let (&kind, rest) = data.split_first().ok_or_else(truncated)?;
+        let hash = S2kHash::from_id(*rest.first().ok_or_else(truncated)?)?;
+        let salt = |rest: &[u8]| -> Result<[u8; SALT_LEN], EncryptionError> {
+            rest.get(1..1 + SALT_LEN)
+                .and_then(|salt| salt.try_into().ok())
+                .ok_or_else(truncated)
+        };
+
+        match kind {
+            S2K_SIMPLE => Ok((Self { hash, salt: None, count: None }, 2)),
+            S2K_SALTED => Ok((Self { hash, salt: Some(salt(rest)?), count: None }, 2 + SALT_LEN)),
+            S2K_ITERATED => {
+                let count = *rest.get(1 + SALT_LEN).ok_or_else(truncated)?;
+                Ok((Self { hash, salt: Some(salt(rest)?), count: Some(count) }, 3 + SALT_LEN))
+            }
+            _ => Err(EncryptionError::UnsupportedFormat(format!("OpenPGP string-to-key type {}", kind))),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let kind = match (self.salt, self.count) {
+            (None, _) => S2K_SIMPLE,
+            (Some(_), None) => S2K_SALTED,
+            (Some(_), Some(_)) => S2K_ITERATED,
+        };
+        let mut bytes = vec![kind, self.hash.id()];
+        if let Some(salt) = self.salt {
+            bytes.extend_from_slice(&salt);
+        }
+        bytes.extend(self.count);
+        bytes
+    }
+
+    /// Derives a key of `key_len` bytes from `password`
+    pub fn derive_key(&self, password: &[u8], key_len: usize) -> Zeroizing<Vec<u8>> {
+        let mut data = Zeroizing::new(self.salt.map(|salt| salt.to_vec()).unwrap_or_default());
+        data.extend_from_slice(password);
+        // The coded count is the number of bytes hashed, but at least salt and password once
+        let count = self
+            .count
+            .map_or(0, |c| (16 + ((c as usize) & 15)) << (((c as usize) >> 4) + 6))
+            .max(data.len());
+
+        match self.hash {
+            S2kHash::Sha1 => hash_iterated::<Sha1>(&data, count, key_len),
+            S2kHash::Sha224 => hash_iterated::<Sha224>(&data, count, key_len),
+            S2kHash::Sha256 => hash_iterated::<Sha256>(&data, count, key_len),
+            S2kHash::Sha384 => hash_iterated::<Sha384>(&data, count, key_len),
+            S2kHash::Sha512 => hash_iterated::<Sha512>(&data, count, key_len),
+        }
+    }
+}
+
+/// Hashes `count` bytes of repeated `data`, with one more zero byte preloaded per
+/// hash context until the digests cover `key_len` bytes
+fn hash_iterated<D: Digest>(data: &[u8], count: usize, key_len: usize) -> Zeroizing<Vec<u8>> {
+    // Whole copies of `data`, so every block starts at the start of `data`
+    let block = Zeroizing::new(data.repeat((64 * 1024 / data.len().max(1)).max(1)));
+    let mut key = Zeroizing::new(Vec::with_capacity(key_len));
+    let mut preload = 0;
+    while key.len() < key_len {
+        let mut hasher = D::new();
+        hasher.update(vec![0u8; preload]);
+        let mut remaining = count;
+        while remaining > 0 && !block.is_empty() {
+            let n = remaining.min(block.len());
+            hasher.update(&block[..n]);
+            remaining -= n;
+        }
+        key.extend_from_slice(&hasher.finalize());
+        preload += 1;
+    }
+    key.truncate(key_len);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::PASSWORD;
+
+    const SALT: [u8; SALT_LEN] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn specifier(kind: u8, hash: u8, count: Option<u8>) -> Vec<u8> {
+        let mut bytes = vec![kind, hash];
+        if kind != S2K_SIMPLE {
+            bytes.extend_from_slice(&SALT);
+        }
+        bytes.extend(count);
+        bytes
+    }
+
+    fn derive(bytes: &[u8], key_len: usize) -> Vec<u8> {
+        S2k::parse(bytes).unwrap().0.derive_key(PASSWORD.as_bytes(), key_len).to_vec()
+    }
+
+    #[test]
+    fn parses_and_writes_specifiers() {
+        for bytes in [
+            specifier(S2K_SIMPLE, 2, None),
+            specifier(S2K_SALTED, 8, None),
+            specifier(S2K_ITERATED, 10, Some(0x60)),
+        ] {
+            let mut data = bytes.clone();
+            // Whatever follows the specifier isn't part of it
+            data.extend_from_slice(&[0xAA; 4]);
+            let (s2k, len) = S2k::parse(&data).unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(s2k.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_specifiers() {
+        let invalid = [
+            vec![],
+            vec![S2K_SALTED],
+            vec![S2K_SALTED, 8, 1, 2, 3],
+            specifier(S2K_ITERATED, 8, None),
+        ];
+        for bytes in invalid {
+            assert!(matches!(S2k::parse(&bytes), Err(EncryptionError::InvalidFormat(_))), "{:?}", bytes);
+        }
+        // GnuPG's private type 101 and MD5
+        for bytes in [specifier(101, 2, None), specifier(S2K_SALTED, 1, None)] {
+            assert!(matches!(S2k::parse(&bytes), Err(EncryptionError::UnsupportedFormat(_))), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn derives_simple_and_salted_keys() {
+        assert_eq!(derive(&specifier(S2K_SIMPLE, 8, None), 32), Sha256::digest(PASSWORD.as_bytes()).to_vec());
+        let salted: Vec<u8> = [&SALT[..], PASSWORD.as_bytes()].concat();
+        assert_eq!(derive(&specifier(S2K_SALTED, 8, None), 16), Sha256::digest(&salted)[..16].to_vec());
+    }
+
+    #[test]
+    fn extends_short_digests_with_preloaded_zeros() {
+        // SHA-1 gives 20 bytes, the rest of an AES-256 key comes from a context preloaded with one zero
+        let mut expected = Sha1::digest(PASSWORD.as_bytes()).to_vec();
+        expected.extend_from_slice(&Sha1::digest([&[0u8][..], PASSWORD.as_bytes()].concat())[..12]);
+        assert_eq!(derive(&specifier(S2K_SIMPLE, 2, None), 32), expected);
+    }
+
+    #[test]
+    fn hashes_the_coded_count_of_bytes() {
+        let salted: Vec<u8> = [&SALT[..], PASSWORD.as_bytes()].concat();
+        // 0x60 codes 16 << 12 = 65536 bytes and 0x00 codes 16 << 6 = 1024 bytes
+        for (count, bytes) in [(0x60, 65536), (0x00, 1024), (0x11, 17 << 7)] {
+            let repeated: Vec<u8> = salted.iter().copied().cycle().take(bytes).collect();
+            let expected = Sha256::digest(&repeated).to_vec();
+            assert_eq!(derive(&specifier(S2K_ITERATED, 8, Some(count)), 32), expected, "{count:#x}");
+        }
+    }
+
+    #[test]
+    fn new_specifiers_are_iterated_with_a_random_salt() {
+        let (first, second) = (S2k::iterated(), S2k::iterated());
+        assert_ne!(first.salt, second.salt);
+        let bytes = first.to_bytes();
+        assert_eq!(bytes[..2], [S2K_ITERATED, 8]);
+        assert_eq!(bytes.last(), Some(&ITERATED_COUNT));
+        assert_eq!(S2k::parse(&bytes).unwrap(), (first, 3 + SALT_LEN));
+    }
+
+    #[test]
+    fn cfb_continues_across_calls() {
+        let key = [7u8; 32];
+        let plaintext: Vec<u8> = (0..100u8).collect();
+        let mut whole = plaintext.clone();
+        CfbEncryptor::new(&key).unwrap().encrypt(&mut whole);
+
+        // Encrypting and decrypting in pieces that don't line up with blocks gives the same result
+        let mut encryptor = CfbEncryptor::new(&key).unwrap();
+        let mut pieces = plaintext.clone();
+        let (first, rest) = pieces.split_at_mut(18);
+        encryptor.encrypt(first);
+        encryptor.encrypt(rest);
+        assert_eq!(pieces, whole);
+
+        let mut decryptor = CfbDecryptor::new(SymmetricAlgorithm::Aes256, &key).unwrap();
+        let (first, rest) = pieces.split_at_mut(5);
+        decryptor.decrypt(first);
+        decryptor.decrypt(rest);
+        assert_eq!(pieces, plaintext);
+    }
+
+    #[test]
+    fn maps_algorithm_ids() {
+        for algorithm in [SymmetricAlgorithm::Aes128, SymmetricAlgorithm::Aes192, SymmetricAlgorithm::Aes256] {
+            assert_eq!(SymmetricAlgorithm::from_id(algorithm.id()).unwrap(), algorithm);
+            assert!(CfbDecryptor::new(algorithm, &vec![0u8; algorithm.key_len()]).is_ok());
+        }
+        // CAST5, which gpg used before AES
+        assert!(matches!(SymmetricAlgorithm::from_id(3), Err(EncryptionError::UnsupportedFormat(_))));
+    }
+}
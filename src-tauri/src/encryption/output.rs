@@ -0,0 +1,569 @@
+use chrono::Local;
+use rand_core::{OsRng, RngCore};
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    error::AppError,
+    types::{ConflictPolicy, OutputOptions, ResponseTextCode},
+};
+
+use super::volume::volume_path;
+
+/// Template used when encrypting without `name_template`
+pub const DEFAULT_ENCRYPTION_TEMPLATE: &str = "{name}.enc";
+/// Template used when encrypting to age without `name_template`
+pub const DEFAULT_AGE_TEMPLATE: &str = "{name}.age";
+/// Templates used when encrypting to OpenPGP without `name_template`, binary and armored
+pub const DEFAULT_PGP_TEMPLATE: &str = "{name}.gpg";
+pub const DEFAULT_PGP_ARMOR_TEMPLATE: &str = "{name}.asc";
+/// Template used when decrypting without `name_template`
+pub const DEFAULT_DECRYPTION_TEMPLATE: &str = "{name}";
+
+/// Extensions appended by encryption and stripped by decryption
+const ENCRYPTED_EXTENSIONS: [&str; 5] = ["enc", "age", "gpg", "pgp", "asc"];
+/// Suffix for decrypted files whose name doesn't end in one of them
+const DECRYPTED_SUFFIX: &str = ".decrypted";
+/// Attempts at finding a free name before giving up
+const MAX_NAME_ATTEMPTS: u32 = 10_000;
+
+/// Where a command should write its output
+#[derive(Debug, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Write to this path, which has been claimed as an empty file
+    Create(PathBuf),
+    /// Write to `temp`, then move it over the existing `destination` with
+    /// [`finish_output`] once complete, so a failed write leaves the old file intact
+    Replace { temp: PathBuf, destination: PathBuf },
+    /// The path exists and the conflict policy says to leave it alone
+    Skip(PathBuf),
+}
+
+/// Derives the plaintext name from an encrypted file name
+///
+/// `input.pdf.enc` -> `input.pdf`, `archive.tar.gz.enc` -> `archive.tar.gz`,
+/// `.bashrc.enc` -> `.bashrc`, `notes.txt.age` -> `notes.txt`, `backup.tar.gpg` ->
+/// `backup.tar`. Names without an `.enc`, `.age`, `.gpg`, `.pgp` or `.asc` extension
+/// get a `.decrypted` suffix instead, so the output never collides with the input.
+pub fn decrypted_name(input_path: &Path) -> Result<OsString, AppError> {
+    let file_name = input_path
+        .file_name()
+        .ok_or_else(|| AppError::path(input_path, "Path has no file name"))?;
+
+    let name_path = Path::new(file_name);
+    match (name_path.extension(), name_path.file_stem()) {
+        (Some(extension), Some(stem)) if ENCRYPTED_EXTENSIONS.iter().any(|e| extension == *e) => {
+            Ok(stem.to_os_string())
+        }
+        _ => {
+            let mut name = file_name.to_os_string();
+            name.push(DECRYPTED_SUFFIX);
+            Ok(name)
+        }
+    }
+}
+
+/// Generates a random file name that reveals nothing about the content
+pub fn random_name() -> OsString {
+    let mut randoms = [0u8; 16];
+    OsRng.fill_bytes(&mut randoms);
+    randoms.iter().map(|b| format!("{:02x}", b)).collect::<String>().into()
+}
+
+/// Expands a naming template for `name`
+///
+/// Placeholders:
+/// - `{name}` - the full file name, e.g. `report.pdf`
+/// - `{stem}` - the name without its last extension, e.g. `report`
+/// - `{ext}` - the last extension, e.g. `pdf`; `.{ext}` collapses when there is none
+/// - `{date}` - the current date as `%Y-%m-%d`
+/// - `{time}` - the current time as `%H%M%S`
+///
+/// Names are substituted as `OsStr`, so non-UTF-8 names survive unchanged.
+/// `/` in a template creates subdirectories below the output directory. Absolute
+/// paths and `..` components are rejected.
+pub fn render_template(template: &str, name: &OsStr) -> Result<PathBuf, AppError> {
+    let invalid = || AppError::InvalidTemplate(template.to_string());
+    let name_path = Path::new(name);
+    let stem = name_path.file_stem().unwrap_or(name);
+    let extension = name_path.extension().unwrap_or_default();
+    let now = Local::now();
+
+    let mut rendered = OsString::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        literal.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(invalid)? + start;
+        let placeholder = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        if placeholder == "ext" && extension.is_empty() && literal.ends_with('.') {
+            literal.pop();
+        }
+        rendered.push(&literal);
+        literal.clear();
+
+        match placeholder {
+            "name" => rendered.push(name),
+            "stem" => rendered.push(stem),
+            "ext" => rendered.push(extension),
+            "date" => rendered.push(now.format("%Y-%m-%d").to_string()),
+            "time" => rendered.push(now.format("%H%M%S").to_string()),
+            _ => return Err(invalid()),
+        }
+    }
+    literal.push_str(rest);
+    rendered.push(&literal);
+
+    let relative = PathBuf::from(rendered);
+    let is_safe = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative.as_os_str().is_empty() || !is_safe {
+        return Err(invalid());
+    }
+    Ok(relative)
+}
+
+/// Creates an empty file at `path` unless one exists
+///
+/// # Returns
+/// `false` if the path is taken
+fn reserve(path: &Path) -> io::Result<bool> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Claims a volume set name if its first volume doesn't exist
+///
+/// Nothing is created here; the volumes themselves are created with `create_new`,
+/// so a concurrent write fails instead of replacing them.
+fn volume_set_free(base: &Path) -> io::Result<bool> {
+    Ok(!volume_path(base, 1).exists())
+}
+
+/// Appends `_1`, `_2`, ... to the file stem until `claim` accepts the path
+///
+/// With [`reserve`] the free path is created empty before returning, so a
+/// concurrent write can't claim the same name in between.
+fn numbered_path(path: &Path, claim: fn(&Path) -> io::Result<bool>) -> io::Result<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default();
+    let name_path = Path::new(file_name);
+    let stem = name_path.file_stem().unwrap_or(file_name);
+
+    for n in 1..=MAX_NAME_ATTEMPTS {
+        let mut candidate = stem.to_os_string();
+        candidate.push(format!("_{}", n));
+        if let Some(extension) = name_path.extension() {
+            candidate.push(".");
+            candidate.push(extension);
+        }
+        let candidate = path.with_file_name(candidate);
+        if claim(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "No free numbered file name"))
+}
+
+/// A hidden temporary name next to `path` that `claim` accepts
+///
+/// It stays in the same directory so the final rename doesn't cross filesystems.
+fn temp_path(path: &Path, claim: fn(&Path) -> io::Result<bool>) -> io::Result<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default();
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let mut candidate = OsString::from(".");
+        candidate.push(file_name);
+        candidate.push(".");
+        candidate.push(random_name());
+        candidate.push(".tmp");
+        let candidate = path.with_file_name(candidate);
+        if claim(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "No free temporary file name"))
+}
+
+/// Creates an empty hidden temporary file next to `path` and returns its name
+pub fn reserve_temp_path(path: &Path) -> io::Result<PathBuf> {
+    temp_path(path, reserve)
+}
+
+/// Moves a completely written output from `path` to `destination`
+///
+/// Does nothing for [`OutputTarget::Create`], where both are the same path.
+pub fn finish_output(path: &Path, destination: &Path) -> io::Result<()> {
+    if path != destination {
+        fs::rename(path, destination)?;
+    }
+    Ok(())
+}
+
+/// Like [`finish_output`] for a volume set of `count` volumes written under the base name `path`
+pub fn finish_volumes(path: &Path, destination: &Path, count: u32) -> io::Result<()> {
+    if path != destination {
+        for index in 1..=count {
+            fs::rename(volume_path(path, index), volume_path(destination, index))?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the output directory and naming template to `name`
+fn render_output_path(
+    input_path: &Path,
+    name: &OsStr,
+    options: &OutputOptions,
+    default_template: &str,
+) -> Result<PathBuf, AppError> {
+    let output_dir = match &options.output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => input_path.parent()
+            .ok_or_else(|| AppError::path(input_path, "Parent directory not found"))?
+            .to_path_buf(),
+    };
+
+    let template = options.name_template.as_deref().unwrap_or(default_template);
+    Ok(output_dir.join(render_template(template, name)?))
+}
+
+/// Creates the parent directories of a new output
+///
+/// Only called once the output will be written, so skipped and failed files
+/// leave no directories behind.
+fn create_parent(output_path: &Path) -> Result<(), AppError> {
+    if let Some(parent_dir) = output_path.parent() {
+        fs::create_dir_all(parent_dir)
+            .map_err(|e| AppError::io(ResponseTextCode::ParentDirectoryRetrieveFailed, e))?;
+    }
+    Ok(())
+}
+
+/// Resolves the output path for `name` according to the output options
+///
+/// # Arguments
+/// * `input_path` - The file being processed; its directory is the default output directory
+/// * `name` - The name the template is applied to
+/// * `options` - Output directory, naming template and conflict policy
+/// * `default_template` - Template used when `options` doesn't set one
+pub fn resolve_output_path(
+    input_path: &Path,
+    name: &OsStr,
+    options: &OutputOptions,
+    default_template: &str,
+) -> Result<OutputTarget, AppError> {
+    let output_path = render_output_path(input_path, name, options, default_template)?;
+
+    // Never truncate the file that is being read
+    let is_input = fs::canonicalize(&output_path).ok() == fs::canonicalize(input_path).ok()
+        && output_path.exists();
+    if is_input {
+        return Err(AppError::OutputExists(output_path));
+    }
+
+    // Claiming the name creates the file, so one that appears after the check
+    // goes through the conflict policy instead of being truncated
+    let create_failed = |e| AppError::io(ResponseTextCode::FileCreationFailed, e);
+    if !output_path.exists() {
+        create_parent(&output_path)?;
+        if reserve(&output_path).map_err(create_failed)? {
+            return Ok(OutputTarget::Create(output_path));
+        }
+    }
+
+    match options.conflict_policy {
+        ConflictPolicy::Overwrite => Ok(OutputTarget::Replace {
+            temp: temp_path(&output_path, reserve).map_err(create_failed)?,
+            destination: output_path,
+        }),
+        ConflictPolicy::Skip => Ok(OutputTarget::Skip(output_path)),
+        ConflictPolicy::Rename => {
+            Ok(OutputTarget::Create(numbered_path(&output_path, reserve).map_err(create_failed)?))
+        }
+        ConflictPolicy::Fail => Err(AppError::OutputExists(output_path)),
+    }
+}
+
+/// Like [`resolve_output_path`] for output split into volumes
+///
+/// The returned paths are base names that the volume numbers are appended to.
+/// Conflicts are checked against the first volume, `<name>.001`, since the base
+/// name itself is never written. [`OutputTarget::Skip`] holds that volume's path.
+pub fn resolve_volume_path(
+    input_path: &Path,
+    name: &OsStr,
+    options: &OutputOptions,
+    default_template: &str,
+) -> Result<OutputTarget, AppError> {
+    let base = render_output_path(input_path, name, options, default_template)?;
+    let first_volume = volume_path(&base, 1);
+
+    if volume_set_free(&base).map_err(|e| AppError::io(ResponseTextCode::FileCreationFailed, e))? {
+        create_parent(&base)?;
+        return Ok(OutputTarget::Create(base));
+    }
+
+    let create_failed = |e| AppError::io(ResponseTextCode::FileCreationFailed, e);
+    match options.conflict_policy {
+        ConflictPolicy::Overwrite => Ok(OutputTarget::Replace {
+            temp: temp_path(&base, volume_set_free).map_err(create_failed)?,
+            destination: base,
+        }),
+        ConflictPolicy::Skip => Ok(OutputTarget::Skip(first_volume)),
+        ConflictPolicy::Rename => Ok(OutputTarget::Create(numbered_path(&base, volume_set_free).map_err(create_failed)?)),
+        ConflictPolicy::Fail => Err(AppError::OutputExists(first_volume)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::test_dir;
+    use proptest::prelude::*;
+
+    fn encrypted_name(name: &OsStr) -> PathBuf {
+        render_template(DEFAULT_ENCRYPTION_TEMPLATE, name).unwrap()
+    }
+
+    #[test]
+    fn strips_only_the_enc_extension() {
+        for name in ["report.pdf", "archive.tar.gz", ".bashrc", "README", "name.", "x.enc", "enc"] {
+            let encrypted = encrypted_name(OsStr::new(name));
+            assert_eq!(decrypted_name(&encrypted).unwrap(), OsStr::new(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn strips_age_and_openpgp_extensions() {
+        for template in [DEFAULT_AGE_TEMPLATE, DEFAULT_PGP_TEMPLATE, DEFAULT_PGP_ARMOR_TEMPLATE] {
+            let encrypted = render_template(template, OsStr::new("notes.txt")).unwrap();
+            assert_eq!(decrypted_name(&encrypted).unwrap(), OsStr::new("notes.txt"), "{template}");
+        }
+    }
+
+    #[test]
+    fn names_without_enc_extension_get_a_suffix() {
+        assert_eq!(decrypted_name(Path::new("notes")).unwrap(), OsStr::new("notes.decrypted"));
+        assert_eq!(decrypted_name(Path::new(".enc")).unwrap(), OsStr::new(".enc.decrypted"));
+        assert_eq!(decrypted_name(Path::new("a.txt")).unwrap(), OsStr::new("a.txt.decrypted"));
+    }
+
+    #[test]
+    fn collapses_missing_extension() {
+        assert_eq!(render_template("{stem}.{ext}.enc", OsStr::new("README")).unwrap(), Path::new("README.enc"));
+        assert_eq!(render_template("{stem}.{ext}.enc", OsStr::new("a.b.c")).unwrap(), Path::new("a.b.c.enc"));
+    }
+
+    #[test]
+    fn rejects_unsafe_templates() {
+        for template in ["../{name}", "/tmp/{name}", "{unknown}", "{name", ""] {
+            assert!(render_template(template, OsStr::new("a.txt")).is_err(), "{template}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        let encrypted = encrypted_name(name);
+        assert_eq!(encrypted.as_os_str().as_bytes(), b"caf\xe9.txt.enc");
+        assert_eq!(decrypted_name(&encrypted).unwrap(), name);
+    }
+
+    fn options(dir: &Path, template: &str, conflict_policy: ConflictPolicy) -> OutputOptions {
+        OutputOptions {
+            output_dir: Some(dir.display().to_string()),
+            name_template: Some(template.to_string()),
+            conflict_policy,
+        }
+    }
+
+    #[test]
+    fn skips_or_fails_existing_outputs_and_creates_new_directories() {
+        let dir = test_dir("no-dirs");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+        fs::create_dir(dir.join("out")).unwrap();
+        fs::write(dir.join("out/a.txt.enc"), b"existing").unwrap();
+
+        // The template's directory is only created for outputs that will be written
+        let skip = options(&dir, "out/{name}.enc", ConflictPolicy::Skip);
+        assert_eq!(
+            resolve_output_path(&input, OsStr::new("a.txt"), &skip, "").unwrap(),
+            OutputTarget::Skip(dir.join("out/a.txt.enc"))
+        );
+        let fail = options(&dir, "out/{name}.enc", ConflictPolicy::Fail);
+        assert!(resolve_output_path(&input, OsStr::new("a.txt"), &fail, "").is_err());
+        let create = options(&dir, "{date}/{name}.enc", ConflictPolicy::Fail);
+        let target = resolve_output_path(&input, OsStr::new("a.txt"), &create, "").unwrap();
+        let OutputTarget::Create(path) = target else { panic!("expected a new file") };
+        assert!(path.parent().unwrap().is_dir());
+    }
+
+    #[test]
+    fn renaming_reserves_a_fresh_name() {
+        let dir = test_dir("rename");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+        fs::write(dir.join("a.enc"), b"existing").unwrap();
+        fs::write(dir.join("a_1.enc"), b"existing").unwrap();
+
+        let rename = options(&dir, "{stem}.enc", ConflictPolicy::Rename);
+        let first = resolve_output_path(&input, OsStr::new("a.txt"), &rename, "").unwrap();
+        let second = resolve_output_path(&input, OsStr::new("a.txt"), &rename, "").unwrap();
+        // Each call claims its name, so two outputs resolved back to back never collide
+        assert_eq!(first, OutputTarget::Create(dir.join("a_2.enc")));
+        assert_eq!(second, OutputTarget::Create(dir.join("a_3.enc")));
+        assert_eq!(fs::read(dir.join("a_1.enc")).unwrap(), b"existing");
+    }
+
+    #[test]
+    fn new_outputs_are_claimed_before_they_are_written() {
+        let dir = test_dir("claim");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+
+        let fail = options(&dir, "{name}.enc", ConflictPolicy::Fail);
+        let first = resolve_output_path(&input, OsStr::new("a.txt"), &fail, "").unwrap();
+        assert_eq!(first, OutputTarget::Create(dir.join("a.txt.enc")));
+        assert_eq!(fs::read(dir.join("a.txt.enc")).unwrap(), b"");
+        // A second output for the same name sees the claim and follows the policy
+        assert!(resolve_output_path(&input, OsStr::new("a.txt"), &fail, "").is_err());
+        let rename = options(&dir, "{name}.enc", ConflictPolicy::Rename);
+        assert_eq!(
+            resolve_output_path(&input, OsStr::new("a.txt"), &rename, "").unwrap(),
+            OutputTarget::Create(dir.join("a.txt_1.enc"))
+        );
+    }
+
+    #[test]
+    fn overwriting_replaces_the_destination_only_when_finished() {
+        let dir = test_dir("overwrite");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+        let existing = dir.join("a.txt.enc");
+        fs::write(&existing, b"existing").unwrap();
+
+        let overwrite = options(&dir, "{name}.enc", ConflictPolicy::Overwrite);
+        let OutputTarget::Replace { temp, destination } =
+            resolve_output_path(&input, OsStr::new("a.txt"), &overwrite, "").unwrap()
+        else {
+            panic!("expected a replacement")
+        };
+        assert_eq!(destination, existing);
+        assert_eq!(temp.parent(), existing.parent());
+
+        // A failed write only loses the temporary file
+        fs::write(&temp, b"partial").unwrap();
+        fs::remove_file(&temp).unwrap();
+        assert_eq!(fs::read(&existing).unwrap(), b"existing");
+
+        let OutputTarget::Replace { temp, destination } =
+            resolve_output_path(&input, OsStr::new("a.txt"), &overwrite, "").unwrap()
+        else {
+            panic!("expected a replacement")
+        };
+        fs::write(&temp, b"new").unwrap();
+        finish_output(&temp, &destination).unwrap();
+        assert_eq!(fs::read(&existing).unwrap(), b"new");
+        assert!(!temp.exists());
+    }
+
+    #[test]
+    fn checks_volume_sets_by_their_first_volume() {
+        let dir = test_dir("volumes");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+        let base = dir.join("a.txt.enc");
+        // The base name is never written, so a file there is no conflict
+        fs::write(&base, b"unrelated").unwrap();
+        let rename = options(&dir, "{name}.enc", ConflictPolicy::Rename);
+        assert_eq!(
+            resolve_volume_path(&input, OsStr::new("a.txt"), &rename, "").unwrap(),
+            OutputTarget::Create(base.clone())
+        );
+
+        fs::write(volume_path(&base, 1), b"volume").unwrap();
+        assert_eq!(
+            resolve_volume_path(&input, OsStr::new("a.txt"), &rename, "").unwrap(),
+            OutputTarget::Create(dir.join("a.txt_1.enc"))
+        );
+        let skip = options(&dir, "{name}.enc", ConflictPolicy::Skip);
+        assert_eq!(
+            resolve_volume_path(&input, OsStr::new("a.txt"), &skip, "").unwrap(),
+            OutputTarget::Skip(volume_path(&base, 1))
+        );
+        let fail = options(&dir, "{name}.enc", ConflictPolicy::Fail);
+        assert!(resolve_volume_path(&input, OsStr::new("a.txt"), &fail, "").is_err());
+
+        let overwrite = options(&dir, "{name}.enc", ConflictPolicy::Overwrite);
+        let OutputTarget::Replace { temp, destination } =
+            resolve_volume_path(&input, OsStr::new("a.txt"), &overwrite, "").unwrap()
+        else {
+            panic!("expected a replacement")
+        };
+        assert_eq!(destination, base);
+        assert!(!volume_path(&temp, 1).exists());
+        fs::write(volume_path(&temp, 1), b"new volume").unwrap();
+        fs::write(volume_path(&temp, 2), b"new volume").unwrap();
+        finish_volumes(&temp, &destination, 2).unwrap();
+        assert_eq!(fs::read(volume_path(&base, 1)).unwrap(), b"new volume");
+        assert!(volume_path(&base, 2).exists() && !volume_path(&temp, 1).exists());
+        assert_eq!(fs::read(&base).unwrap(), b"unrelated");
+    }
+
+    #[test]
+    fn never_writes_over_the_input() {
+        let dir = test_dir("input");
+        let input = dir.join("a.txt");
+        fs::write(&input, b"input").unwrap();
+        let overwrite = options(&dir, "{name}", ConflictPolicy::Overwrite);
+        assert!(matches!(
+            resolve_output_path(&input, OsStr::new("a.txt"), &overwrite, ""),
+            Err(AppError::OutputExists(_))
+        ));
+    }
+
+    /// File names: any mix of dots and name characters, no separators
+    fn file_name() -> impl Strategy<Value = String> {
+        "[.]{0,2}[a-zA-Z0-9 _\\-äöü漢]{1,12}(\\.[a-zA-Z0-9.]{0,6}){0,3}"
+            .prop_filter("not a special directory name", |name| name != "." && name != "..")
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_default_templates(name in file_name()) {
+            let encrypted = encrypted_name(OsStr::new(&name));
+            let decrypted = decrypted_name(&encrypted).unwrap();
+            let restored = render_template(DEFAULT_DECRYPTION_TEMPLATE, &decrypted).unwrap();
+            prop_assert_eq!(restored.as_os_str(), OsStr::new(&name));
+        }
+
+        #[test]
+        fn rendered_paths_stay_relative(name in file_name(), template in "(\\{name\\}|\\{stem\\}|\\.\\{ext\\}|\\{date\\}/|x){1,5}") {
+            if let Ok(path) = render_template(&template, OsStr::new(&name)) {
+                prop_assert!(path.components().all(|c| matches!(c, Component::Normal(_))));
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn round_trips_arbitrary_bytes(bytes in proptest::collection::vec(1u8..=255, 1..24)) {
+            use std::os::unix::ffi::OsStrExt;
+            prop_assume!(!bytes.contains(&b'/') && bytes != b"." && bytes != b"..");
+            let name = OsStr::from_bytes(&bytes);
+            let encrypted = encrypted_name(name);
+            prop_assert_eq!(decrypted_name(&encrypted).unwrap(), name);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::{fs::File, io::Read, path::Path};
+use tauri::{AppHandle, Manager};
+use zeroize::Zeroizing;
+
+use crate::{
+    error::AppError,
+    session::{session_password, SessionState},
+    types::{AppResponse, PreviewKind, PreviewResponse, ResponseTextCode},
+};
+
+use super::{
+    helpers::validate_password,
+    range::{unwrap_io_error, DecryptReader},
+    xchacha20::{check_random_access, create_error_response},
+};
+
+/// Largest plaintext that is decrypted into memory for a preview
+const MAX_PREVIEW_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Detects how decrypted content can be previewed from its leading bytes
+///
+/// # Returns
+/// The preview kind and MIME type, or `None` for unsupported binary content
+fn detect_content(data: &[u8]) -> Option<(PreviewKind, &'static str)> {
+    const SIGNATURES: &[(&[u8], PreviewKind, &str)] = &[
+        (b"%PDF-", PreviewKind::Pdf, "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", PreviewKind::Image, "image/png"),
+        (b"\xff\xd8\xff", PreviewKind::Image, "image/jpeg"),
+        (b"GIF87a", PreviewKind::Image, "image/gif"),
+        (b"GIF89a", PreviewKind::Image, "image/gif"),
+    ];
+
+    if let Some((_, kind, mime)) = SIGNATURES.iter().find(|(magic, _, _)| data.starts_with(magic)) {
+        return Some((*kind, *mime));
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some((PreviewKind::Image, "image/webp"));
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return Some((PreviewKind::Text, "text/plain"));
+    }
+    None
+}
+
+/// Decrypts a file into memory for previewing in the webview
+///
+/// Nothing is written to disk. The plaintext buffer is zeroized once the
+/// response has been built; files above `MAX_PREVIEW_SIZE` are rejected
+/// before any chunk is decrypted.
+#[tauri::command]
+pub async fn preview_file(app: AppHandle, file_path: &str, password: &str) -> Result<PreviewResponse, AppResponse> {
+    let password = session_password(&app, password)?;
+    preview_path(&app.state::<SessionState>(), file_path, &password)
+}
+
+/// Decrypts `file_path` into memory, see [`preview_file`]
+///
+/// # Arguments
+/// * `session` - Supplies cached keys if `password` is the session password
+fn preview_path(session: &SessionState, file_path: &str, password: &str) -> Result<PreviewResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    check_random_access(Path::new(file_path)).map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
+    })?;
+
+    let mut reader = DecryptReader::with_cipher(source_file, |header| session.cipher(header, password))
+    .map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
+
+    if reader.plaintext_len() > MAX_PREVIEW_SIZE {
+        return Err(create_error_response(ResponseTextCode::PreviewTooLarge, Some(file_path.to_string())));
+    }
+
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(reader.plaintext_len() as usize));
+    reader.read_to_end(&mut plaintext).map_err(|e| {
+        AppError::from(unwrap_io_error(e)).into_response(Some(file_path.to_string()))
+    })?;
+
+    let (kind, mime_type) = detect_content(&plaintext).ok_or_else(|| {
+        create_error_response(ResponseTextCode::PreviewUnsupported, Some(file_path.to_string()))
+    })?;
+
+    let data = match kind {
+        PreviewKind::Text => String::from_utf8_lossy(&plaintext).into_owned(),
+        PreviewKind::Image | PreviewKind::Pdf => STANDARD.encode(plaintext.as_slice()),
+    };
+
+    Ok(PreviewResponse {
+        kind,
+        mime_type: mime_type.to_string(),
+        data,
+        size_bytes: plaintext.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            format::{FileHeader, EXT_CONTENT_HASH},
+            helpers::generate_nonce,
+            metadata::FileMetadata,
+            progress::ProgressEmitter,
+            test_util::{test_dir, PASSWORD},
+            xchacha20::encrypt_cipher_file,
+        },
+        types::EncryptOptions,
+    };
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    /// Encrypts `content` into `dir` and removes the original
+    fn encrypted(dir: &Path, name: &str, content: &[u8]) -> PathBuf {
+        let source = dir.join(name);
+        fs::write(&source, content).unwrap();
+        let response = encrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &source.display().to_string(),
+            PASSWORD,
+            None,
+            true,
+            &EncryptOptions::default(),
+        )
+        .unwrap();
+        PathBuf::from(response.file_path.unwrap())
+    }
+
+    fn preview(path: &Path, password: &str) -> Result<PreviewResponse, AppResponse> {
+        preview_path(&SessionState::default(), &path.display().to_string(), password)
+    }
+
+    /// Everything in `dir`, to check that a preview leaves no files behind
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn detects_content_from_magic_bytes() {
+        let webp = b"RIFF\x10\0\0\0WEBPVP8 ";
+        let cases: &[(&[u8], PreviewKind, &str)] = &[
+            (b"%PDF-1.7\n", PreviewKind::Pdf, "application/pdf"),
+            (b"\x89PNG\r\n\x1a\n\0\0", PreviewKind::Image, "image/png"),
+            (b"\xff\xd8\xff\xe0", PreviewKind::Image, "image/jpeg"),
+            (b"GIF87a", PreviewKind::Image, "image/gif"),
+            (b"GIF89a", PreviewKind::Image, "image/gif"),
+            (webp, PreviewKind::Image, "image/webp"),
+            (b"plain text, \xc3\xa9", PreviewKind::Text, "text/plain"),
+            (b"", PreviewKind::Text, "text/plain"),
+        ];
+        for (data, kind, mime) in cases {
+            assert_eq!(detect_content(data), Some((*kind, *mime)), "{:?}", data);
+        }
+        // Binary content without a known signature, including a RIFF file that isn't WebP
+        assert_eq!(detect_content(b"\xff\xfe\x00binary"), None);
+        assert_eq!(detect_content(b"RIFF\xff\0\0\0WAVE"), None);
+    }
+
+    #[test]
+    fn previews_decrypted_content_without_writing_it() {
+        let dir = test_dir("preview");
+        let png = [&b"\x89PNG\r\n\x1a\n"[..], &[0u8; 100]].concat();
+        let image = encrypted(&dir, "image.png", &png);
+        let text = encrypted(&dir, "notes.txt", b"preview me");
+        let before = files(&dir);
+
+        let response = preview(&image, PASSWORD).unwrap();
+        assert_eq!(response.kind, PreviewKind::Image);
+        assert_eq!(response.mime_type, "image/png");
+        assert_eq!(STANDARD.decode(&response.data).unwrap(), png);
+        assert_eq!(response.size_bytes, png.len());
+
+        let response = preview(&text, PASSWORD).unwrap();
+        assert_eq!(response.kind, PreviewKind::Text);
+        assert_eq!(response.data, "preview me");
+        assert_eq!(files(&dir), before);
+    }
+
+    #[test]
+    fn rejects_content_above_the_size_cap() {
+        let dir = test_dir("too-large");
+        // A header claiming more than the cap: the size is checked before any chunk is read
+        let path = dir.join("large.enc");
+        let mut header = FileHeader::new(generate_nonce());
+        header.set_extension(EXT_CONTENT_HASH, Vec::new());
+        let cipher = header.cipher(PASSWORD).unwrap();
+        let metadata = FileMetadata { size: Some(MAX_PREVIEW_SIZE + 1), ..FileMetadata::default() };
+        header.seal_metadata(&cipher, &metadata).unwrap();
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        fs::write(&path, bytes).unwrap();
+
+        let response = preview(&path, PASSWORD).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::PreviewTooLarge));
+        assert_eq!(files(&dir), ["large.enc"]);
+    }
+
+    #[test]
+    fn tells_a_wrong_password_from_corrupted_content() {
+        let dir = test_dir("errors");
+        let path = encrypted(&dir, "notes.txt", b"preview me");
+
+        let response = preview(&path, "Wrong-Password-1!").unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::WrongPassword));
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        fs::write(&path, bytes).unwrap();
+        let response = preview(&path, PASSWORD).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk));
+        assert_eq!(files(&dir), ["notes.txt.enc"]);
+    }
+}
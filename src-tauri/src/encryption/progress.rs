@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use crate::types::ProgressInfo;
+
+use super::xchacha20::sanitize_path;
+
+const MIN_EVENT_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Emits throttled `<kind>_progress_<path>` events for a long-running file operation
+pub struct ProgressEmitter<'a> {
+    /// `None` reports nothing, for running operations outside the app
+    app: Option<&'a AppHandle>,
+    event_name: String,
+    total_bytes: usize,
+    start_time: Instant,
+    last_event: Instant,
+}
+
+impl<'a> ProgressEmitter<'a> {
+    /// `kind` is the event prefix, e.g. `encryption` or `hash`
+    pub fn new(app: &'a AppHandle, kind: &str, file_path: &str, total_bytes: usize) -> Self {
+        Self {
+            app: Some(app),
+            event_name: format!("{}_progress_{}", kind, sanitize_path(file_path)),
+            total_bytes,
+            start_time: Instant::now(),
+            last_event: Instant::now(),
+        }
+    }
+
+    /// An emitter that reports nothing
+    #[cfg(test)]
+    pub fn silent() -> Self {
+        Self {
+            app: None,
+            event_name: String::new(),
+            total_bytes: 0,
+            start_time: Instant::now(),
+            last_event: Instant::now(),
+        }
+    }
+
+    /// Sets the size progress is measured against, for operations that only know it once the input is open
+    pub fn set_total(&mut self, total_bytes: usize) {
+        self.total_bytes = total_bytes;
+    }
+
+    /// Reports `bytes_processed` unless an event was emitted very recently
+    pub fn update(&mut self, bytes_processed: usize) {
+        let Some(app) = self.app else {
+            return;
+        };
+        if self.last_event.elapsed() < MIN_EVENT_INTERVAL {
+            return;
+        }
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let speed = bytes_processed as f64 / (1024.0 * 1024.0) / elapsed;
+        app.emit(&self.event_name, &ProgressInfo {
+            percentage: bytes_processed as f64 / self.total_bytes as f64 * 100.0,
+            bytes_processed,
+            total_bytes: self.total_bytes,
+            speed_mbps: speed,
+            elapsed_seconds: elapsed,
+            estimated_remaining_seconds: if speed > 0.0 {
+                self.total_bytes.saturating_sub(bytes_processed) as f64 / (speed * 1024.0 * 1024.0)
+            } else {
+                0.0
+            },
+        }).ok();
+        self.last_event = Instant::now();
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+use zeroize::Zeroizing;
+
+use super::{
+    fec::{self, FecParams},
+    format::{ChunkCipher, ChunkScheme, FileHeader, EXT_CONTENT_HASH, EXT_ERROR_CORRECTION, EXT_SIGNER},
+    helpers::{read_chunk, EncryptionError, CHUNK_SIZE},
+    metadata::FileMetadata,
+};
+
+/// Seekable reader that decrypts an encrypted stream chunk by chunk
+///
+/// Chunks have a fixed plaintext size, so any plaintext offset maps directly
+/// to one chunk on disk. Only the chunk under the cursor is decrypted and kept
+/// in memory.
+pub struct DecryptReader<R: Read + Seek> {
+    inner: R,
+    cipher: ChunkCipher,
+    metadata: FileMetadata,
+    fec: Option<FecParams>,
+    data_start: u64,
+    plaintext_len: u64,
+    position: u64,
+    chunk_index: Option<u64>,
+    chunk: Zeroizing<Vec<u8>>,
+}
+
+impl<R: Read + Seek> DecryptReader<R> {
+    /// Reads the header from `inner` and derives the key from `password`
+    /// 
+    /// For files with a header the metadata block is decrypted here, so a wrong
+    /// password is reported before any chunk is read.
+    pub fn new(inner: R, password: &str) -> Result<Self, EncryptionError> {
+        Self::with_cipher(inner, |header| header.cipher(password))
+    }
+
+    /// Like [`DecryptReader::new`], but lets the caller supply the cipher, e.g. from a key cache
+    pub fn with_cipher<F>(mut inner: R, cipher_for: F) -> Result<Self, EncryptionError>
+    where
+        F: FnOnce(&FileHeader) -> Result<ChunkCipher, EncryptionError>,
+    {
+        inner.seek(SeekFrom::Start(0))?;
+        let header = FileHeader::read(&mut inner)?;
+        let cipher = cipher_for(&header)?;
+        let metadata = header.open_metadata(&cipher)?;
+        let fec = header.extension(EXT_ERROR_CORRECTION).map(FecParams::from_extension).transpose()?;
+
+        let data_start = header.encoded_len();
+        let total_len = inner.seek(SeekFrom::End(0))?;
+        // Parity and signatures follow the chunks, so the size comes from the metadata
+        let has_trailer = fec.is_some()
+            || header.extension(EXT_SIGNER).is_some()
+            || header.extension(EXT_CONTENT_HASH).is_some();
+        let plaintext_len = match (has_trailer, metadata.size) {
+            (true, Some(size)) => size,
+            (false, Some(size)) => {
+                // Whole chunks cut off the end still leave a well-formed chunk section
+                let len = plaintext_len(total_len.saturating_sub(data_start), cipher.scheme())?;
+                if len < size {
+                    return Err(EncryptionError::TruncatedFile);
+                }
+                if len > size {
+                    return Err(EncryptionError::InvalidFormat(format!("Expected {} bytes but found {}", size, len)));
+                }
+                len
+            }
+            (_, None) => plaintext_len(total_len.saturating_sub(data_start), cipher.scheme())?,
+        };
+
+        Ok(Self {
+            inner,
+            cipher,
+            metadata,
+            fec,
+            data_start,
+            plaintext_len,
+            position: 0,
+            chunk_index: None,
+            chunk: Zeroizing::new(Vec::new()),
+        })
+    }
+
+    /// Total size of the decrypted content in bytes
+    pub fn plaintext_len(&self) -> u64 {
+        self.plaintext_len
+    }
+
+    /// Metadata stored in the file header (empty for legacy files)
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.metadata
+    }
+
+    /// Decrypts chunk `index` into the internal buffer unless it is already loaded
+    fn load_chunk(&mut self, index: u64) -> Result<(), EncryptionError> {
+        if self.chunk_index == Some(index) {
+            return Ok(());
+        }
+
+        let chunk_size = self.cipher.scheme().encrypted_chunk_size();
+        self.inner
+            .seek(SeekFrom::Start(self.data_start + index * chunk_size as u64))?;
+        // Stop at the end of the chunk section, parity may follow
+        let data_len = fec::data_section_len(self.plaintext_len, self.cipher.scheme());
+        let len = data_len.saturating_sub(index * chunk_size as u64).min(chunk_size as u64);
+        let mut buffer = vec![0u8; len as usize];
+        let n = read_chunk(&mut self.inner, &mut buffer)?;
+
+        let chunk = match (self.cipher.open_chunk(index, &buffer[..n]), self.fec) {
+            (Ok(chunk), _) => chunk,
+            (Err(_), Some(params)) => fec::repair_chunk(
+                &mut self.inner,
+                params,
+                &self.cipher,
+                self.data_start,
+                self.plaintext_len,
+                index,
+            )?,
+            (Err(e), None) => return Err(e),
+        };
+        self.chunk = Zeroizing::new(chunk);
+        self.chunk_index = Some(index);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.plaintext_len {
+            return Ok(0);
+        }
+
+        let index = self.position / CHUNK_SIZE as u64;
+        self.load_chunk(index)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let offset = (self.position % CHUNK_SIZE as u64) as usize;
+        let available = &self.chunk[offset..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.plaintext_len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match target {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// Computes the plaintext size from the size of the chunk section
+fn plaintext_len(encrypted_len: u64, scheme: ChunkScheme) -> Result<u64, EncryptionError> {
+    let chunk_size = scheme.encrypted_chunk_size() as u64;
+    let overhead = scheme.overhead() as u64;
+    let full_chunks = encrypted_len / chunk_size;
+    let remainder = encrypted_len % chunk_size;
+
+    if remainder == 0 {
+        return Ok(full_chunks * CHUNK_SIZE as u64);
+    }
+    if remainder <= overhead {
+        return Err(EncryptionError::TruncatedFile);
+    }
+    Ok(full_chunks * CHUNK_SIZE as u64 + remainder - overhead)
+}
+
+/// Decrypts `len` bytes starting at plaintext `offset` without processing the whole file
+///
+/// `cipher_for` derives the key from the header, see [`DecryptReader::with_cipher`].
+///
+/// # Returns
+/// The decrypted bytes, shorter than `len` if the range extends past the end of the file
+pub fn decrypt_range<F>(
+    file_path: &Path,
+    cipher_for: F,
+    offset: u64,
+    len: usize,
+) -> Result<Vec<u8>, EncryptionError>
+where
+    F: FnOnce(&FileHeader) -> Result<ChunkCipher, EncryptionError>,
+{
+    let mut reader = DecryptReader::with_cipher(File::open(file_path)?, cipher_for)?;
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut output = Vec::with_capacity(len.min(reader.plaintext_len().saturating_sub(offset) as usize));
+    reader.take(len as u64).read_to_end(&mut output).map_err(unwrap_io_error)?;
+    Ok(output)
+}
+
+/// Recovers the [`EncryptionError`] that [`DecryptReader`] wrapped into an `io::Error`
+pub(crate) fn unwrap_io_error(e: io::Error) -> EncryptionError {
+    let is_decryption_error = e
+        .get_ref()
+        .is_some_and(|inner| inner.is::<EncryptionError>());
+    if !is_decryption_error {
+        return EncryptionError::Io(e);
+    }
+    e.into_inner()
+        .and_then(|inner| inner.downcast::<EncryptionError>().ok())
+        .map(|inner| *inner)
+        .unwrap_or(EncryptionError::Decryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::armor::dearmor;
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    /// Password of every file in the golden corpus
+    const GOLDEN_PASSWORD: &str = "Golden-Test-1!";
+
+    /// Files written by earlier versions that every later version must decrypt
+    fn golden(name: &str) -> Vec<u8> {
+        let path: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/testdata/golden").join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn decrypt_all(encrypted: Vec<u8>, password: &str) -> Result<(Vec<u8>, FileMetadata), EncryptionError> {
+        let mut reader = DecryptReader::new(Cursor::new(encrypted), password)?;
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).map_err(unwrap_io_error)?;
+        Ok((plaintext, reader.metadata().clone()))
+    }
+
+    #[test]
+    fn decrypts_golden_legacy_file() {
+        let (plaintext, metadata) = decrypt_all(golden("legacy.enc"), GOLDEN_PASSWORD).unwrap();
+        assert_eq!(plaintext, b"cipher legacy golden file\n");
+        assert!(metadata.name.is_none() && metadata.size.is_none());
+    }
+
+    #[test]
+    fn decrypts_golden_v2_files() {
+        let (plaintext, metadata) = decrypt_all(golden("v2.enc"), GOLDEN_PASSWORD).unwrap();
+        assert_eq!(plaintext, b"cipher v2 golden file\n");
+        assert_eq!(metadata.name.as_deref(), Some("golden.txt"));
+        assert_eq!(metadata.size, Some(plaintext.len() as u64));
+        assert_eq!(metadata.modified, Some(1_700_000_000));
+        assert_eq!(metadata.mode, Some(0o644));
+
+        let (plaintext, metadata) = decrypt_all(golden("v2-empty.enc"), GOLDEN_PASSWORD).unwrap();
+        assert!(plaintext.is_empty());
+        assert_eq!(metadata.name.as_deref(), Some("empty.txt"));
+        assert_eq!(metadata.size, Some(0));
+    }
+
+    #[test]
+    fn decrypts_golden_file_with_a_key_salt() {
+        let (plaintext, metadata) = decrypt_all(golden("v2-key-salt.enc"), GOLDEN_PASSWORD).unwrap();
+        assert_eq!(plaintext, b"cipher key salt golden file\n");
+        assert_eq!(metadata.name.as_deref(), Some("salted.txt"));
+    }
+
+    #[test]
+    fn decrypts_golden_armored_file() {
+        let armored = String::from_utf8(golden("v2-armored.enc")).unwrap();
+        let (plaintext, metadata) = decrypt_all(dearmor(&armored).unwrap(), GOLDEN_PASSWORD).unwrap();
+        assert_eq!(plaintext, b"cipher armored golden file\n");
+        assert_eq!(metadata.name.as_deref(), Some("armored.txt"));
+    }
+
+    #[test]
+    fn golden_files_reject_wrong_password() {
+        for name in ["legacy.enc", "v2.enc", "v2-empty.enc", "v2-key-salt.enc"] {
+            let result = decrypt_all(golden(name), "Golden-Test-2!");
+            assert!(matches!(result, Err(EncryptionError::WrongPassword)), "{name}: {:?}", result.map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn reads_golden_ranges() {
+        let mut reader = DecryptReader::new(Cursor::new(golden("v2.enc")), GOLDEN_PASSWORD).unwrap();
+        assert_eq!(reader.plaintext_len(), b"cipher v2 golden file\n".len() as u64);
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        let mut range = [0u8; 9];
+        reader.read_exact(&mut range).unwrap();
+        assert_eq!(&range, b"v2 golden");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::types::{DamagedRange, RecoveryMode, RecoveryReport};
+
+/// Collects damaged regions while decrypting in recovery mode
+///
+/// Offsets always refer to the original plaintext. With [`RecoveryMode::ZeroFill`]
+/// they are also offsets in the output; with [`RecoveryMode::Skip`] the data after
+/// a damaged region moves forward by the length of that region.
+pub struct RecoveryTracker {
+    mode: RecoveryMode,
+    damaged_ranges: Vec<DamagedRange>,
+    recovered_bytes: u64,
+}
+
+impl RecoveryTracker {
+    pub fn new(mode: RecoveryMode) -> Self {
+        Self {
+            mode,
+            damaged_ranges: Vec::new(),
+            recovered_bytes: 0,
+        }
+    }
+
+    /// Records an intact chunk of `length` plaintext bytes
+    pub fn recovered(&mut self, length: u64) {
+        self.recovered_bytes += length;
+    }
+
+    /// Records chunk `index` at plaintext `offset` as damaged
+    ///
+    /// # Returns
+    /// The bytes to write in place of the chunk: zeros or nothing, depending on the mode
+    pub fn damaged_chunk(&mut self, index: u64, offset: u64, length: u64) -> Vec<u8> {
+        self.damaged_ranges.push(DamagedRange {
+            chunk_index: Some(index),
+            offset,
+            length,
+        });
+        match self.mode {
+            RecoveryMode::ZeroFill => vec![0u8; length as usize],
+            RecoveryMode::Skip => Vec::new(),
+        }
+    }
+
+    /// Records data missing at the end, e.g. chunks cut off at a chunk boundary
+    pub fn missing_tail(&mut self, offset: u64, expected_size: u64) {
+        if expected_size > offset {
+            self.damaged_ranges.push(DamagedRange {
+                chunk_index: None,
+                offset,
+                length: expected_size - offset,
+            });
+        }
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        !self.damaged_ranges.is_empty()
+    }
+
+    /// True if at least one chunk decrypted, i.e. the key was right
+    pub fn has_recovered_data(&self) -> bool {
+        self.recovered_bytes > 0
+    }
+
+    pub fn into_report(self) -> RecoveryReport {
+        RecoveryReport {
+            mode: self.mode,
+            lost_bytes: self.damaged_ranges.iter().map(|range| range.length).sum(),
+            damaged_ranges: self.damaged_ranges,
+            recovered_bytes: self.recovered_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            format::{ChunkScheme, FileHeader},
+            helpers::CHUNK_SIZE,
+            progress::ProgressEmitter,
+            test_util::{test_dir, PASSWORD},
+            xchacha20::{decrypt_cipher_file, encrypt_cipher_file},
+        },
+        session::SessionState,
+        types::{AppResponse, DecryptOptions, EncryptOptions, ResponseTextCode},
+    };
+    use std::{
+        fs::{self, File},
+        path::{Path, PathBuf},
+    };
+
+    /// Three chunks, the last one partial
+    fn content() -> Vec<u8> {
+        (0..2 * CHUNK_SIZE + 1000).map(|i| (i.wrapping_mul(7) ^ (i >> 9)) as u8).collect()
+    }
+
+    /// Encrypts `content()` into `dir` and removes the original
+    ///
+    /// # Returns
+    /// The encrypted file and the offset of its first chunk
+    fn encrypted(dir: &Path) -> (PathBuf, u64) {
+        let source = dir.join("data.bin");
+        fs::write(&source, content()).unwrap();
+        let response = encrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &source.display().to_string(),
+            PASSWORD,
+            None,
+            true,
+            &EncryptOptions::default(),
+        )
+        .unwrap();
+        let path = PathBuf::from(response.file_path.unwrap());
+        let data_start = FileHeader::read(&mut File::open(&path).unwrap()).unwrap().encoded_len();
+        (path, data_start)
+    }
+
+    fn decrypt(path: &Path, mode: RecoveryMode) -> AppResponse {
+        let options = DecryptOptions { recovery: Some(mode), ..DecryptOptions::default() };
+        decrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &path.display().to_string(),
+            PASSWORD,
+            false,
+            &options,
+        )
+        .unwrap()
+    }
+
+    /// Damages the second chunk in place
+    fn damage_second_chunk(path: &Path, data_start: u64) {
+        let mut bytes = fs::read(path).unwrap();
+        let position = data_start as usize + ChunkScheme::V2.encrypted_chunk_size() + 10;
+        bytes[position] ^= 0x01;
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn zero_fills_a_damaged_chunk_in_place() {
+        let dir = test_dir("zero-fill");
+        let (path, data_start) = encrypted(&dir);
+        damage_second_chunk(&path, data_start);
+
+        let response = decrypt(&path, RecoveryMode::ZeroFill);
+        assert!(matches!(response.text_code, ResponseTextCode::PartiallyRecovered));
+        let report = response.recovery.unwrap();
+        assert_eq!(report.damaged_ranges, [DamagedRange {
+            chunk_index: Some(1),
+            offset: CHUNK_SIZE as u64,
+            length: CHUNK_SIZE as u64,
+        }]);
+        assert_eq!(report.lost_bytes, CHUNK_SIZE as u64);
+        assert_eq!(report.recovered_bytes, CHUNK_SIZE as u64 + 1000);
+
+        // Everything but the damaged chunk stays at its original offset
+        let output = fs::read(dir.join("data.bin")).unwrap();
+        let expected = content();
+        assert_eq!(output.len(), expected.len());
+        assert_eq!(output[..CHUNK_SIZE], expected[..CHUNK_SIZE]);
+        assert!(output[CHUNK_SIZE..2 * CHUNK_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(output[2 * CHUNK_SIZE..], expected[2 * CHUNK_SIZE..]);
+    }
+
+    #[test]
+    fn skips_a_damaged_chunk() {
+        let dir = test_dir("skip");
+        let (path, data_start) = encrypted(&dir);
+        damage_second_chunk(&path, data_start);
+
+        let response = decrypt(&path, RecoveryMode::Skip);
+        assert!(matches!(response.text_code, ResponseTextCode::PartiallyRecovered));
+        let report = response.recovery.unwrap();
+        // Offsets still refer to the original plaintext
+        assert_eq!(report.damaged_ranges, [DamagedRange {
+            chunk_index: Some(1),
+            offset: CHUNK_SIZE as u64,
+            length: CHUNK_SIZE as u64,
+        }]);
+        assert_eq!(report.lost_bytes, CHUNK_SIZE as u64);
+
+        // The last chunk moves forward into the gap
+        let output = fs::read(dir.join("data.bin")).unwrap();
+        let expected = content();
+        assert_eq!(output.len(), CHUNK_SIZE + 1000);
+        assert_eq!(output[..CHUNK_SIZE], expected[..CHUNK_SIZE]);
+        assert_eq!(output[CHUNK_SIZE..], expected[2 * CHUNK_SIZE..]);
+    }
+
+    #[test]
+    fn reports_chunks_missing_at_the_end() {
+        let dir = test_dir("missing-tail");
+        let (path, data_start) = encrypted(&dir);
+        // Cut at a chunk boundary, so only the stored size shows the last chunk is gone
+        let len = data_start + 2 * ChunkScheme::V2.encrypted_chunk_size() as u64;
+        File::options().write(true).open(&path).unwrap().set_len(len).unwrap();
+
+        for mode in [RecoveryMode::ZeroFill, RecoveryMode::Skip] {
+            let response = decrypt(&path, mode);
+            assert!(matches!(response.text_code, ResponseTextCode::PartiallyRecovered));
+            let report = response.recovery.unwrap();
+            assert_eq!(report.damaged_ranges, [DamagedRange {
+                chunk_index: None,
+                offset: 2 * CHUNK_SIZE as u64,
+                length: 1000,
+            }]);
+            assert_eq!(report.recovered_bytes, 2 * CHUNK_SIZE as u64);
+
+            // Missing data is never made up, not even with zeros
+            let output = fs::read(dir.join("data.bin")).unwrap();
+            assert_eq!(output, content()[..2 * CHUNK_SIZE]);
+            fs::remove_file(dir.join("data.bin")).unwrap();
+        }
+    }
+}
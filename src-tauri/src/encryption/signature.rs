@@ -0,0 +1,354 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::Local;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use orion::hazardous::{hash::blake2::blake2b::Blake2b, mac::poly1305::POLY1305_OUTSIZE};
+use rand_core::OsRng;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    error::AppError,
+    log::add_log_internal,
+    types::{AppResponse, LogLevel, ResponseTextCode, SigningKeyResponse, Status},
+};
+
+use super::{
+    format::FileHeader,
+    helpers::{validate_password, EncryptionError},
+    text::{decrypt_bytes, encrypt_bytes},
+    xchacha20::create_error_response,
+};
+
+/// Size of the sealed signature at the end of signed files
+///
+/// Signed files carry the signer's public key in the `EXT_SIGNER` header
+/// extension and end with the signature, sealed like a chunk:
+/// ```text
+/// header | chunks | parity (optional) | content hash (optional) | sealed signature (64 + 16)
+/// ```
+/// The signature is made before encryption, so only holders of the password
+/// can see who signed a file.
+pub const SIGNATURE_BLOCK_LEN: u64 = (SIGNATURE_LENGTH + POLY1305_OUTSIZE) as u64;
+
+/// Separates signed manifests from any other use of the same key
+const SIGNATURE_CONTEXT: &[u8] = b"cipher file signature v1";
+const DIGEST_LEN: usize = 32;
+
+/// Hash of everything a signature covers
+///
+/// The manifest is the encoded header, including the signer's public key and
+/// the sealed metadata, followed by the BLAKE2b-256 hash of every plaintext chunk.
+pub struct Manifest {
+    hasher: Blake2b,
+}
+
+impl Manifest {
+    pub fn new(header: &FileHeader) -> Result<Self, EncryptionError> {
+        let mut header_bytes = Vec::new();
+        header.write(&mut header_bytes)?;
+        let mut manifest = Self {
+            hasher: Blake2b::new(DIGEST_LEN).map_err(|_| EncryptionError::Encryption)?,
+        };
+        manifest.update(SIGNATURE_CONTEXT)?;
+        manifest.update(&header_bytes)?;
+        Ok(manifest)
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<(), EncryptionError> {
+        self.hasher.update(data).map_err(|_| EncryptionError::Encryption)
+    }
+
+    /// Adds the next plaintext chunk
+    pub fn push_chunk(&mut self, chunk: &[u8]) -> Result<(), EncryptionError> {
+        let digest = orion::hash::digest(chunk).map_err(|_| EncryptionError::Encryption)?;
+        self.update(digest.as_ref())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, EncryptionError> {
+        self.hasher
+            .finalize()
+            .map(|digest| digest.as_ref().to_vec())
+            .map_err(|_| EncryptionError::Encryption)
+    }
+}
+
+/// Ed25519 signing key, stored encrypted with a password like any other file
+pub struct SigningIdentity {
+    key: SigningKey,
+}
+
+impl SigningIdentity {
+    pub fn generate() -> Self {
+        Self { key: SigningKey::generate(&mut OsRng) }
+    }
+
+    /// Decrypts the key file at `path`
+    pub fn load(path: &Path, password: &str) -> Result<Self, EncryptionError> {
+        let seed = decrypt_bytes(&fs::read(path)?, password)?;
+        let seed: &[u8; 32] = seed
+            .as_slice()
+            .try_into()
+            .map_err(|_| EncryptionError::InvalidFormat("Malformed signing key".to_string()))?;
+        Ok(Self { key: SigningKey::from_bytes(seed) })
+    }
+
+    /// Writes the key encrypted with `password`
+    pub fn save(&self, path: &Path, password: &str) -> Result<(), EncryptionError> {
+        let seed = Zeroizing::new(self.key.to_bytes());
+        fs::write(path, encrypt_bytes(seed.as_slice(), password)?)?;
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, manifest: Manifest) -> Result<[u8; SIGNATURE_LENGTH], EncryptionError> {
+        Ok(self.key.sign(&manifest.finish()?).to_bytes())
+    }
+}
+
+/// Checks `signature` over `manifest` against the public key from the header
+pub fn verify(public_key: &[u8], manifest: Manifest, signature: &[u8]) -> Result<(), EncryptionError> {
+    let public_key: &[u8; PUBLIC_KEY_LENGTH] = public_key
+        .try_into()
+        .map_err(|_| EncryptionError::InvalidFormat("Malformed signer extension".to_string()))?;
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| EncryptionError::InvalidSignature)?;
+    let signature = Signature::from_slice(signature).map_err(|_| EncryptionError::InvalidSignature)?;
+    key.verify_strict(&manifest.finish()?, &signature)
+        .map_err(|_| EncryptionError::InvalidSignature)
+}
+
+/// Short form of a public key for comparing signers, e.g. `3f2a 9c41 ...`
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let digest = orion::hash::digest(public_key)
+        .map(|digest| digest.as_ref().to_vec())
+        .unwrap_or_default();
+    digest[..digest.len().min(16)]
+        .chunks(2)
+        .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Creates a new signing key, encrypted with `password`, at `key_path`
+///
+/// The key file is never overwritten; the fingerprint in the response is what
+/// recipients see when they decrypt a file signed with this key.
+#[tauri::command]
+pub async fn generate_signing_key(key_path: &str, password: &str) -> Result<SigningKeyResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+    if Path::new(key_path).exists() {
+        return Err(AppError::OutputExists(PathBuf::from(key_path)).into_response(Some(key_path.to_string())));
+    }
+
+    let identity = SigningIdentity::generate();
+    identity
+        .save(Path::new(key_path), password)
+        .map_err(|e| AppError::from(e).into_response(Some(key_path.to_string())))?;
+
+    add_log_internal(LogLevel::Info, ResponseTextCode::SigningKeyCreated, Some(key_path.to_string())).ok();
+    let public_key = identity.public_key();
+    Ok(SigningKeyResponse {
+        status: Status::Success,
+        text_code: ResponseTextCode::SigningKeyCreated,
+        public_key: STANDARD.encode(public_key),
+        fingerprint: fingerprint(&public_key),
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            format::EXT_SIGNER,
+            helpers::{CHUNK_SIZE, NONCE_SIZE},
+            progress::ProgressEmitter,
+            test_util::{test_dir, PASSWORD},
+            xchacha20::{decrypt_cipher_file, encrypt_cipher_file},
+        },
+        session::SessionState,
+        types::{DecryptOptions, EncryptOptions, RecoveryMode, SignatureStatus, SigningOptions},
+    };
+    use std::fs::File;
+
+    const KEY_PASSWORD: &str = "Signing-Key-Test-1!";
+
+    /// Two chunks, so recovery keeps one when the other is damaged
+    fn plaintext() -> Vec<u8> {
+        (0..CHUNK_SIZE + 4096).map(|i| (i.wrapping_mul(17) ^ (i >> 9)) as u8).collect()
+    }
+
+    /// Encrypts `plaintext()` signed with a new key; returns the encrypted file and the key
+    fn signed_file(dir: &Path) -> (PathBuf, SigningIdentity) {
+        let identity = SigningIdentity::generate();
+        let key_path = dir.join("signing.key");
+        identity.save(&key_path, KEY_PASSWORD).unwrap();
+        let source = dir.join("signed.bin");
+        fs::write(&source, plaintext()).unwrap();
+
+        let options = EncryptOptions {
+            signing_key: Some(SigningOptions {
+                key_path: key_path.display().to_string(),
+                password: Zeroizing::new(KEY_PASSWORD.to_string()),
+            }),
+            ..EncryptOptions::default()
+        };
+        let response = encrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &source.display().to_string(),
+            PASSWORD,
+            None,
+            true,
+            &options,
+        )
+        .unwrap();
+        (PathBuf::from(response.file_path.unwrap()), identity)
+    }
+
+    fn decrypt(path: &Path, recovery: Option<RecoveryMode>) -> Result<AppResponse, AppResponse> {
+        let options = DecryptOptions { recovery, ..DecryptOptions::default() };
+        decrypt_cipher_file(
+            &mut ProgressEmitter::silent(),
+            &SessionState::default(),
+            &path.display().to_string(),
+            PASSWORD,
+            false,
+            &options,
+        )
+    }
+
+    fn flip_byte(path: &Path, offset: usize) {
+        let mut bytes = fs::read(path).unwrap();
+        bytes[offset] ^= 0x01;
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn header_len(path: &Path) -> usize {
+        FileHeader::read(&mut File::open(path).unwrap()).unwrap().encoded_len() as usize
+    }
+
+    /// A signed header and its manifest over `chunks`
+    fn manifest(public_key: [u8; PUBLIC_KEY_LENGTH], chunks: &[&[u8]]) -> Manifest {
+        let mut header = FileHeader::new([7u8; NONCE_SIZE]);
+        header.set_extension(EXT_SIGNER, public_key.to_vec());
+        let mut manifest = Manifest::new(&header).unwrap();
+        for chunk in chunks {
+            manifest.push_chunk(chunk).unwrap();
+        }
+        manifest
+    }
+
+    #[test]
+    fn verifies_a_signed_file() {
+        let dir = test_dir("verify");
+        let (encrypted, identity) = signed_file(&dir);
+
+        let response = decrypt(&encrypted, None).unwrap();
+        // The frontend reads the signer details as top-level fields
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["signature"], "verified");
+        assert_eq!(json["signer_fingerprint"], fingerprint(&identity.public_key()));
+
+        let signer = response.signer.unwrap();
+        assert_eq!(signer.signature, SignatureStatus::Verified);
+        assert_eq!(signer.signer_fingerprint, Some(fingerprint(&identity.public_key())));
+        assert_eq!(fs::read(response.file_path.unwrap()).unwrap(), plaintext());
+    }
+
+    #[test]
+    fn manifest_covers_every_chunk() {
+        let identity = SigningIdentity::generate();
+        let signature = identity.sign(manifest(identity.public_key(), &[b"first", b"second"])).unwrap();
+
+        verify(&identity.public_key(), manifest(identity.public_key(), &[b"first", b"second"]), &signature).unwrap();
+        let tampered = manifest(identity.public_key(), &[b"first", b"secohd"]);
+        assert!(matches!(verify(&identity.public_key(), tampered, &signature), Err(EncryptionError::InvalidSignature)));
+        let dropped = manifest(identity.public_key(), &[b"first"]);
+        assert!(matches!(verify(&identity.public_key(), dropped, &signature), Err(EncryptionError::InvalidSignature)));
+    }
+
+    #[test]
+    fn manifest_covers_the_header() {
+        let identity = SigningIdentity::generate();
+        let signature = identity.sign(manifest(identity.public_key(), &[b"chunk"])).unwrap();
+
+        let mut header = FileHeader::new([8u8; NONCE_SIZE]);
+        header.set_extension(EXT_SIGNER, identity.public_key().to_vec());
+        let mut tampered = Manifest::new(&header).unwrap();
+        tampered.push_chunk(b"chunk").unwrap();
+        assert!(matches!(verify(&identity.public_key(), tampered, &signature), Err(EncryptionError::InvalidSignature)));
+    }
+
+    #[test]
+    fn rejects_another_signers_key() {
+        let identity = SigningIdentity::generate();
+        let other = SigningIdentity::generate();
+        let signature = identity.sign(manifest(identity.public_key(), &[b"chunk"])).unwrap();
+
+        // Claiming the file for another key changes the header as well as the key checked
+        let claimed = manifest(other.public_key(), &[b"chunk"]);
+        assert!(matches!(verify(&other.public_key(), claimed, &signature), Err(EncryptionError::InvalidSignature)));
+        let original = manifest(identity.public_key(), &[b"chunk"]);
+        assert!(matches!(verify(&other.public_key(), original, &signature), Err(EncryptionError::InvalidSignature)));
+    }
+
+    #[test]
+    fn rejects_a_tampered_chunk() {
+        let dir = test_dir("chunk");
+        let (encrypted, _) = signed_file(&dir);
+        flip_byte(&encrypted, header_len(&encrypted) + 100);
+
+        let response = decrypt(&encrypted, None).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk));
+        assert!(!dir.join("signed.bin").exists());
+    }
+
+    #[test]
+    fn rejects_a_tampered_header() {
+        let dir = test_dir("header");
+        let (encrypted, identity) = signed_file(&dir);
+        let bytes = fs::read(&encrypted).unwrap();
+        let key_offset = bytes
+            .windows(PUBLIC_KEY_LENGTH)
+            .position(|window| window == identity.public_key())
+            .unwrap();
+        flip_byte(&encrypted, key_offset);
+
+        assert!(matches!(decrypt(&encrypted, None).unwrap_err().status, Status::Error));
+        assert!(!dir.join("signed.bin").exists());
+    }
+
+    #[test]
+    fn rejects_a_stripped_signature() {
+        let dir = test_dir("stripped");
+        let (encrypted, _) = signed_file(&dir);
+        let len = fs::metadata(&encrypted).unwrap().len();
+        File::options().write(true).open(&encrypted).unwrap().set_len(len - SIGNATURE_BLOCK_LEN).unwrap();
+
+        let response = decrypt(&encrypted, None).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::TruncatedFile));
+        assert!(!dir.join("signed.bin").exists());
+    }
+
+    #[test]
+    fn recovered_files_are_reported_unverified() {
+        let dir = test_dir("recovered");
+        let (encrypted, _) = signed_file(&dir);
+        flip_byte(&encrypted, header_len(&encrypted) + 100);
+
+        let response = decrypt(&encrypted, Some(RecoveryMode::ZeroFill)).unwrap();
+        assert!(matches!(response.text_code, ResponseTextCode::PartiallyRecovered));
+        let signer = response.signer.unwrap();
+        assert_eq!(signer.signature, SignatureStatus::Unverified);
+        assert_eq!(signer.signer_fingerprint, None);
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the test modules
+
+use std::{ops::Deref, path::Path};
+
+use tempfile::TempDir;
+
+/// Password the tests encrypt with; it meets the password policy
+pub const PASSWORD: &str = "Cipher-Test-1!";
+
+/// A fresh directory in the system temp directory, removed with its contents when dropped
+pub struct TestDir(TempDir);
+
+/// Creates a [`TestDir`] named after `name`, so leftovers of a crashed run are easy to find
+pub fn test_dir(name: &str) -> TestDir {
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("cipher-{}-", name))
+        .tempdir()
+        .expect("failed to create a test directory");
+    TestDir(dir)
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        self.0.path()
+    }
+}
@@ -0,0 +1,205 @@
+use chrono::Local;
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use zeroize::Zeroizing;
+
+use crate::{
+    log::add_log_internal,
+    types::{AppResponse, LogLevel, ResponseTextCode, Status, TextResponse},
+};
+
+use super::{
+    armor::{armor, dearmor},
+    format::FileHeader,
+    helpers::{generate_nonce, validate_password, EncryptionError, CHUNK_SIZE},
+    metadata::FileMetadata,
+    xchacha20::create_error_response,
+};
+
+/// Encrypts an in-memory buffer into the same header + chunks layout as `encrypt_file`
+pub fn encrypt_bytes(plaintext: &[u8], password: &str) -> Result<Vec<u8>, EncryptionError> {
+    let mut header = FileHeader::new(generate_nonce());
+    let cipher = header.cipher(password)?;
+    header.seal_metadata(&cipher, &FileMetadata::default())?;
+
+    let chunk_count = plaintext.len().div_ceil(CHUNK_SIZE);
+    let mut output = Vec::with_capacity(
+        header.encoded_len() as usize + plaintext.len() + chunk_count * cipher.scheme().overhead(),
+    );
+    header.write(&mut output)?;
+    for (index, chunk) in plaintext.chunks(CHUNK_SIZE).enumerate() {
+        output.extend_from_slice(&cipher.seal_chunk(index as u64, chunk)?);
+    }
+    Ok(output)
+}
+
+/// Decrypts a buffer produced by [`encrypt_bytes`] or read from an encrypted file
+pub fn decrypt_bytes(mut data: &[u8], password: &str) -> Result<Zeroizing<Vec<u8>>, EncryptionError> {
+    let header = FileHeader::read(&mut data)?;
+    let cipher = header.cipher(password)?;
+    header.open_metadata(&cipher)?;
+
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(data.len()));
+    for (index, chunk) in data.chunks(cipher.scheme().encrypted_chunk_size()).enumerate() {
+        plaintext.extend_from_slice(&Zeroizing::new(cipher.open_chunk(index as u64, chunk)?));
+    }
+    Ok(plaintext)
+}
+
+/// Encrypts a text snippet into an armored string
+pub fn encrypt_to_armor(text: &str, password: &str) -> Result<String, EncryptionError> {
+    Ok(armor(&encrypt_bytes(text.as_bytes(), password)?))
+}
+
+/// Decrypts an armored string back into text
+pub fn decrypt_from_armor(armored: &str, password: &str) -> Result<Zeroizing<String>, EncryptionError> {
+    let plaintext = decrypt_bytes(&dearmor(armored)?, password)?;
+    let text = std::str::from_utf8(&plaintext)
+        .map_err(|_| EncryptionError::InvalidFormat("Decrypted data is not valid UTF-8".to_string()))?;
+    Ok(Zeroizing::new(text.to_string()))
+}
+
+/// Maps a text encryption error to the code shown in the UI
+fn error_code(e: &EncryptionError, is_encryption: bool) -> ResponseTextCode {
+    match e {
+        EncryptionError::KeyDerivation | EncryptionError::SecretKeyCreation => ResponseTextCode::KeyGenerationFailed,
+        EncryptionError::InvalidFormat(_) => ResponseTextCode::InvalidArmor,
+        EncryptionError::WrongPassword => ResponseTextCode::WrongPassword,
+        EncryptionError::Decryption | EncryptionError::CorruptedChunk { .. } => ResponseTextCode::CorruptedChunk,
+        EncryptionError::TruncatedFile => ResponseTextCode::TruncatedFile,
+        EncryptionError::UnsupportedFormat(_) => ResponseTextCode::UnsupportedFormat,
+        _ if is_encryption => ResponseTextCode::EncryptionFailed,
+        _ => ResponseTextCode::DecryptionFailed,
+    }
+}
+
+/// Creates a text success response with logging
+fn create_text_response(code: ResponseTextCode, text: Option<String>) -> TextResponse {
+    add_log_internal(LogLevel::Info, code.clone(), None).ok();
+    TextResponse {
+        status: Status::Success,
+        text_code: code,
+        text,
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }
+}
+
+#[tauri::command]
+pub async fn encrypt_text(text: String, password: &str) -> Result<TextResponse, AppResponse> {
+    let text = Zeroizing::new(text);
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let armored = encrypt_to_armor(&text, password)
+        .map_err(|e| create_error_response(error_code(&e, true), None))?;
+
+    Ok(create_text_response(ResponseTextCode::EncryptionSuccessful, Some(armored)))
+}
+
+#[tauri::command]
+pub async fn decrypt_text(text: &str, password: &str) -> Result<TextResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let plaintext = decrypt_from_armor(text, password)
+        .map_err(|e| create_error_response(error_code(&e, false), None))?;
+
+    Ok(create_text_response(ResponseTextCode::DecryptionSuccessful, Some(plaintext.to_string())))
+}
+
+/// Replaces the clipboard content with its armored encryption
+#[tauri::command]
+pub async fn encrypt_clipboard(app: AppHandle, password: &str) -> Result<TextResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let text = Zeroizing::new(app.clipboard().read_text().map_err(|_| {
+        create_error_response(ResponseTextCode::ClipboardReadFailed, None)
+    })?);
+
+    let armored = encrypt_to_armor(&text, password)
+        .map_err(|e| create_error_response(error_code(&e, true), None))?;
+
+    app.clipboard().write_text(armored).map_err(|_| {
+        create_error_response(ResponseTextCode::ClipboardWriteFailed, None)
+    })?;
+
+    Ok(create_text_response(ResponseTextCode::EncryptionSuccessful, None))
+}
+
+/// Decrypts armored clipboard content
+///
+/// The plaintext is only returned to the webview; the clipboard keeps the
+/// armored message so secrets don't linger there.
+#[tauri::command]
+pub async fn decrypt_clipboard(app: AppHandle, password: &str) -> Result<TextResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let armored = app.clipboard().read_text().map_err(|_| {
+        create_error_response(ResponseTextCode::ClipboardReadFailed, None)
+    })?;
+
+    let plaintext = decrypt_from_armor(&armored, password)
+        .map_err(|e| create_error_response(error_code(&e, false), None))?;
+
+    Ok(create_text_response(ResponseTextCode::DecryptionSuccessful, Some(plaintext.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{armor::is_armored, test_util::PASSWORD};
+
+    #[test]
+    fn round_trips_text_through_armor() {
+        for text in ["", "hello", "äöü 漢字 🔐\nwith lines\r\n", "x".repeat(10_000).as_str()] {
+            let armored = encrypt_to_armor(text, PASSWORD).unwrap();
+            assert!(is_armored(armored.as_bytes()));
+            assert_eq!(decrypt_from_armor(&armored, PASSWORD).unwrap().as_str(), text);
+        }
+    }
+
+    #[test]
+    fn round_trips_bytes_across_chunks() {
+        let data: Vec<u8> = (0..CHUNK_SIZE + 10).map(|i| i as u8).collect();
+        let encrypted = encrypt_bytes(&data, PASSWORD).unwrap();
+        assert_eq!(decrypt_bytes(&encrypted, PASSWORD).unwrap().as_slice(), data.as_slice());
+    }
+
+    #[test]
+    fn encrypts_to_a_fresh_message_each_time() {
+        assert_ne!(encrypt_to_armor("same", PASSWORD).unwrap(), encrypt_to_armor("same", PASSWORD).unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let armored = encrypt_to_armor("secret", PASSWORD).unwrap();
+        let result = decrypt_from_armor(&armored, "Wrong-Test-Pass1!");
+        assert!(matches!(result, Err(EncryptionError::WrongPassword)));
+        assert!(matches!(error_code(&result.unwrap_err(), false), ResponseTextCode::WrongPassword));
+    }
+
+    #[test]
+    fn rejects_damaged_messages() {
+        let armored = encrypt_to_armor("secret", PASSWORD).unwrap();
+        assert!(matches!(decrypt_from_armor("not armored", PASSWORD), Err(EncryptionError::InvalidFormat(_))));
+
+        // Re-armoring tampered ciphertext gets past the checksum, the AEAD tag catches it
+        let mut encrypted = dearmor(&armored).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 0x01;
+        let result = decrypt_from_armor(&armor(&encrypted), PASSWORD);
+        assert!(matches!(result, Err(EncryptionError::CorruptedChunk { index: 0 })), "{:?}", result.map(|_| ()));
+    }
+
+    #[test]
+    fn rejects_non_utf8_plaintext() {
+        let armored = armor(&encrypt_bytes(&[0xff, 0xfe, 0x00], PASSWORD).unwrap());
+        assert!(matches!(decrypt_from_armor(&armored, PASSWORD), Err(EncryptionError::InvalidFormat(_))));
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    error::AppError,
+    keystore::KeyStore,
+    session::{session_password, SessionState},
+    types::{AppResponse, ProcessingStats, ResponseTextCode},
+};
+
+use super::{
+    format::{ChunkCipher, ChunkScheme, FileHeader, EXT_KEY_ID},
+    helpers::{generate_nonce, read_chunk, sync_parent_dir, validate_password, EncryptionError, CHUNK_SIZE},
+    metadata::FileMetadata,
+    output::reserve_temp_path,
+    progress::ProgressEmitter,
+    range::{unwrap_io_error, DecryptReader},
+    xchacha20::{create_error_response, create_info_response, create_success_response, detect_format, DetectedFormat},
+};
+
+/// Legacy files were always named `<name>.enc`, so folders are only searched for these
+const LEGACY_EXTENSION: &str = "enc";
+
+/// Lists the `.enc` files in `dir`, descending into subfolders if `recursive` is set
+///
+/// Symbolic links are not followed, so a link can't pull files from elsewhere into the batch.
+fn legacy_candidates(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() && recursive {
+                pending.push(path);
+            } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == LEGACY_EXTENSION) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Decrypts every chunk of `reader` and writes it re-encrypted to `temp_path`
+fn write_upgraded<R: Read + Seek>(
+    progress: &mut ProgressEmitter,
+    reader: &mut DecryptReader<R>,
+    header: &FileHeader,
+    cipher: &ChunkCipher,
+    temp_path: &Path,
+) -> Result<(), EncryptionError> {
+    // Reserved empty by `reserve_temp_path`, so no other file is ever written to
+    let mut writer = BufWriter::new(OpenOptions::new().write(true).open(temp_path)?);
+    header.write(&mut writer)?;
+
+    progress.set_total(reader.plaintext_len() as usize);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut chunk_index = 0;
+    let mut bytes_processed = 0;
+    loop {
+        let n = read_chunk(reader, &mut buffer).map_err(unwrap_io_error)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&cipher.seal_chunk(chunk_index, &buffer[..n])?)?;
+        chunk_index += 1;
+        bytes_processed += n;
+        progress.update(bytes_processed);
+    }
+
+    // The original is only replaced by a copy that is fully on disk
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Opens a legacy file and checks that its first chunk decrypts
+///
+/// Legacy files have no magic bytes, so the only thing that identifies one is
+/// its layout: a nonce followed by whole chunks and a last chunk longer than
+/// its tag. A file that doesn't fit is reported as unsupported. One that fits
+/// but fails to decrypt keeps the wrong password error, since random data of
+/// that shape can't be told apart from a legacy file.
+fn open_legacy(
+    session: &SessionState,
+    source: File,
+    password: &str,
+) -> Result<DecryptReader<BufReader<File>>, EncryptionError> {
+    let not_legacy = |e| match e {
+        EncryptionError::TruncatedFile => EncryptionError::UnsupportedFormat("not a legacy cipher file".to_string()),
+        e => e,
+    };
+    let mut reader = DecryptReader::with_cipher(BufReader::new(source), |header| session.cipher(header, password))
+        .map_err(not_legacy)?;
+    io::copy(&mut (&mut reader).take(1), &mut io::sink()).map_err(|e| not_legacy(unwrap_io_error(e)))?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(reader)
+}
+
+/// Re-encrypts the legacy file at `path` into the current format, replacing it
+///
+/// The new file is written next to the original and renamed over it once it
+/// is complete, so a failed or interrupted upgrade leaves the original intact.
+/// Every chunk is authenticated before the original goes away.
+///
+/// # Arguments
+/// * `key_id` - Id of the remembered key `password` belongs to, stored in the new header
+///
+/// # Returns
+/// The plaintext size in bytes
+fn upgrade_legacy_file(
+    progress: &mut ProgressEmitter,
+    session: &SessionState,
+    key_id: Option<Vec<u8>>,
+    path: &Path,
+    password: &str,
+) -> Result<u64, EncryptionError> {
+    let source = File::open(path)?;
+    let permissions = source.metadata()?.permissions();
+    let mut reader = open_legacy(session, source, password)?;
+
+    let plaintext_len = reader.plaintext_len();
+    let mut header = FileHeader::new(generate_nonce());
+    session.set_key_salt(&mut header, password);
+    let cipher = session.cipher(&header, password)?;
+    if let Some(id) = key_id {
+        header.set_extension(EXT_KEY_ID, id);
+    }
+    // Legacy files carry no metadata; the size lets decryption detect truncation from now on
+    let metadata = FileMetadata {
+        size: Some(plaintext_len),
+        ..FileMetadata::default()
+    };
+    header.seal_metadata(&cipher, &metadata)?;
+
+    let temp_path = reserve_temp_path(path)?;
+    let result = write_upgraded(progress, &mut reader, &header, &cipher, &temp_path)
+        .and_then(|_| {
+            // Windows can't replace a file that is still open
+            drop(reader);
+            fs::set_permissions(&temp_path, permissions)?;
+            fs::rename(&temp_path, path)?;
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    // Only a synced directory keeps the rename, and with it the upgrade, after a crash
+    sync_parent_dir(path)?;
+    Ok(plaintext_len)
+}
+
+/// Upgrades one legacy file and builds its response
+fn upgrade_one(
+    progress: &mut ProgressEmitter,
+    session: &SessionState,
+    key_id: Option<Vec<u8>>,
+    path: &Path,
+    password: &str,
+) -> Result<AppResponse, AppResponse> {
+    let start_time = Instant::now();
+    let file_path = path.display().to_string();
+    let size = upgrade_legacy_file(progress, session, key_id, path, password)
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.clone())))?;
+
+    let total_time = start_time.elapsed().as_secs_f64();
+    Ok(create_success_response(
+        ResponseTextCode::FileUpgraded,
+        file_path,
+        ProcessingStats {
+            total_size_bytes: size as usize,
+            processing_time_seconds: total_time,
+            average_speed_mbps: size as f64 / (1024.0 * 1024.0) / total_time,
+            content_hash: None,
+        },
+    ))
+}
+
+/// Finds the legacy files under `file_path` and runs `upgrade` on each, see [`upgrade_file`]
+fn upgrade_legacy_files<F>(file_path: &str, recursive: bool, mut upgrade: F) -> Result<Vec<AppResponse>, AppResponse>
+where
+    F: FnMut(&Path) -> Result<AppResponse, AppResponse>,
+{
+    let path = Path::new(file_path);
+    if !path.is_dir() {
+        return match detect_format(path) {
+            Ok(DetectedFormat::Cipher(ChunkScheme::Legacy)) => upgrade(path).map(|response| vec![response]),
+            Ok(DetectedFormat::Cipher(ChunkScheme::V2)) => {
+                Ok(vec![create_info_response(ResponseTextCode::AlreadyUpgraded, file_path.to_string())])
+            }
+            Ok(_) => Err(AppError::from(EncryptionError::UnsupportedFormat(
+                "age and OpenPGP files are not upgraded".to_string(),
+            ))
+            .into_response(Some(file_path.to_string()))),
+            // Too short for even the nonce of a legacy file
+            Err(EncryptionError::TruncatedFile) => Err(AppError::from(EncryptionError::UnsupportedFormat(
+                "not a legacy cipher file".to_string(),
+            ))
+            .into_response(Some(file_path.to_string()))),
+            Err(e) => Err(AppError::from(e).into_response(Some(file_path.to_string()))),
+        };
+    }
+
+    let candidates = legacy_candidates(path, recursive).map_err(|e| {
+        AppError::io(ResponseTextCode::FileReadFailed, e).into_response(Some(file_path.to_string()))
+    })?;
+    let responses = candidates
+        .iter()
+        .filter(|path| matches!(detect_format(path), Ok(DetectedFormat::Cipher(ChunkScheme::Legacy))))
+        .map(|path| match upgrade(path) {
+            Ok(response) | Err(response) => response,
+        })
+        .collect();
+    Ok(responses)
+}
+
+/// Re-encrypts legacy headerless files into the current format in place
+///
+/// `file_path` is a single file or a folder. Folders are searched for `.enc`
+/// files, including subfolders if `recursive` is set, and only the legacy ones
+/// among them are upgraded. An empty password uses the unlocked session's.
+///
+/// # Returns
+/// One response per file. In folder mode a failed file doesn't stop the batch
+/// and is reported in its response; a single file that fails is an error.
+///
+/// # Events
+/// `upgrade_progress_<path>` with a [`crate::types::ProgressInfo`] while a file is re-encrypted
+#[tauri::command]
+pub async fn upgrade_file(
+    app: AppHandle,
+    file_path: &str,
+    password: &str,
+    recursive: bool,
+) -> Result<Vec<AppResponse>, AppResponse> {
+    let password = session_password(&app, password)?;
+    let password = password.as_str();
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    let session = app.state::<SessionState>();
+    let keystore = app.state::<KeyStore>();
+    upgrade_legacy_files(file_path, recursive, |path| {
+        let key_id = keystore.remembered_key(path).filter(|key| key.matches(password)).map(|key| key.id);
+        let mut progress = ProgressEmitter::new(&app, "upgrade", &path.display().to_string(), 0);
+        upgrade_one(&mut progress, &session, key_id, path, password)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encryption::{
+            helpers::{create_key, NONCE_SIZE},
+            test_util::{test_dir, PASSWORD},
+        },
+        types::Status,
+    };
+
+    /// Spans two chunks, so a failure can happen after part of the file is written
+    fn content() -> Vec<u8> {
+        (0..CHUNK_SIZE + 1000).map(|i| (i.wrapping_mul(13) ^ (i >> 10)) as u8).collect()
+    }
+
+    /// Writes `plaintext` the way earlier versions did: the nonce, then the chunks
+    fn write_legacy(path: &Path, plaintext: &[u8]) {
+        let nonce = generate_nonce();
+        let cipher = ChunkCipher::new(create_key(PASSWORD, nonce.to_vec()).unwrap(), nonce, ChunkScheme::Legacy);
+        let mut bytes = nonce.to_vec();
+        for (index, chunk) in plaintext.chunks(CHUNK_SIZE).enumerate() {
+            bytes.extend(cipher.seal_chunk(index as u64, chunk).unwrap());
+        }
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn upgrade(path: &Path, recursive: bool) -> Result<Vec<AppResponse>, AppResponse> {
+        let session = SessionState::default();
+        upgrade_legacy_files(&path.display().to_string(), recursive, |path| {
+            upgrade_one(&mut ProgressEmitter::silent(), &session, None, path, PASSWORD)
+        })
+    }
+
+    fn decrypt(path: &Path) -> Vec<u8> {
+        let mut reader = DecryptReader::new(File::open(path).unwrap(), PASSWORD).unwrap();
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).unwrap();
+        plaintext
+    }
+
+    /// Everything in `dir` except subfolders, to catch leftover temporary files
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().unwrap().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn upgrades_a_legacy_file_in_place() {
+        let dir = test_dir("in-place");
+        let path = dir.join("notes.enc");
+        write_legacy(&path, &content());
+
+        let responses = upgrade(&path, false).unwrap();
+        assert!(matches!(responses[0].text_code, ResponseTextCode::FileUpgraded));
+        assert_eq!(detect_format(&path).unwrap(), DetectedFormat::Cipher(ChunkScheme::V2));
+        assert_eq!(decrypt(&path), content());
+        let reader = DecryptReader::new(File::open(&path).unwrap(), PASSWORD).unwrap();
+        assert_eq!(reader.metadata().size, Some(content().len() as u64));
+        assert_eq!(files(&dir), ["notes.enc"]);
+
+        let responses = upgrade(&path, false).unwrap();
+        assert!(matches!(responses[0].text_code, ResponseTextCode::AlreadyUpgraded));
+    }
+
+    #[test]
+    fn keeps_the_original_when_the_upgrade_fails() {
+        let dir = test_dir("failed");
+        let path = dir.join("damaged.enc");
+        write_legacy(&path, &content());
+        // The second chunk fails after the first was already written to the new copy
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        fs::write(&path, &bytes).unwrap();
+
+        let response = upgrade(&path, false).unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::CorruptedChunk));
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        assert_eq!(files(&dir), ["damaged.enc"]);
+    }
+
+    #[test]
+    fn reports_files_in_no_known_format() {
+        let dir = test_dir("unknown");
+        // One byte more than a full chunk leaves a last chunk shorter than its tag
+        let path = dir.join("random.enc");
+        let random: Vec<u8> = (0..(NONCE_SIZE + ChunkScheme::Legacy.encrypted_chunk_size() + 1) as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        fs::write(&path, &random).unwrap();
+        let short = dir.join("short.enc");
+        fs::write(&short, b"not encrypted at all, just text").unwrap();
+        let tiny = dir.join("tiny.enc");
+        fs::write(&tiny, b"tiny").unwrap();
+
+        for path in [&path, &short, &tiny] {
+            let response = upgrade(path, false).unwrap_err();
+            assert!(matches!(response.text_code, ResponseTextCode::UnsupportedFormat), "{}", path.display());
+        }
+        assert_eq!(fs::read(&path).unwrap(), random);
+        assert_eq!(files(&dir), ["random.enc", "short.enc", "tiny.enc"]);
+    }
+
+    #[test]
+    fn reports_a_wrong_password_for_legacy_files() {
+        let dir = test_dir("wrong-password");
+        let path = dir.join("notes.enc");
+        write_legacy(&path, &content());
+        let bytes = fs::read(&path).unwrap();
+
+        let session = SessionState::default();
+        let response = upgrade_legacy_files(&path.display().to_string(), false, |path| {
+            upgrade_one(&mut ProgressEmitter::silent(), &session, None, path, "Wrong-Password-1!")
+        })
+        .unwrap_err();
+        assert!(matches!(response.text_code, ResponseTextCode::WrongPassword));
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        assert_eq!(files(&dir), ["notes.enc"]);
+    }
+
+    #[test]
+    fn upgrades_a_folder_and_keeps_going_past_failures() {
+        let dir = test_dir("batch");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        write_legacy(&dir.join("a.enc"), b"first legacy file");
+        write_legacy(&dir.join("nested/b.enc"), b"nested legacy file");
+        write_legacy(&dir.join("c.txt"), b"not named like a legacy file");
+        fs::write(dir.join("d.enc"), b"plain text with the legacy extension").unwrap();
+
+        let responses = upgrade(&dir, false).unwrap();
+        let results: Vec<_> = responses
+            .iter()
+            .map(|response| (response.file_path.clone().unwrap(), matches!(response.status, Status::Success)))
+            .collect();
+        assert_eq!(results, [
+            (dir.join("a.enc").display().to_string(), true),
+            (dir.join("d.enc").display().to_string(), false),
+        ]);
+        assert_eq!(decrypt(&dir.join("a.enc")), b"first legacy file");
+        assert_eq!(detect_format(&dir.join("nested/b.enc")).unwrap(), DetectedFormat::Cipher(ChunkScheme::Legacy));
+        assert_eq!(detect_format(&dir.join("c.txt")).unwrap(), DetectedFormat::Cipher(ChunkScheme::Legacy));
+
+        // Upgraded files are skipped on the second run, subfolders are searched with `recursive`
+        let responses = upgrade(&dir, true).unwrap();
+        let upgraded: Vec<_> = responses
+            .iter()
+            .filter(|response| matches!(response.text_code, ResponseTextCode::FileUpgraded))
+            .map(|response| response.file_path.clone().unwrap())
+            .collect();
+        assert_eq!(upgraded, [dir.join("nested/b.enc").display().to_string()]);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(decrypt(&dir.join("nested/b.enc")), b"nested legacy file");
+        assert_eq!(files(&dir), ["a.enc", "c.txt", "d.enc"]);
+    }
+}
@@ -0,0 +1,743 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{Local, Utc};
+use orion::hazardous::aead::xchacha20poly1305::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    error::AppError,
+    log::add_log_internal,
+    types::{
+        AppResponse, LogLevel, OutputOptions, ResponseTextCode, Status, VaultEntryInfo, VaultResponse,
+    },
+};
+
+use super::{
+    fec::data_section_len,
+    format::{ChunkCipher, ChunkScheme},
+    helpers::{
+        create_key, generate_nonce, read_chunk, sync_parent_dir, validate_password, EncryptionError, CHUNK_SIZE,
+        NONCE_SIZE,
+    },
+    metadata::FileMetadata,
+    output::{finish_output, reserve_temp_path, resolve_output_path, OutputTarget, DEFAULT_DECRYPTION_TEMPLATE},
+    xchacha20::{create_error_response, create_info_response},
+};
+
+/// Marks a vault file
+pub const VAULT_MAGIC: &[u8; 4] = b"CPVT";
+const VAULT_VERSION: u8 = 1;
+/// Marks the trailer that points to the current index
+const TRAILER_MAGIC: &[u8; 4] = b"CPVI";
+
+/// `magic | version u8 | salt (24)`
+const HEADER_LEN: u64 = (VAULT_MAGIC.len() + 1 + NONCE_SIZE) as u64;
+/// `index nonce (24) | index offset u64 LE | index length u32 LE | magic`
+const TRAILER_LEN: u64 = (NONCE_SIZE + 8 + 4 + TRAILER_MAGIC.len()) as u64;
+
+/// Upper bound for the sealed index, guards against allocating garbage lengths
+const MAX_INDEX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// One file stored in the vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryRecord {
+    id: u64,
+    #[serde(flatten)]
+    metadata: FileMetadata,
+    /// When the entry was added, in seconds since the Unix epoch
+    added: i64,
+    /// Base nonce of the entry's chunks, base64
+    nonce: String,
+    /// Position and size of the encrypted chunks in the vault file
+    offset: u64,
+    length: u64,
+}
+
+impl EntryRecord {
+    fn info(&self) -> VaultEntryInfo {
+        VaultEntryInfo {
+            id: self.id,
+            name: self
+                .metadata
+                .safe_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            size_bytes: self.metadata.size.unwrap_or_default(),
+            modified: self.metadata.modified,
+            added: self.added,
+        }
+    }
+}
+
+/// The list of entries, stored encrypted at the end of the vault
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VaultIndex {
+    next_id: u64,
+    entries: Vec<EntryRecord>,
+}
+
+/// Container holding many encrypted files under one password
+///
+/// Layout:
+/// ```text
+/// header | entry chunks ... | sealed index | trailer
+/// ```
+/// The key is derived once from the password and the salt in the header. Every
+/// entry is encrypted like a file body, as chunks under its own random nonce,
+/// and the index listing names, sizes and positions is sealed under a fresh
+/// nonce on every write, so entry names are only readable with the password.
+///
+/// Adding appends the new chunks and a new index after the current trailer,
+/// which is left in place. The index is synced before the new trailer is
+/// written, so a trailer on disk always points to a complete index. If an add
+/// is interrupted, the file ends in a partial write; opening then searches back
+/// for the last complete trailer, and the next add overwrites the partial data.
+/// Deleting rewrites the vault into a temporary file that replaces the original,
+/// which also drops the space of old indexes.
+pub struct Vault {
+    path: PathBuf,
+    file: File,
+    key: SecretKey,
+    salt: [u8; NONCE_SIZE],
+    index: VaultIndex,
+    /// End of the current trailer, where the next add starts writing
+    end: u64,
+}
+
+/// Location of a sealed index, as read from a trailer
+struct Trailer {
+    nonce: [u8; NONCE_SIZE],
+    offset: u64,
+    len: u64,
+}
+
+impl Vault {
+    /// Creates an empty vault; fails if `path` exists
+    pub fn create(path: &Path, password: &str) -> Result<Self, EncryptionError> {
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(path)?;
+        let salt = generate_nonce();
+        let mut vault = Self {
+            path: path.to_path_buf(),
+            file,
+            key: create_key(password, salt.to_vec())?,
+            salt,
+            index: VaultIndex::default(),
+            end: HEADER_LEN,
+        };
+        vault.file.write_all(&vault.header_bytes())?;
+        vault.write_index(HEADER_LEN)?;
+        Ok(vault)
+    }
+
+    /// Opens a vault read-only, failing with `WrongPassword` if the index doesn't decrypt
+    pub fn open(path: &Path, password: &str) -> Result<Self, EncryptionError> {
+        Self::open_with(path, password, false)
+    }
+
+    /// Opens a vault for [`Vault::add`] and [`Vault::delete`]
+    pub fn open_for_update(path: &Path, password: &str) -> Result<Self, EncryptionError> {
+        Self::open_with(path, password, true)
+    }
+
+    fn open_with(path: &Path, password: &str, writable: bool) -> Result<Self, EncryptionError> {
+        let mut file = OpenOptions::new().read(true).write(writable).open(path)?;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header).map_err(|_| EncryptionError::TruncatedFile)?;
+        if !header.starts_with(VAULT_MAGIC) {
+            return Err(EncryptionError::InvalidFormat("Not a vault".to_string()));
+        }
+        if header[4] != VAULT_VERSION {
+            return Err(EncryptionError::UnsupportedFormat(format!("vault version {}", header[4])));
+        }
+        let mut salt = [0u8; NONCE_SIZE];
+        salt.copy_from_slice(&header[5..]);
+
+        let mut vault = Self {
+            path: path.to_path_buf(),
+            file,
+            key: create_key(password, salt.to_vec())?,
+            salt,
+            index: VaultIndex::default(),
+            end: HEADER_LEN,
+        };
+        let (index, end) = vault.read_index()?;
+        vault.index = index;
+        vault.end = end;
+        Ok(vault)
+    }
+
+    fn header_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN as usize);
+        bytes.extend_from_slice(VAULT_MAGIC);
+        bytes.push(VAULT_VERSION);
+        bytes.extend_from_slice(&self.salt);
+        bytes
+    }
+
+    /// Creates a cipher for `nonce` under the vault key
+    fn cipher(&self, nonce: [u8; NONCE_SIZE]) -> Result<ChunkCipher, EncryptionError> {
+        let key = SecretKey::from_slice(self.key.unprotected_as_bytes())
+            .map_err(|_| EncryptionError::SecretKeyCreation)?;
+        Ok(ChunkCipher::new(key, nonce, ChunkScheme::V2))
+    }
+
+    /// Reads the current index
+    ///
+    /// # Returns
+    /// The index and the end of its trailer. That is the file length unless an
+    /// interrupted add left a partial write behind the last complete trailer.
+    fn read_index(&mut self) -> Result<(VaultIndex, u64), EncryptionError> {
+        let len = self.file.metadata()?.len();
+        if len < HEADER_LEN + TRAILER_LEN {
+            return Err(EncryptionError::TruncatedFile);
+        }
+        let (trailer_pos, trailer) = match self.trailer_at(len - TRAILER_LEN)? {
+            Some(trailer) => (len - TRAILER_LEN, trailer),
+            None => self
+                .find_trailer(len - TRAILER_LEN)?
+                .ok_or_else(|| EncryptionError::InvalidFormat("Vault index trailer is missing".to_string()))?,
+        };
+
+        let mut sealed = vec![0u8; trailer.len as usize];
+        self.file.seek(SeekFrom::Start(trailer.offset))?;
+        self.file.read_exact(&mut sealed)?;
+        let json = self.cipher(trailer.nonce)?.open_chunk(0, &sealed).map_err(|e| match e {
+            EncryptionError::CorruptedChunk { .. } => EncryptionError::WrongPassword,
+            e => e,
+        })?;
+        let index = serde_json::from_slice(&json).map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+        Ok((index, trailer_pos + TRAILER_LEN))
+    }
+
+    /// Reads the trailer starting at `pos`
+    ///
+    /// # Returns
+    /// `None` unless the magic is there and the index it points to ends right
+    /// before it
+    fn trailer_at(&mut self, pos: u64) -> Result<Option<Trailer>, EncryptionError> {
+        let mut bytes = [0u8; TRAILER_LEN as usize];
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.read_exact(&mut bytes)?;
+        if !bytes.ends_with(TRAILER_MAGIC) {
+            return Ok(None);
+        }
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&bytes[..NONCE_SIZE]);
+        let mut field = [0u8; 8];
+        field.copy_from_slice(&bytes[NONCE_SIZE..NONCE_SIZE + 8]);
+        let offset = u64::from_le_bytes(field);
+        let mut field = [0u8; 4];
+        field.copy_from_slice(&bytes[NONCE_SIZE + 8..NONCE_SIZE + 12]);
+        let len = u32::from_le_bytes(field) as u64;
+        if offset < HEADER_LEN || len > MAX_INDEX_SIZE || offset.checked_add(len) != Some(pos) {
+            return Ok(None);
+        }
+        Ok(Some(Trailer { nonce, offset, len }))
+    }
+
+    /// Searches backwards from `before` for the last valid trailer and its position
+    fn find_trailer(&mut self, before: u64) -> Result<Option<(u64, Trailer)>, EncryptionError> {
+        const SCAN_BLOCK: u64 = 64 * 1024;
+        let magic_len = TRAILER_MAGIC.len() as u64;
+        let lowest = HEADER_LEN + TRAILER_LEN - magic_len;
+        // The magic of a trailer starting before `before` starts before this
+        let mut scan_end = before + TRAILER_LEN - magic_len;
+
+        while scan_end > lowest {
+            let scan_start = scan_end.saturating_sub(SCAN_BLOCK).max(lowest);
+            // Read a few bytes past the block so a magic across the boundary is found
+            let mut block = vec![0u8; (scan_end - scan_start + magic_len - 1) as usize];
+            self.file.seek(SeekFrom::Start(scan_start))?;
+            self.file.read_exact(&mut block)?;
+
+            for at in (0..=block.len() - TRAILER_MAGIC.len()).rev() {
+                if &block[at..at + TRAILER_MAGIC.len()] != TRAILER_MAGIC {
+                    continue;
+                }
+                let pos = scan_start + at as u64 + magic_len - TRAILER_LEN;
+                if let Some(trailer) = self.trailer_at(pos)? {
+                    return Ok(Some((pos, trailer)));
+                }
+            }
+            scan_end = scan_start;
+        }
+        Ok(None)
+    }
+
+    /// Seals the index under a fresh nonce at `offset`, followed by the trailer
+    fn write_index(&mut self, offset: u64) -> Result<(), EncryptionError> {
+        let json = Zeroizing::new(
+            serde_json::to_vec(&self.index).map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?,
+        );
+        let nonce = generate_nonce();
+        let sealed = self.cipher(nonce)?.seal_chunk(0, &json)?;
+
+        let mut trailer = Vec::with_capacity(TRAILER_LEN as usize);
+        trailer.extend_from_slice(&nonce);
+        trailer.extend_from_slice(&offset.to_le_bytes());
+        trailer.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        trailer.extend_from_slice(TRAILER_MAGIC);
+
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&sealed)?;
+        // The entry chunks and the index must be on disk before a trailer points to them
+        self.file.sync_data()?;
+        self.file.write_all(&trailer)?;
+        let end = offset + sealed.len() as u64 + TRAILER_LEN;
+        self.file.set_len(end)?;
+        self.file.sync_all()?;
+        self.end = end;
+        Ok(())
+    }
+
+    /// Lists the entries
+    pub fn entries(&self) -> Vec<VaultEntryInfo> {
+        self.index.entries.iter().map(EntryRecord::info).collect()
+    }
+
+    fn entry(&self, id: u64) -> Result<&EntryRecord, EncryptionError> {
+        self.index
+            .entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or(EncryptionError::EntryNotFound(id))
+    }
+
+    /// Encrypts the file at `source` into the vault
+    ///
+    /// # Returns
+    /// The id of the new entry
+    pub fn add(&mut self, source: &Path) -> Result<u64, EncryptionError> {
+        if fs::canonicalize(source)? == fs::canonicalize(&self.path)? {
+            return Err(EncryptionError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The vault can't be added to itself",
+            )));
+        }
+
+        let end = self.end;
+        let result = self.append_entry(source, end);
+        if result.is_err() {
+            // The current trailer is still intact at the old end
+            let _ = self.file.set_len(end);
+            self.end = end;
+        }
+        result
+    }
+
+    fn append_entry(&mut self, source: &Path, offset: u64) -> Result<u64, EncryptionError> {
+        let nonce = generate_nonce();
+        let cipher = self.cipher(nonce)?;
+        let mut reader = BufReader::new(File::open(source)?);
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut writer = BufWriter::new(&self.file);
+
+        let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+        let mut chunk_index = 0;
+        let mut size = 0;
+        let mut length = 0;
+        loop {
+            let n = read_chunk(&mut reader, &mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            let sealed = cipher.seal_chunk(chunk_index, &buffer[..n])?;
+            writer.write_all(&sealed)?;
+            chunk_index += 1;
+            size += n as u64;
+            length += sealed.len() as u64;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let mut metadata = FileMetadata::from_path(source, false);
+        metadata.size = Some(size);
+        let id = self.index.next_id;
+        self.index.next_id += 1;
+        self.index.entries.push(EntryRecord {
+            id,
+            metadata,
+            added: Utc::now().timestamp(),
+            nonce: STANDARD.encode(nonce),
+            offset,
+            length,
+        });
+        if let Err(e) = self.write_index(offset + length) {
+            self.index.entries.pop();
+            self.index.next_id -= 1;
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Decrypts entry `id` into `writer`
+    ///
+    /// # Returns
+    /// The stored metadata of the entry
+    pub fn extract<W: Write>(&mut self, id: u64, writer: &mut W) -> Result<FileMetadata, EncryptionError> {
+        let entry = self.entry(id)?.clone();
+        let mut nonce = [0u8; NONCE_SIZE];
+        let decoded = STANDARD
+            .decode(&entry.nonce)
+            .map_err(|e| EncryptionError::InvalidFormat(e.to_string()))?;
+        if decoded.len() != NONCE_SIZE {
+            return Err(EncryptionError::InvalidFormat("Invalid entry nonce".to_string()));
+        }
+        nonce.copy_from_slice(&decoded);
+        let cipher = self.cipher(nonce)?;
+
+        let size = entry.metadata.size.unwrap_or_default();
+        if data_section_len(size, ChunkScheme::V2) != entry.length {
+            return Err(EncryptionError::InvalidFormat("Entry length doesn't match its size".to_string()));
+        }
+
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let mut reader = BufReader::new(&self.file).take(entry.length);
+        let mut buffer = vec![0u8; ChunkScheme::V2.encrypted_chunk_size()];
+        let mut chunk_index = 0;
+        loop {
+            let n = read_chunk(&mut reader, &mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            let decrypted = Zeroizing::new(cipher.open_chunk(chunk_index, &buffer[..n])?);
+            writer.write_all(&decrypted)?;
+            chunk_index += 1;
+        }
+        writer.flush()?;
+        Ok(entry.metadata)
+    }
+
+    /// Removes entry `id` and compacts the vault
+    pub fn delete(&mut self, id: u64) -> Result<(), EncryptionError> {
+        self.entry(id)?;
+
+        let temp_path = reserve_temp_path(&self.path)?;
+        let result = self.rewrite_without(id, &temp_path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    fn rewrite_without(&mut self, id: u64, temp_path: &Path) -> Result<(), EncryptionError> {
+        let mut temp = OpenOptions::new().read(true).write(true).open(temp_path)?;
+        temp.write_all(&self.header_bytes())?;
+
+        // Entries are copied still encrypted, only their offsets change
+        let mut entries = Vec::with_capacity(self.index.entries.len());
+        let mut offset = HEADER_LEN;
+        for entry in self.index.entries.iter().filter(|entry| entry.id != id) {
+            self.file.seek(SeekFrom::Start(entry.offset))?;
+            let copied = io::copy(&mut (&self.file).take(entry.length), &mut temp)?;
+            if copied != entry.length {
+                return Err(EncryptionError::TruncatedFile);
+            }
+            entries.push(EntryRecord { offset, ..entry.clone() });
+            offset += entry.length;
+        }
+
+        let previous_end = self.end;
+        let previous = std::mem::replace(&mut self.file, temp);
+        let previous_entries = std::mem::replace(&mut self.index.entries, entries);
+        let result = self
+            .write_index(offset)
+            .and_then(|_| fs::rename(temp_path, &self.path).map_err(EncryptionError::from));
+        if let Err(e) = result {
+            self.file = previous;
+            self.index.entries = previous_entries;
+            self.end = previous_end;
+            return Err(e);
+        }
+        drop(previous);
+        sync_parent_dir(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Creates a vault response with logging
+fn create_vault_response(code: ResponseTextCode, vault_path: &str, entries: Vec<VaultEntryInfo>) -> VaultResponse {
+    add_log_internal(LogLevel::Info, code.clone(), Some(vault_path.to_string())).ok();
+    VaultResponse {
+        status: Status::Success,
+        text_code: code,
+        entries,
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }
+}
+
+/// Opens the vault at `vault_path` after validating the password, read-only unless `writable`
+fn open_vault(vault_path: &str, password: &str, writable: bool) -> Result<Vault, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+    let vault = if writable {
+        Vault::open_for_update(Path::new(vault_path), password)
+    } else {
+        Vault::open(Path::new(vault_path), password)
+    };
+    vault.map_err(|e| AppError::from(e).into_response(Some(vault_path.to_string())))
+}
+
+#[tauri::command]
+pub async fn create_vault(vault_path: &str, password: &str) -> Result<VaultResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+    if Path::new(vault_path).exists() {
+        return Err(AppError::OutputExists(PathBuf::from(vault_path)).into_response(Some(vault_path.to_string())));
+    }
+    Vault::create(Path::new(vault_path), password)
+        .map_err(|e| AppError::from(e).into_response(Some(vault_path.to_string())))?;
+
+    Ok(create_vault_response(ResponseTextCode::VaultCreated, vault_path, Vec::new()))
+}
+
+#[tauri::command]
+pub async fn list_vault(vault_path: &str, password: &str) -> Result<VaultResponse, AppResponse> {
+    let vault = open_vault(vault_path, password, false)?;
+    Ok(create_vault_response(ResponseTextCode::VaultOpened, vault_path, vault.entries()))
+}
+
+#[tauri::command]
+pub async fn add_to_vault(vault_path: &str, password: &str, file_paths: Vec<String>) -> Result<VaultResponse, AppResponse> {
+    let mut vault = open_vault(vault_path, password, true)?;
+    for file_path in &file_paths {
+        vault
+            .add(Path::new(file_path))
+            .map_err(|e| AppError::from(e).into_response(Some(file_path.clone())))?;
+    }
+    Ok(create_vault_response(ResponseTextCode::VaultEntryAdded, vault_path, vault.entries()))
+}
+
+#[tauri::command]
+pub async fn extract_from_vault(
+    vault_path: &str,
+    password: &str,
+    entry_id: u64,
+    options: Option<OutputOptions>,
+) -> Result<AppResponse, AppResponse> {
+    let options = options.unwrap_or_default();
+    let mut vault = open_vault(vault_path, password, false)?;
+
+    let name = vault
+        .entry(entry_id)
+        .map_err(|e| AppError::from(e).into_response(Some(vault_path.to_string())))?
+        .metadata
+        .safe_name()
+        .unwrap_or_else(|| format!("entry_{}", entry_id).into());
+    let (output_path, destination) = match resolve_output_path(Path::new(vault_path), &name, &options, DEFAULT_DECRYPTION_TEMPLATE)
+        .map_err(|e| e.into_response(Some(vault_path.to_string())))?
+    {
+        OutputTarget::Create(path) => (path.clone(), path),
+        OutputTarget::Replace { temp, destination } => (temp, destination),
+        OutputTarget::Skip(existing_path) => {
+            return Ok(create_info_response(ResponseTextCode::FileSkipped, existing_path.display().to_string()));
+        }
+    };
+    let output_path_str = destination.display().to_string();
+
+    let mut output_file = File::create(&output_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
+    let metadata = vault.extract(entry_id, &mut output_file).map_err(|e| {
+        let _ = fs::remove_file(&output_path);
+        AppError::from(e).into_response(Some(vault_path.to_string()))
+    })?;
+    if metadata.apply(&output_file, &output_path).is_err() {
+        add_log_internal(LogLevel::Warning, ResponseTextCode::MetadataRestoreFailed, Some(output_path_str.clone())).ok();
+    }
+    drop(output_file);
+    finish_output(&output_path, &destination).map_err(|e| {
+        let _ = fs::remove_file(&output_path);
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
+
+    Ok(create_info_response(ResponseTextCode::VaultEntryExtracted, output_path_str))
+}
+
+#[tauri::command]
+pub async fn delete_from_vault(vault_path: &str, password: &str, entry_id: u64) -> Result<VaultResponse, AppResponse> {
+    let mut vault = open_vault(vault_path, password, true)?;
+    vault
+        .delete(entry_id)
+        .map_err(|e| AppError::from(e).into_response(Some(vault_path.to_string())))?;
+    Ok(create_vault_response(ResponseTextCode::VaultEntryDeleted, vault_path, vault.entries()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::{test_dir, TestDir, PASSWORD};
+
+    /// A fresh directory with a small and a multi-chunk file to add
+    ///
+    /// # Returns
+    /// The directory, the vault path and the two files
+    fn setup(name: &str) -> (TestDir, PathBuf, PathBuf, PathBuf) {
+        let dir = test_dir(name);
+        let small = dir.join("notes.txt");
+        fs::write(&small, b"vault entry").unwrap();
+        let large = dir.join("large.bin");
+        fs::write(&large, large_content()).unwrap();
+        let vault = dir.join("files.vault");
+        (dir, vault, small, large)
+    }
+
+    fn large_content() -> Vec<u8> {
+        (0..CHUNK_SIZE + CHUNK_SIZE / 2).map(|i| (i.wrapping_mul(31) ^ (i >> 11)) as u8).collect()
+    }
+
+    fn extracted(vault: &mut Vault, id: u64) -> Vec<u8> {
+        let mut output = Vec::new();
+        vault.extract(id, &mut output).unwrap();
+        output
+    }
+
+    fn names(vault: &Vault) -> Vec<String> {
+        vault.entries().into_iter().map(|entry| entry.name).collect()
+    }
+
+    #[test]
+    fn adds_lists_extracts_and_deletes() {
+        let (_dir, path, small, large) = setup("round-trip");
+        let mut vault = Vault::create(&path, PASSWORD).unwrap();
+        let small_id = vault.add(&small).unwrap();
+        let large_id = vault.add(&large).unwrap();
+        drop(vault);
+
+        let mut vault = Vault::open(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["notes.txt", "large.bin"]);
+        assert_eq!(vault.entries()[1].size_bytes, large_content().len() as u64);
+        assert_eq!(extracted(&mut vault, small_id), b"vault entry");
+        assert_eq!(extracted(&mut vault, large_id), large_content());
+        drop(vault);
+
+        let mut vault = Vault::open_for_update(&path, PASSWORD).unwrap();
+        vault.delete(small_id).unwrap();
+        assert!(matches!(vault.delete(small_id), Err(EncryptionError::EntryNotFound(id)) if id == small_id));
+        drop(vault);
+
+        let mut vault = Vault::open(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["large.bin"]);
+        assert_eq!(extracted(&mut vault, large_id), large_content());
+        assert!(matches!(vault.extract(small_id, &mut Vec::new()), Err(EncryptionError::EntryNotFound(_))));
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        let (_dir, path, small, _) = setup("wrong-password");
+        Vault::create(&path, PASSWORD).unwrap().add(&small).unwrap();
+
+        assert!(matches!(Vault::open(&path, "Vault-Test-2!"), Err(EncryptionError::WrongPassword)));
+    }
+
+    #[test]
+    fn falls_back_to_the_last_complete_index_after_a_truncated_trailer() {
+        let (_dir, path, small, large) = setup("truncated-trailer");
+        let mut vault = Vault::create(&path, PASSWORD).unwrap();
+        let small_id = vault.add(&small).unwrap();
+        vault.add(&large).unwrap();
+        drop(vault);
+
+        // An add interrupted while writing its trailer
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 10).unwrap();
+
+        let mut vault = Vault::open(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["notes.txt"]);
+        assert_eq!(extracted(&mut vault, small_id), b"vault entry");
+        drop(vault);
+
+        // The next add overwrites the partial write
+        let mut vault = Vault::open_for_update(&path, PASSWORD).unwrap();
+        let large_id = vault.add(&large).unwrap();
+        drop(vault);
+        let mut vault = Vault::open(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["notes.txt", "large.bin"]);
+        assert_eq!(extracted(&mut vault, large_id), large_content());
+    }
+
+    #[test]
+    fn ignores_partial_data_after_the_trailer() {
+        let (_dir, path, small, _) = setup("partial-data");
+        let mut vault = Vault::create(&path, PASSWORD).unwrap();
+        vault.add(&small).unwrap();
+        let complete_len = fs::metadata(&path).unwrap().len();
+        drop(vault);
+
+        // Chunks of an add that never got to write its index
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&large_content()[..200_000]).unwrap();
+        drop(file);
+
+        let mut vault = Vault::open_for_update(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["notes.txt"]);
+        let small_len = fs::metadata(&small).unwrap().len();
+        vault.add(&small).unwrap();
+        drop(vault);
+
+        let data_len = data_section_len(small_len, ChunkScheme::V2);
+        let vault = Vault::open(&path, PASSWORD).unwrap();
+        assert_eq!(names(&vault), ["notes.txt", "notes.txt"]);
+        assert!(fs::metadata(&path).unwrap().len() < complete_len + data_len + 1024);
+    }
+
+    #[test]
+    fn reports_a_file_shorter_than_the_header_as_truncated() {
+        let (_dir, path, _, _) = setup("short");
+        fs::write(&path, b"CPVT\x01").unwrap();
+
+        assert!(matches!(Vault::open(&path, PASSWORD), Err(EncryptionError::TruncatedFile)));
+    }
+
+    #[test]
+    fn refuses_to_add_the_vault_to_itself() {
+        let (_dir, path, small, _) = setup("self");
+        let mut vault = Vault::create(&path, PASSWORD).unwrap();
+        vault.add(&small).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+
+        assert!(matches!(vault.add(&path), Err(EncryptionError::Io(e)) if e.kind() == io::ErrorKind::InvalidInput));
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(names(&vault), ["notes.txt"]);
+    }
+
+    #[test]
+    fn reads_without_write_access() {
+        let (_dir, path, small, _) = setup("read-only");
+        Vault::create(&path, PASSWORD).unwrap().add(&small).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+
+        let mut vault = Vault::open(&path, PASSWORD).unwrap();
+        assert!(vault.add(&small).is_err());
+        assert_eq!(names(&vault), ["notes.txt"]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn delete_leaves_other_temporary_files_alone() {
+        let (_dir, path, small, large) = setup("temp-name");
+        let mut vault = Vault::create(&path, PASSWORD).unwrap();
+        let small_id = vault.add(&small).unwrap();
+        vault.add(&large).unwrap();
+        let unrelated = path.with_extension("vault.tmp");
+        fs::write(&unrelated, b"keep me").unwrap();
+
+        vault.delete(small_id).unwrap();
+
+        assert_eq!(fs::read(&unrelated).unwrap(), b"keep me");
+        let leftovers = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with('.'))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
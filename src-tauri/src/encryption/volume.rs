@@ -0,0 +1,535 @@
+use orion::hazardous::hash::blake2::blake2b::Blake2b;
+use std::{
+    ffi::OsStr,
+    fs::{remove_file, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    format::{ChunkCipher, FileHeader},
+    helpers::{read_chunk, EncryptionError, NONCE_SIZE},
+};
+
+/// Marks a volume of a split file
+pub const VOLUME_MAGIC: &[u8; 4] = b"CPHV";
+const VOLUME_VERSION: u8 = 1;
+
+/// `magic | version u8 | file nonce (24) | index u32 LE | count u32 LE`
+pub const VOLUME_HEADER_LEN: u64 = (VOLUME_MAGIC.len() + 1 + NONCE_SIZE + 4 + 4) as u64;
+/// Poly1305 tag closing every volume
+const VOLUME_TAG_LEN: u64 = 16;
+
+/// Smallest accepted volume size, so every volume carries a useful amount of data
+pub const MIN_VOLUME_SIZE: u64 = 64 * 1024;
+
+/// Size of the BLAKE2b digest the volume tag authenticates
+const DIGEST_LEN: usize = 32;
+
+/// Header at the start of every volume
+///
+/// A split file is the normal encrypted stream cut into pieces named
+/// `<name>.001`, `<name>.002`, ... Each piece is
+/// ```text
+/// volume header | part of the encrypted stream | tag (16)
+/// ```
+/// The tag authenticates a BLAKE2b digest of the header and the data with the
+/// file key, so a volume from another file, a reordered or renamed volume and
+/// any damage are reported for the volume they affect. Every header carries the
+/// volume count, which lets decryption check that all volumes are present first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VolumeHeader {
+    nonce: [u8; NONCE_SIZE],
+    index: u32,
+    count: u32,
+}
+
+impl VolumeHeader {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(VOLUME_HEADER_LEN as usize);
+        bytes.extend_from_slice(VOLUME_MAGIC);
+        bytes.push(VOLUME_VERSION);
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes
+    }
+
+    fn read<R: Read>(reader: &mut R, index: u32) -> Result<Self, EncryptionError> {
+        let mut bytes = [0u8; VOLUME_HEADER_LEN as usize];
+        if read_chunk(reader, &mut bytes)? < bytes.len() || !is_volume(&bytes) {
+            return Err(EncryptionError::CorruptedVolume { index });
+        }
+        if bytes[4] != VOLUME_VERSION {
+            return Err(EncryptionError::UnsupportedFormat(format!("volume version {}", bytes[4])));
+        }
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&bytes[5..5 + NONCE_SIZE]);
+        let field = |offset: usize| {
+            u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+        };
+        Ok(Self {
+            nonce,
+            index: field(5 + NONCE_SIZE),
+            count: field(9 + NONCE_SIZE),
+        })
+    }
+}
+
+/// Returns true if `data` starts like a volume
+pub fn is_volume(data: &[u8]) -> bool {
+    data.starts_with(VOLUME_MAGIC)
+}
+
+/// Path of volume `index` (1-based): `report.pdf.enc` -> `report.pdf.enc.001`
+pub fn volume_path(base: &Path, index: u32) -> PathBuf {
+    let mut path = base.as_os_str().to_os_string();
+    path.push(format!(".{:03}", index));
+    PathBuf::from(path)
+}
+
+/// Strips the volume number: `report.pdf.enc.002` -> `report.pdf.enc`
+pub fn base_path(volume: &Path) -> Option<PathBuf> {
+    let extension = volume.extension()?.to_str()?;
+    if extension.len() < 3 || !extension.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(volume.with_extension(OsStr::new("")))
+}
+
+/// Number of volumes needed for `total_len` bytes of encrypted stream
+pub fn volume_count(total_len: u64, volume_size: u64) -> u64 {
+    let capacity = volume_size - VOLUME_HEADER_LEN - VOLUME_TAG_LEN;
+    total_len.div_ceil(capacity).max(1)
+}
+
+fn new_hasher() -> Result<Blake2b, EncryptionError> {
+    Blake2b::new(DIGEST_LEN).map_err(|_| EncryptionError::Encryption)
+}
+
+fn digest(hasher: &mut Blake2b) -> Result<Vec<u8>, EncryptionError> {
+    hasher
+        .finalize()
+        .map(|digest| digest.as_ref().to_vec())
+        .map_err(|_| EncryptionError::Encryption)
+}
+
+fn hash_update(hasher: &mut Blake2b, data: &[u8]) -> io::Result<()> {
+    hasher
+        .update(data)
+        .map_err(|_| io::Error::other("Failed to hash volume"))
+}
+
+/// Splits the encrypted stream into volumes of `volume_size` bytes
+///
+/// The number of volumes is fixed up front from the expected stream length.
+/// Volumes are always created new, never written over existing files. A failed
+/// encryption calls [`VolumeWriter::discard`] to remove the volumes written so far.
+pub struct VolumeWriter {
+    cipher: ChunkCipher,
+    base: PathBuf,
+    nonce: [u8; NONCE_SIZE],
+    count: u32,
+    capacity: u64,
+    index: u32,
+    written: u64,
+    file: Option<(BufWriter<File>, Blake2b)>,
+    paths: Vec<PathBuf>,
+}
+
+impl VolumeWriter {
+    /// # Arguments
+    /// * `cipher` - Cipher of the file being written, used for the volume tags
+    /// * `nonce` - The file nonce, repeated in every volume header
+    /// * `base` - Output path; volumes get `.001`, `.002`, ... appended
+    /// * `total_len` - Expected length of the encrypted stream
+    /// * `volume_size` - Size of every volume except the last one
+    pub fn new(
+        cipher: ChunkCipher,
+        nonce: [u8; NONCE_SIZE],
+        base: &Path,
+        total_len: u64,
+        volume_size: u64,
+    ) -> Result<Self, EncryptionError> {
+        let count = u32::try_from(volume_count(total_len, volume_size))
+            .map_err(|_| EncryptionError::InvalidFormat("Too many volumes".to_string()))?;
+        Ok(Self {
+            cipher,
+            base: base.to_path_buf(),
+            nonce,
+            count,
+            capacity: volume_size - VOLUME_HEADER_LEN - VOLUME_TAG_LEN,
+            index: 0,
+            written: 0,
+            file: None,
+            paths: Vec::new(),
+        })
+    }
+
+    /// Number of volumes the stream is split into
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    fn open_next(&mut self) -> io::Result<()> {
+        if self.index == self.count {
+            return Err(io::Error::other("Encrypted data exceeds the planned volumes"));
+        }
+        self.index += 1;
+        let path = volume_path(&self.base, self.index);
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        self.paths.push(path);
+
+        let mut writer = BufWriter::new(file);
+        let mut hasher = new_hasher().map_err(to_io_error)?;
+        let header = VolumeHeader {
+            nonce: self.nonce,
+            index: self.index,
+            count: self.count,
+        }
+        .to_bytes();
+        writer.write_all(&header)?;
+        hash_update(&mut hasher, &header)?;
+        self.file = Some((writer, hasher));
+        self.written = 0;
+        Ok(())
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        if let Some((mut writer, mut hasher)) = self.file.take() {
+            let digest = digest(&mut hasher).map_err(to_io_error)?;
+            let tag = self.cipher.seal_volume_tag(self.index, &digest).map_err(to_io_error)?;
+            writer.write_all(&tag)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the last volume and checks that the planned count was reached
+    ///
+    /// The volumes are removed if that fails.
+    ///
+    /// # Returns
+    /// The paths of all volumes
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        let result = self.close_current().and_then(|_| {
+            if self.index != self.count {
+                return Err(io::Error::other("Encrypted data is shorter than planned"));
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(std::mem::take(&mut self.paths)),
+            Err(e) => {
+                self.discard();
+                Err(e)
+            }
+        }
+    }
+
+    /// Closes and removes every volume written so far, after a failed encryption
+    ///
+    /// Only volumes this writer created are removed.
+    pub fn discard(&mut self) {
+        self.file = None;
+        for path in self.paths.drain(..) {
+            let _ = remove_file(path);
+        }
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.file.is_none() {
+            self.open_next()?;
+        }
+        let n = buf.len().min((self.capacity - self.written) as usize);
+        if let Some((writer, hasher)) = self.file.as_mut() {
+            writer.write_all(&buf[..n])?;
+            hash_update(hasher, &buf[..n])?;
+        }
+        self.written += n as u64;
+        if self.written == self.capacity {
+            self.close_current()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the encrypted stream back from its volumes, verifying each volume tag
+///
+/// All volumes are checked for presence and a matching header when the reader
+/// is opened; the tag of each volume is verified when its end is reached.
+pub struct VolumeReader {
+    cipher: ChunkCipher,
+    paths: Vec<PathBuf>,
+    index: u32,
+    remaining: u64,
+    current: Option<(BufReader<File>, Blake2b)>,
+}
+
+impl VolumeReader {
+    /// Opens the volume set that `path` belongs to
+    ///
+    /// # Arguments
+    /// * `path` - Any volume of the set
+    /// * `password` - Used to verify the volume tags
+    pub fn open(path: &Path, password: &str) -> Result<Self, EncryptionError> {
+        // The tags are sealed with the file key, so the key salt comes from the file header
+        Self::open_with_cipher(path, |mut stream| FileHeader::read(&mut stream)?.cipher(password))
+    }
+
+    /// Like [`VolumeReader::open`] with the cipher built by `cipher_for` from the start of the joined stream
+    fn open_with_cipher(
+        path: &Path,
+        cipher_for: impl FnOnce(&mut dyn Read) -> Result<ChunkCipher, EncryptionError>,
+    ) -> Result<Self, EncryptionError> {
+        let base = base_path(path)
+            .ok_or_else(|| EncryptionError::InvalidFormat("Volume name must end in a volume number".to_string()))?;
+
+        let first = VolumeHeader::read(&mut File::open(volume_path(&base, 1)).map_err(|_| {
+            EncryptionError::MissingVolume { index: 1 }
+        })?, 1)?;
+
+        let mut paths = Vec::with_capacity(first.count as usize);
+        for index in 1..=first.count {
+            let path = volume_path(&base, index);
+            let mut file = File::open(&path).map_err(|_| EncryptionError::MissingVolume { index })?;
+            let header = VolumeHeader::read(&mut file, index)?;
+            let expected = VolumeHeader { index, ..first };
+            if header != expected {
+                return Err(EncryptionError::CorruptedVolume { index });
+            }
+            paths.push(path);
+        }
+
+        let mut stream = BufReader::new(File::open(&paths[0]).map_err(|_| EncryptionError::MissingVolume { index: 1 })?);
+        stream.seek(SeekFrom::Start(VOLUME_HEADER_LEN))?;
+        Ok(Self {
+            cipher: cipher_for(&mut stream)?,
+            paths,
+            index: 0,
+            remaining: 0,
+            current: None,
+        })
+    }
+
+    /// Paths of all volumes in order
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    fn open_next(&mut self) -> Result<bool, EncryptionError> {
+        if self.index as usize == self.paths.len() {
+            return Ok(false);
+        }
+        self.index += 1;
+        let index = self.index;
+        let file = File::open(&self.paths[index as usize - 1])
+            .map_err(|_| EncryptionError::MissingVolume { index })?;
+        let len = file.metadata()?.len();
+        if len < VOLUME_HEADER_LEN + VOLUME_TAG_LEN {
+            return Err(EncryptionError::CorruptedVolume { index });
+        }
+
+        let mut reader = BufReader::new(file);
+        let header = VolumeHeader::read(&mut reader, index)?;
+        let mut hasher = new_hasher()?;
+        hash_update(&mut hasher, &header.to_bytes())?;
+        self.remaining = len - VOLUME_HEADER_LEN - VOLUME_TAG_LEN;
+        self.current = Some((reader, hasher));
+        Ok(true)
+    }
+
+    fn close_current(&mut self) -> Result<(), EncryptionError> {
+        if let Some((mut reader, mut hasher)) = self.current.take() {
+            let index = self.index;
+            let mut tag = [0u8; VOLUME_TAG_LEN as usize];
+            reader
+                .read_exact(&mut tag)
+                .map_err(|_| EncryptionError::CorruptedVolume { index })?;
+            self.cipher
+                .verify_volume_tag(index, &digest(&mut hasher)?, &tag)
+                .map_err(|_| EncryptionError::CorruptedVolume { index })?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.current.is_none() && !self.open_next().map_err(to_io_error)? {
+                return Ok(0);
+            }
+            if self.remaining == 0 {
+                self.close_current().map_err(to_io_error)?;
+                continue;
+            }
+
+            let max = buf.len().min(self.remaining as usize);
+            let index = self.index;
+            let Some((reader, hasher)) = self.current.as_mut() else {
+                continue;
+            };
+            let n = reader.read(&mut buf[..max])?;
+            if n == 0 {
+                return Err(to_io_error(EncryptionError::CorruptedVolume { index }));
+            }
+            hash_update(hasher, &buf[..n])?;
+            self.remaining -= n as u64;
+            return Ok(n);
+        }
+    }
+}
+
+/// Wraps an [`EncryptionError`] so it can be recovered with `range::unwrap_io_error`
+fn to_io_error(e: EncryptionError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{
+        format::ChunkScheme,
+        range::unwrap_io_error,
+        test_util::{test_dir, TestDir},
+    };
+    use orion::hazardous::aead::xchacha20poly1305::SecretKey;
+    use std::fs;
+
+    const NONCE: [u8; NONCE_SIZE] = [9u8; NONCE_SIZE];
+    /// Fills two full volumes and part of a third
+    const STREAM_LEN: usize = 150 * 1024;
+
+    fn key() -> SecretKey {
+        SecretKey::from_slice(&[3u8; 32]).unwrap()
+    }
+
+    fn cipher() -> ChunkCipher {
+        ChunkCipher::new(key(), NONCE, ChunkScheme::V2)
+    }
+
+    fn stream() -> Vec<u8> {
+        (0..STREAM_LEN).map(|i| (i.wrapping_mul(7) ^ (i >> 9)) as u8).collect()
+    }
+
+    /// Splits [`stream`] into volumes in a fresh directory
+    ///
+    /// # Returns
+    /// The directory and the base name of the volumes
+    fn split(name: &str) -> (TestDir, PathBuf) {
+        let dir = test_dir(name);
+        let base = dir.join("data.enc");
+
+        let mut writer = VolumeWriter::new(cipher(), NONCE, &base, STREAM_LEN as u64, MIN_VOLUME_SIZE).unwrap();
+        assert_eq!(writer.count(), 3);
+        writer.write_all(&stream()).unwrap();
+        let paths = writer.finish().unwrap();
+        assert_eq!(paths, (1..=3).map(|index| volume_path(&base, index)).collect::<Vec<_>>());
+        (dir, base)
+    }
+
+    fn read(base: &Path) -> Result<Vec<u8>, EncryptionError> {
+        let mut reader = VolumeReader::open_with_cipher(&volume_path(base, 2), |_| Ok(cipher()))?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(unwrap_io_error)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn joins_the_volumes_from_any_of_them() {
+        let (_dir, base) = split("round-trip");
+        assert_eq!(read(&base).unwrap(), stream());
+    }
+
+    #[test]
+    fn reports_a_missing_volume() {
+        let (_dir, base) = split("missing");
+        fs::remove_file(volume_path(&base, 3)).unwrap();
+        assert!(matches!(read(&base), Err(EncryptionError::MissingVolume { index: 3 })));
+    }
+
+    #[test]
+    fn reports_reordered_volumes() {
+        let (_dir, base) = split("reordered");
+        let (first, second) = (volume_path(&base, 1), volume_path(&base, 2));
+        let swap = base.with_extension("swap");
+        fs::rename(&first, &swap).unwrap();
+        fs::rename(&second, &first).unwrap();
+        fs::rename(&swap, &second).unwrap();
+        assert!(matches!(read(&base), Err(EncryptionError::CorruptedVolume { index: 1 })));
+    }
+
+    #[test]
+    fn reports_a_corrupted_volume_tag() {
+        let (_dir, base) = split("tag");
+        let path = volume_path(&base, 2);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0x01;
+        fs::write(&path, bytes).unwrap();
+        assert!(matches!(read(&base), Err(EncryptionError::CorruptedVolume { index: 2 })));
+    }
+
+    #[test]
+    fn reports_a_damaged_volume_body() {
+        let (_dir, base) = split("body");
+        let path = volume_path(&base, 1);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[VOLUME_HEADER_LEN as usize + 1000] ^= 0x01;
+        fs::write(&path, bytes).unwrap();
+        assert!(matches!(read(&base), Err(EncryptionError::CorruptedVolume { index: 1 })));
+    }
+
+    #[test]
+    fn reports_a_truncated_last_volume() {
+        for keep in [VOLUME_HEADER_LEN + 100, VOLUME_HEADER_LEN + 4] {
+            let (_dir, base) = split(&format!("truncated-{keep}"));
+            let path = volume_path(&base, 3);
+            let bytes = fs::read(&path).unwrap();
+            fs::write(&path, &bytes[..keep as usize]).unwrap();
+            assert!(matches!(read(&base), Err(EncryptionError::CorruptedVolume { index: 3 })), "{keep}");
+        }
+    }
+
+    #[test]
+    fn never_writes_over_existing_volumes() {
+        let (_dir, base) = split("existing");
+        let mut writer = VolumeWriter::new(cipher(), NONCE, &base, STREAM_LEN as u64, MIN_VOLUME_SIZE).unwrap();
+        assert!(writer.write_all(&stream()).is_err());
+        writer.discard();
+        // The set written first is untouched
+        assert_eq!(read(&base).unwrap(), stream());
+    }
+
+    #[test]
+    fn discarding_removes_the_volumes_written_so_far() {
+        let (_dir, base) = split("discard");
+        let other = base.with_file_name("other.enc");
+        let mut writer = VolumeWriter::new(cipher(), NONCE, &other, STREAM_LEN as u64, MIN_VOLUME_SIZE).unwrap();
+        writer.write_all(&stream()[..STREAM_LEN / 2]).unwrap();
+        assert!(volume_path(&other, 2).exists());
+        writer.discard();
+        assert!(!volume_path(&other, 1).exists() && !volume_path(&other, 2).exists());
+
+        // Finishing short of the planned volumes removes them as well
+        let mut writer = VolumeWriter::new(cipher(), NONCE, &other, STREAM_LEN as u64, MIN_VOLUME_SIZE).unwrap();
+        writer.write_all(&stream()[..STREAM_LEN / 2]).unwrap();
+        assert!(writer.finish().is_err());
+        assert!(!volume_path(&other, 1).exists());
+    }
+}
@@ -1,76 +1,276 @@
 use chrono::Local;
-use orion::hazardous::{
-    aead::xchacha20poly1305::{open, seal, Nonce},
-    mac::poly1305::POLY1305_OUTSIZE,
-    stream::chacha20::CHACHA_KEYSIZE,
-};
 use std::{
     fs::{remove_file, File},
-    io::{BufReader, Read, Write, BufWriter},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write, BufWriter},
     path::{Path, PathBuf},
-    time::{Instant, Duration},
+    time::Instant,
 };
-use tauri::{AppHandle, Emitter};
+use tauri::{ipc::Response, AppHandle, Manager};
+use zeroize::Zeroizing;
 
 use crate::{
+    error::AppError,
+    keystore::KeyStore,
     log::add_log_internal,
-    types::{AppResponse, LogLevel, ProcessingStats, ProgressInfo, ResponseTextCode, Status},
+    session::{session_password, CommandPassword, SessionState},
+    types::{
+        AppResponse, DecryptOptions, EncryptOptions, FileFormat, HashAlgorithm, LogLevel, ProcessingStats,
+        ResponseTextCode, SignatureStatus, SignerDetails, Status,
+    },
 };
 
-use super::helpers::{
-    create_key, generate_auth_tag, generate_nonce, validate_password,
-    EncryptionError,
+use super::{
+    age_format,
+    armor::{is_armored, ArmorReader, ArmorWriter},
+    fec::{self, FecParams, ParityEncoder},
+    format::{ChunkCipher, ChunkScheme, FileHeader, EXT_CONTENT_HASH, EXT_ERROR_CORRECTION, EXT_KEY_ID, EXT_SIGNER},
+    hash::{to_hex, verify_content_hash, ContentHasher, CONTENT_HASH_BLOCK_LEN},
+    helpers::{generate_nonce, read_block, read_chunk, validate_password, EncryptionError, CHUNK_SIZE},
+    metadata::FileMetadata,
+    openpgp,
+    output::{
+        decrypted_name, finish_output, finish_volumes, random_name, resolve_output_path, resolve_volume_path,
+        OutputTarget,
+        DEFAULT_DECRYPTION_TEMPLATE, DEFAULT_ENCRYPTION_TEMPLATE,
+    },
+    progress::ProgressEmitter,
+    range,
+    recovery::RecoveryTracker,
+    signature::{self, Manifest, SigningIdentity, SIGNATURE_BLOCK_LEN},
+    volume::{self, is_volume, volume_path, VolumeReader, VolumeWriter, MIN_VOLUME_SIZE, VOLUME_HEADER_LEN},
 };
 
-const CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks for better progress tracking
-const MIN_EVENT_INTERVAL: Duration = Duration::from_millis(30);
+/// Upper bound for a single `decrypt_range` request
+const MAX_RANGE_SIZE: usize = 64 * 1024 * 1024;
 
-/// Creates a unique output path for encrypted/decrypted files
-/// 
-/// Appends timestamp if file already exists
-fn create_unique_output_path(input_path: &Path, is_encryption: bool) -> Result<PathBuf, EncryptionError> {
-    let parent_dir = input_path.parent()
-        .ok_or_else(|| EncryptionError::PasswordValidation("Parent directory not found".to_string()))?;
-
-    if is_encryption {
-        // For encryption: input.pdf -> input.pdf.enc or input_timestamp.pdf.enc
-        let output_path = parent_dir.join(format!("{}.enc", input_path.file_name().unwrap().to_str().unwrap()));
-        if !output_path.exists() {
-            Ok(output_path)
-        } else {
-            let file_stem = input_path.file_stem()
-                .and_then(|stem| stem.to_str())
-                .ok_or_else(|| EncryptionError::PasswordValidation("Invalid filename".to_string()))?;
-            let extension = input_path.extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("");
-            let timestamp = Local::now().format("%Y%m%dT%H%M%S");
-            Ok(parent_dir.join(format!("{}_{}.{}.enc", file_stem, timestamp, extension)))
+/// Destination of the encrypted stream, optionally ASCII-armored or split into volumes
+enum EncryptedWriter {
+    Binary(BufWriter<File>),
+    Armored(ArmorWriter<BufWriter<File>>),
+    /// Boxed, as the writer carries the cipher and a running hash besides the open volume
+    Volumes(Box<VolumeWriter>),
+}
+
+impl EncryptedWriter {
+    /// Writes any trailing armor lines or volume tags and flushes to disk
+    ///
+    /// Everything written is removed if that fails.
+    fn finish(self, path: &Path) -> io::Result<()> {
+        let result = match self {
+            EncryptedWriter::Binary(mut writer) => writer.flush(),
+            EncryptedWriter::Armored(writer) => writer.finish().and_then(|mut writer| writer.flush()),
+            // Removes its volumes itself on failure
+            EncryptedWriter::Volumes(writer) => return writer.finish().map(|_| ()),
+        };
+        if result.is_err() {
+            let _ = remove_file(path);
         }
-    } else {
-        // For decryption: input.pdf.enc -> input.pdf or input_timestamp.pdf
-        let file_stem = input_path.file_stem() // removes .enc
-            .and_then(|stem| Path::new(stem).file_stem()) // removes original extension
-            .and_then(|stem| stem.to_str())
-            .ok_or_else(|| EncryptionError::PasswordValidation("Invalid filename".to_string()))?;
-        
-        let extension = input_path.file_stem() // removes .enc
-            .and_then(|stem| Path::new(stem).extension()) // gets original extension
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("");
-
-        let output_path = parent_dir.join(format!("{}.{}", file_stem, extension));
-        if !output_path.exists() {
-            Ok(output_path)
-        } else {
-            let timestamp = Local::now().format("%Y%m%dT%H%M%S");
-            Ok(parent_dir.join(format!("{}_{}.{}", file_stem, timestamp, extension)))
+        result
+    }
+
+    /// Removes everything written so far, after a failed encryption
+    ///
+    /// `path` is the output file; volumes are tracked by their writer, since
+    /// the base name they are numbered from is never written.
+    fn discard(&mut self, path: &Path) {
+        match self {
+            EncryptedWriter::Binary(_) | EncryptedWriter::Armored(_) => {
+                let _ = remove_file(path);
+            }
+            EncryptedWriter::Volumes(writer) => writer.discard(),
+        }
+    }
+}
+
+impl Write for EncryptedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            EncryptedWriter::Binary(writer) => writer.write(buf),
+            EncryptedWriter::Armored(writer) => writer.write(buf),
+            EncryptedWriter::Volumes(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            EncryptedWriter::Binary(writer) => writer.flush(),
+            EncryptedWriter::Armored(writer) => writer.flush(),
+            EncryptedWriter::Volumes(writer) => writer.flush(),
         }
     }
 }
 
+/// How the encrypted stream read by `decrypt_file` is stored
+enum EncryptedSource {
+    Binary,
+    Armored,
+    /// Split into volumes, with the paths of all volumes in order
+    Volumes(Vec<PathBuf>),
+}
+
+/// Opens an encrypted file for reading, decoding ASCII armor or joining volumes if needed
+/// 
+/// # Arguments
+/// * `file` - The encrypted file
+/// * `file_path` - Path of `file`, used to find the other volumes of a split file
+/// * `password` - Verifies the volume tags of split files
+/// * `recover` - Decode damaged armor as far as possible instead of failing
+///
+/// # Returns
+/// The binary reader and how the stream is stored
+fn open_encrypted_reader(
+    mut file: File,
+    file_path: &Path,
+    password: &str,
+    recover: bool,
+) -> Result<(Box<dyn Read + Send>, EncryptedSource), EncryptionError> {
+    let mut head = [0u8; 64];
+    let n = read_chunk(&mut file, &mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if is_volume(&head[..n]) {
+        let reader = VolumeReader::open(file_path, password)?;
+        let paths = reader.paths().to_vec();
+        Ok((Box::new(reader), EncryptedSource::Volumes(paths)))
+    } else if is_armored(&head[..n]) {
+        let reader = ArmorReader::new(BufReader::new(file))?;
+        let reader = if recover { reader.recovering() } else { reader };
+        Ok((Box::new(reader), EncryptedSource::Armored))
+    } else {
+        Ok((Box::new(BufReader::new(file)), EncryptedSource::Binary))
+    }
+}
+
+/// Reads the file header without a password, e.g. to find its key id
+fn peek_header(file_path: &Path) -> Result<FileHeader, EncryptionError> {
+    let mut file = File::open(file_path)?;
+    let mut head = [0u8; 64];
+    let n = read_chunk(&mut file, &mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if is_volume(&head[..n]) {
+        let base = volume::base_path(file_path)
+            .ok_or_else(|| EncryptionError::InvalidFormat("Volume name must end in a volume number".to_string()))?;
+        let mut first = File::open(volume_path(&base, 1)).map_err(|_| EncryptionError::MissingVolume { index: 1 })?;
+        first.seek(SeekFrom::Start(VOLUME_HEADER_LEN))?;
+        FileHeader::read(&mut BufReader::new(first))
+    } else if is_armored(&head[..n]) {
+        FileHeader::read(&mut ArmorReader::new(BufReader::new(file))?.without_checksum())
+    } else {
+        FileHeader::read(&mut BufReader::new(file))
+    }
+}
+
+/// Format of an encrypted file as found by [`detect_format`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DetectedFormat {
+    /// This app's format, with or without a header
+    Cipher(ChunkScheme),
+    Age,
+    OpenPgp,
+}
+
+/// Sniffs the format of an encrypted file from its first bytes
+///
+/// Legacy files have no magic bytes, so any file that matches none of the
+/// other formats is reported as a legacy file.
+pub(crate) fn detect_format(file_path: &Path) -> Result<DetectedFormat, EncryptionError> {
+    if age_format::is_age_file(file_path) {
+        return Ok(DetectedFormat::Age);
+    }
+    if openpgp::is_openpgp_file(file_path) {
+        return Ok(DetectedFormat::OpenPgp);
+    }
+    peek_header(file_path).map(|header| DetectedFormat::Cipher(header.scheme))
+}
+
+/// Looks up the password of a file whose header names a key remembered in the keystore
+fn stored_password(app: &AppHandle, file_path: &Path) -> Option<Zeroizing<String>> {
+    let header = peek_header(file_path).ok()?;
+    header.extension(EXT_KEY_ID).and_then(|id| app.state::<KeyStore>().password_for(id))
+}
+
+/// Resolves the password to decrypt `file_path` with
+///
+/// An empty `password` looks up the file's remembered key in the keystore
+/// first, then falls back to the unlocked session's password.
+pub(crate) fn decryption_password(app: &AppHandle, file_path: &Path, password: &str) -> Result<CommandPassword, AppResponse> {
+    let stored = password.is_empty().then(|| stored_password(app, file_path)).flatten();
+    match stored {
+        Some(password) => Ok(CommandPassword::new(&password)),
+        None => session_password(app, password),
+    }
+}
+
+/// Checks that a file can be decrypted in place with [`range::DecryptReader`]
+///
+/// Random access needs a plain binary file in the cipher format. age and
+/// OpenPGP messages, armored and split files are only decrypted as a stream
+/// by `decrypt_file`, so they are rejected here instead of failing to decrypt
+/// like a legacy file with the wrong password. Unreadable files pass, so the
+/// reader reports the precise error.
+pub(crate) fn check_random_access(file_path: &Path) -> Result<(), EncryptionError> {
+    let unsupported = |kind: &str| Err(EncryptionError::UnsupportedFormat(format!("{} can only be decrypted to a file", kind)));
+    match detect_format(file_path) {
+        Ok(DetectedFormat::Age) => return unsupported("age files"),
+        Ok(DetectedFormat::OpenPgp) => return unsupported("OpenPGP files"),
+        Ok(DetectedFormat::Cipher(_)) | Err(_) => {}
+    }
+
+    let mut head = [0u8; 64];
+    let n = match File::open(file_path).and_then(|mut file| read_chunk(&mut file, &mut head)) {
+        Ok(n) => n,
+        Err(_) => return Ok(()),
+    };
+    if is_armored(&head[..n]) {
+        return unsupported("armored files");
+    }
+    if is_volume(&head[..n]) {
+        return unsupported("split files");
+    }
+    Ok(())
+}
+
+/// Reads and checks what follows the chunks: the content hash and the signature
+///
+/// # Arguments
+/// * `reader` - Positioned right after the last chunk
+/// * `parity_len` - Bytes of parity to skip before the trailer
+///
+/// # Returns
+/// The verified content hash as hex and the signer's fingerprint, if the file has them
+fn verify_trailer<R: Read>(
+    reader: &mut R,
+    cipher: &ChunkCipher,
+    parity_len: u64,
+    content_hasher: Option<ContentHasher>,
+    signature: Option<(Vec<u8>, Manifest)>,
+) -> Result<(Option<String>, Option<String>), EncryptionError> {
+    if content_hasher.is_none() && signature.is_none() {
+        return Ok((None, None));
+    }
+    io::copy(&mut reader.by_ref().take(parity_len), &mut io::sink())?;
+
+    let content_hash = match content_hasher {
+        Some(hasher) => {
+            let stored = cipher.open_content_hash(&read_block(reader, CONTENT_HASH_BLOCK_LEN)?)?;
+            Some(verify_content_hash(&stored, hasher)?)
+        }
+        None => None,
+    };
+    let signer_fingerprint = match signature {
+        Some((public_key, manifest)) => {
+            let signature = cipher.open_signature(&read_block(reader, SIGNATURE_BLOCK_LEN)?)?;
+            signature::verify(&public_key, manifest, &signature)?;
+            Some(signature::fingerprint(&public_key))
+        }
+        None => None,
+    };
+    Ok((content_hash, signer_fingerprint))
+}
+
 /// Creates an error response with logging
-fn create_error_response(code: ResponseTextCode, file_path: Option<String>) -> AppResponse {
+pub(crate) fn create_error_response(code: ResponseTextCode, file_path: Option<String>) -> AppResponse {
     add_log_internal(LogLevel::Error, code.clone(), file_path.clone()).ok();
     AppResponse {
         status: Status::Error,
@@ -78,23 +278,49 @@ fn create_error_response(code: ResponseTextCode, file_path: Option<String>) -> A
         file_path: file_path,
         timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
         stats: None,
+        recovery: None,
+        detail: None,
+        signer: None,
     }
 }
 
 /// Creates a success response with logging
-fn create_success_response(code: ResponseTextCode, file_path: String, stats: ProcessingStats) -> AppResponse {
+pub(crate) fn create_success_response(code: ResponseTextCode, file_path: String, stats: ProcessingStats) -> AppResponse {
     add_log_internal(LogLevel::Info, code.clone(), Some(file_path.clone())).ok();
     AppResponse {
         text_code: code,
         status: Status::Success,
         file_path: Some(file_path),
         timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
-        stats: Some(stats),
+        stats: Some(Box::new(stats)),
+        recovery: None,
+        detail: None,
+        signer: None,
+    }
+}
+
+/// Creates a response for a file left alone because its output already exists
+pub(crate) fn create_skipped_response(existing_path: &Path) -> AppResponse {
+    create_info_response(ResponseTextCode::FileSkipped, existing_path.display().to_string())
+}
+
+/// Creates a success response without stats with logging
+pub(crate) fn create_info_response(code: ResponseTextCode, file_path: String) -> AppResponse {
+    add_log_internal(LogLevel::Info, code.clone(), Some(file_path.clone())).ok();
+    AppResponse {
+        status: Status::Success,
+        text_code: code,
+        file_path: Some(file_path),
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        stats: None,
+        recovery: None,
+        detail: None,
+        signer: None,
     }
 }
 
 /// Sanitizes a file path for use in event names
-fn sanitize_path(path: &str) -> String {
+pub(crate) fn sanitize_path(path: &str) -> String {
     path.chars()
         .map(|c| {
             if c.is_alphanumeric() || c == '-' || c == '/' || c == ':' || c == '_' {
@@ -106,107 +332,300 @@ fn sanitize_path(path: &str) -> String {
         .collect()
 }
 
+/// Encrypts a file, or writes age or OpenPGP if `options.format` asks for it
+///
+/// # Events
+/// `encryption_progress_<path>` with a [`crate::types::ProgressInfo`] while the file is encrypted
 #[tauri::command]
 pub async fn encrypt_file(
     app: AppHandle,
     file_path: &str,
     password: &str,
     delete_original: bool,
+    options: Option<EncryptOptions>,
+) -> Result<AppResponse, AppResponse> {
+    let options = options.unwrap_or_default();
+    match options.format {
+        FileFormat::Cipher => {}
+        FileFormat::Age => {
+            return age_format::encrypt_age_file(&app, file_path, password, delete_original, &options);
+        }
+        FileFormat::OpenPgp => {
+            let password = session_password(&app, password)?;
+            let mut progress = ProgressEmitter::new(&app, "encryption", file_path, 0);
+            return openpgp::encrypt_openpgp_file(&mut progress, file_path, &password, delete_original, &options);
+        }
+    }
+    // Inside a remembered scope the keystore supplies the password and the header gets its key id
+    let remembered = app.state::<KeyStore>().remembered_key(Path::new(file_path));
+    let password = match &remembered {
+        Some(key) if password.is_empty() => CommandPassword::new(&key.password),
+        _ => session_password(&app, password)?,
+    };
+    let key_id = remembered.filter(|key| key.matches(&password)).map(|key| key.id);
+
+    let mut progress = ProgressEmitter::new(&app, "encryption", file_path, 0);
+    encrypt_cipher_file(&mut progress, &app.state::<SessionState>(), file_path, &password, key_id, delete_original, &options)
+}
+
+/// Encrypts `file_path` into the cipher format
+///
+/// # Arguments
+/// * `session` - Supplies the key salt and cached root key if `password` is the session password
+/// * `key_id` - Id of the remembered key `password` belongs to, stored in the header
+pub(crate) fn encrypt_cipher_file(
+    progress: &mut ProgressEmitter,
+    session: &SessionState,
+    file_path: &str,
+    password: &str,
+    key_id: Option<Vec<u8>>,
+    delete_original: bool,
+    options: &EncryptOptions,
 ) -> Result<AppResponse, AppResponse> {
     let start_time = Instant::now();
-    let mut last_event = Instant::now();
-    
     validate_password(password).map_err(|_| {
         create_error_response(ResponseTextCode::InvalidPassword, None)
     })?;
 
-    let source_file = File::open(file_path).map_err(|_| {
-        create_error_response(ResponseTextCode::FileOpenFailed, Some(file_path.to_string()))
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
     })?;
 
     let file_size = source_file.metadata().map(|m| m.len()).unwrap_or(0) as usize;
     let mut bytes_processed = 0;
+    progress.set_total(file_size);
 
     // Generate encryption key and nonce first
-    let nonce = generate_nonce();
-    let key = create_key(password, nonce.clone()).map_err(|_| {
+    let mut header = FileHeader::new(generate_nonce());
+    session.set_key_salt(&mut header, password);
+    let cipher = session.cipher(&header, password).map_err(|_| {
         create_error_response(ResponseTextCode::KeyGenerationFailed, None)
     })?;
-    let nonce_obj = Nonce::from_slice(nonce.as_slice()).unwrap();
-
-    // Create output file with proper naming
-    let output_path = create_unique_output_path(Path::new(file_path), true)
-        .map_err(|_| create_error_response(ResponseTextCode::FileNameExtractionFailed, Some(file_path.to_string())))?;
-    let output_path_str = output_path.display().to_string();
-    println!("Output path: {}", output_path_str);
+    let fec = options.error_correction
+        .map(|ratio| FecParams::new(ratio.data_chunks, ratio.parity_chunks))
+        .transpose()
+        .map_err(|e| AppError::InvalidOptions(e.to_string()).into_response(Some(file_path.to_string())))?;
+    match options.volume_size {
+        Some(size) if size < MIN_VOLUME_SIZE => {
+            return Err(AppError::InvalidOptions(format!("Volumes must be at least {} bytes", MIN_VOLUME_SIZE))
+                .into_response(Some(file_path.to_string())));
+        }
+        Some(_) if options.armored => {
+            return Err(AppError::InvalidOptions("Armored output can't be split into volumes".to_string())
+                .into_response(Some(file_path.to_string())));
+        }
+        _ => {}
+    }
+    if let Some(params) = fec {
+        header.set_extension(EXT_ERROR_CORRECTION, params.to_extension());
+    }
+    if let Some(id) = key_id {
+        header.set_extension(EXT_KEY_ID, id);
+    }
+    let signer = options.signing_key.as_ref()
+        .map(|signing| {
+            SigningIdentity::load(Path::new(&signing.key_path), &signing.password)
+                .map_err(|e| AppError::from(e).into_response(Some(signing.key_path.clone())))
+        })
+        .transpose()?;
+    if let Some(signer) = &signer {
+        header.set_extension(EXT_SIGNER, signer.public_key().to_vec());
+    }
+    if options.record_hash {
+        header.set_extension(EXT_CONTENT_HASH, Vec::new());
+    }
 
-    let mut writer = BufWriter::new(File::create(&output_path).map_err(|_| {
-        create_error_response(ResponseTextCode::FileCreationFailed, Some(output_path_str.clone()))
-    })?);
+    let mut metadata = FileMetadata::from_path(Path::new(file_path), options.preserve_xattrs);
+    // Decryption locates the parity from the plaintext size
+    metadata.size.get_or_insert(file_size as u64);
+    header.seal_metadata(&cipher, &metadata).map_err(|_| {
+        create_error_response(ResponseTextCode::EncryptionFailed, Some(file_path.to_string()))
+    })?;
+    let mut manifest = signer.as_ref()
+        .map(|_| Manifest::new(&header))
+        .transpose()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    let mut content_hasher = options.record_hash
+        .then(|| ContentHasher::new(HashAlgorithm::Blake2b))
+        .transpose()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
 
-    // Write nonce first
-    writer.write_all(nonce.as_slice()).map_err(|_| {
-        let _ = remove_file(&output_path);
+    // Create output file with proper naming
+    let input_name = if options.encrypt_file_name {
+        random_name()
+    } else {
+        Path::new(file_path)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .ok_or_else(|| create_error_response(ResponseTextCode::FileNameExtractionFailed, Some(file_path.to_string())))?
+    };
+    // For volumes these are base names that the volume numbers are appended to
+    let target = match options.volume_size {
+        Some(_) => resolve_volume_path(Path::new(file_path), &input_name, &options.output, DEFAULT_ENCRYPTION_TEMPLATE),
+        None => resolve_output_path(Path::new(file_path), &input_name, &options.output, DEFAULT_ENCRYPTION_TEMPLATE),
+    };
+    let (output_path, destination) = match target.map_err(|e| e.into_response(Some(file_path.to_string())))? {
+        OutputTarget::Create(path) => (path.clone(), path),
+        OutputTarget::Replace { temp, destination } => (temp, destination),
+        OutputTarget::Skip(existing_path) => return Ok(create_skipped_response(&existing_path)),
+    };
+    let output_path_str = match options.volume_size {
+        Some(_) => volume_path(&destination, 1).display().to_string(),
+        None => destination.display().to_string(),
+    };
+
+    let mut writer = if let Some(volume_size) = options.volume_size {
+        // The volume count is fixed up front, so the whole stream length is computed here
+        let total_len = header.encoded_len()
+            + fec::data_section_len(file_size as u64, cipher.scheme())
+            + fec.map_or(0, |params| fec::parity_len(file_size as u64, cipher.scheme(), params))
+            + if options.record_hash { CONTENT_HASH_BLOCK_LEN } else { 0 }
+            + signer.as_ref().map_or(0, |_| SIGNATURE_BLOCK_LEN);
+        let volume_cipher = session.cipher(&header, password).map_err(|_| {
+            create_error_response(ResponseTextCode::KeyGenerationFailed, None)
+        })?;
+        EncryptedWriter::Volumes(Box::new(
+            VolumeWriter::new(volume_cipher, header.nonce, &output_path, total_len, volume_size)
+                .map_err(|e| AppError::InvalidOptions(e.to_string()).into_response(Some(file_path.to_string())))?,
+        ))
+    } else if options.armored {
+        let output_file = BufWriter::new(File::create(&output_path).map_err(|e| {
+            AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+        })?);
+        EncryptedWriter::Armored(ArmorWriter::new(output_file).map_err(|_| {
+            let _ = remove_file(&output_path);
+            create_error_response(ResponseTextCode::EncryptionFailed, Some(output_path_str.clone()))
+        })?)
+    } else {
+        EncryptedWriter::Binary(BufWriter::new(File::create(&output_path).map_err(|e| {
+            AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+        })?))
+    };
+
+    // Write header first
+    header.write(&mut writer).map_err(|_| {
+        writer.discard(&output_path);
         create_error_response(ResponseTextCode::EncryptionFailed, Some(output_path_str.clone()))
     })?;
 
+    let mut parity = fec
+        .map(|params| ParityEncoder::new(params, &output_path))
+        .transpose()
+        .map_err(|e| {
+            writer.discard(&output_path);
+            AppError::from(e).into_response(Some(output_path_str.clone()))
+        })?;
+
     // Process file in chunks
     let mut reader = BufReader::new(source_file);
     let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut chunk_index = 0;
 
-    while let Ok(n) = reader.read(&mut buffer) {
+    loop {
+        let n = read_chunk(&mut reader, &mut buffer).map_err(|e| {
+            writer.discard(&output_path);
+            AppError::io(ResponseTextCode::FileReadFailed, e).into_response(Some(file_path.to_string()))
+        })?;
         if n == 0 { break; }
-        let chunk = &buffer[..n];
+
+        if let Some(manifest) = manifest.as_mut() {
+            manifest.push_chunk(&buffer[..n]).map_err(|e| {
+                writer.discard(&output_path);
+                AppError::from(e).into_response(Some(file_path.to_string()))
+            })?;
+        }
+        if let Some(hasher) = content_hasher.as_mut() {
+            hasher.update(&buffer[..n]).map_err(|e| {
+                writer.discard(&output_path);
+                AppError::from(e).into_response(Some(file_path.to_string()))
+            })?;
+        }
 
         // Encrypt chunk
-        let ad = generate_auth_tag();
-        let mut output = vec![0u8; n + POLY1305_OUTSIZE + ad.len()];
-        output[..CHACHA_KEYSIZE].copy_from_slice(ad.as_ref());
-        
-        seal(&key, &nonce_obj, chunk, Some(ad.as_slice()), &mut output[CHACHA_KEYSIZE..]).map_err(|_| {
+        let output = cipher.seal_chunk(chunk_index, &buffer[..n]).map_err(|_| {
+            writer.discard(&output_path);
             create_error_response(ResponseTextCode::EncryptionFailed, Some(output_path_str.clone()))
         })?;
+        chunk_index += 1;
 
         // Write encrypted chunk
-        writer.write_all(&output).map_err(|_| {
-            create_error_response(ResponseTextCode::EncryptionFailed, Some(output_path_str.clone()))
+        writer.write_all(&output).map_err(|e| {
+            writer.discard(&output_path);
+            AppError::io(ResponseTextCode::EncryptionFailed, e).into_response(Some(output_path_str.clone()))
         })?;
+        if let Some(parity) = parity.as_mut() {
+            parity.push(&output).map_err(|e| {
+                writer.discard(&output_path);
+                AppError::from(e).into_response(Some(output_path_str.clone()))
+            })?;
+        }
 
         bytes_processed += n;
 
-        if last_event.elapsed() >= MIN_EVENT_INTERVAL {
-            let progress = (bytes_processed as f64 / file_size as f64) * 100.0;
-            let elapsed = start_time.elapsed().as_secs_f64();
-            let speed = bytes_processed as f64 / (1024.0 * 1024.0) / elapsed;
-            
-            let event_name = format!("encryption_progress_{}", sanitize_path(file_path));
-            app.emit(&event_name, &ProgressInfo {
-                percentage: progress,
-                bytes_processed,
-                total_bytes: file_size,
-                speed_mbps: speed,
-                elapsed_seconds: elapsed,
-                estimated_remaining_seconds: if speed > 0.0 {
-                    (file_size - bytes_processed) as f64 / (speed * 1024.0 * 1024.0)
-                } else {
-                    0.0
-                },
-            }).unwrap();
-            last_event = Instant::now();
+        progress.update(bytes_processed);
+    }
+
+    // The parity and trailer offsets and the volume count would not match a file that changed while being read
+    let has_trailer = fec.is_some() || signer.is_some() || options.record_hash;
+    if (has_trailer || options.volume_size.is_some()) && bytes_processed != file_size {
+        writer.discard(&output_path);
+        return Err(AppError::Format(format!(
+            "File changed during encryption: expected {} bytes, read {}",
+            file_size, bytes_processed
+        )).into_response(Some(file_path.to_string())));
+    }
+    if let Some(parity) = parity.take() {
+        parity.finish(&mut writer).map_err(|e| {
+            writer.discard(&output_path);
+            AppError::from(e).into_response(Some(output_path_str.clone()))
+        })?;
+    }
+    let content_hash = match content_hasher.take() {
+        Some(hasher) => {
+            let hash = hasher.finish()
+                .and_then(|hash| cipher.seal_content_hash(&hash).map(|sealed| (hash, sealed)))
+                .and_then(|(hash, sealed)| writer.write_all(&sealed).map(|_| hash).map_err(EncryptionError::from))
+                .map_err(|e| {
+                    writer.discard(&output_path);
+                    AppError::from(e).into_response(Some(output_path_str.clone()))
+                })?;
+            Some(to_hex(&hash))
         }
+        None => None,
+    };
+    if let (Some(signer), Some(manifest)) = (signer, manifest.take()) {
+        signer.sign(manifest)
+            .and_then(|signature| cipher.seal_signature(&signature))
+            .and_then(|sealed| writer.write_all(&sealed).map_err(EncryptionError::from))
+            .map_err(|e| {
+                writer.discard(&output_path);
+                AppError::from(e).into_response(Some(output_path_str.clone()))
+            })?;
     }
 
-    writer.flush().map_err(|_| {
-        let _ = remove_file(&output_path);
+    let volume_count = match &writer {
+        EncryptedWriter::Volumes(writer) => Some(writer.count()),
+        _ => None,
+    };
+    writer.finish(&output_path).map_err(|_| {
         create_error_response(ResponseTextCode::EncryptionFailed, Some(output_path_str.clone()))
     })?;
+    let finished = match volume_count {
+        Some(count) => finish_volumes(&output_path, &destination, count),
+        None => finish_output(&output_path, &destination).inspect_err(|_| {
+            let _ = remove_file(&output_path);
+        }),
+    };
+    finished.map_err(|e| {
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
 
     let total_time = start_time.elapsed().as_secs_f64();
     let average_speed = file_size as f64 / (1024.0 * 1024.0) / total_time;
 
     if delete_original {
-        remove_file(file_path).map_err(|_| {
-            create_error_response(ResponseTextCode::FileDeleteFailed, Some(file_path.to_string()))
+        remove_file(file_path).map_err(|e| {
+            AppError::io(ResponseTextCode::FileDeleteFailed, e).into_response(Some(file_path.to_string()))
         })?;
     }
 
@@ -217,121 +636,349 @@ pub async fn encrypt_file(
             total_size_bytes: file_size,
             processing_time_seconds: total_time,
             average_speed_mbps: average_speed,
+            content_hash,
         }
     ))
 }
 
+/// Decrypts a file in the cipher, age or OpenPGP format
+///
+/// # Events
+/// `decryption_progress_<path>` with a [`crate::types::ProgressInfo`] while the file is decrypted
 #[tauri::command]
 pub async fn decrypt_file(
     app: AppHandle,
     file_path: &str,
     password: &str,
     delete_original: bool,
+    options: Option<DecryptOptions>,
+) -> Result<AppResponse, AppResponse> {
+    let options = options.unwrap_or_default();
+    match detect_format(Path::new(file_path)) {
+        Ok(DetectedFormat::Age) => {
+            return age_format::decrypt_age_file(&app, file_path, password, delete_original, &options);
+        }
+        Ok(DetectedFormat::OpenPgp) => {
+            let password = session_password(&app, password)?;
+            let mut progress = ProgressEmitter::new(&app, "decryption", file_path, 0);
+            return openpgp::decrypt_openpgp_file(&mut progress, file_path, &password, delete_original, &options);
+        }
+        // Legacy files share the chunk loop through their chunk scheme;
+        // unreadable files fail there with the precise error
+        Ok(DetectedFormat::Cipher(_)) | Err(_) => {}
+    }
+    // Files with a key id find their password in the keystore
+    let password = decryption_password(&app, Path::new(file_path), password)?;
+
+    let mut progress = ProgressEmitter::new(&app, "decryption", file_path, 0);
+    decrypt_cipher_file(&mut progress, &app.state::<SessionState>(), file_path, &password, delete_original, &options)
+}
+
+/// Decrypts a file in the cipher format, current or legacy
+///
+/// # Arguments
+/// * `session` - Supplies cached keys if `password` is the session password
+pub(crate) fn decrypt_cipher_file(
+    progress: &mut ProgressEmitter,
+    session: &SessionState,
+    file_path: &str,
+    password: &str,
+    delete_original: bool,
+    options: &DecryptOptions,
 ) -> Result<AppResponse, AppResponse> {
     let start_time = Instant::now();
-    let mut last_event = Instant::now();
-    
     validate_password(password).map_err(|_| {
         create_error_response(ResponseTextCode::InvalidPassword, None)
     })?;
 
-    let mut source_file = File::open(file_path).map_err(|_| {
-        create_error_response(ResponseTextCode::FileOpenFailed, Some(file_path.to_string()))
+    let source_file = File::open(file_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(file_path.to_string()))
     })?;
 
     let file_size = source_file.metadata().map(|m| m.len()).unwrap_or(0) as usize;
     let mut bytes_processed = 0;
 
-    // Read nonce (first 24 bytes)
-    let mut nonce_bytes = vec![0u8; 24];
-    source_file.read_exact(&mut nonce_bytes).map_err(|_| {
-        create_error_response(ResponseTextCode::DecryptionFailed, Some(file_path.to_string()))
+    let (mut reader, source) = open_encrypted_reader(source_file, Path::new(file_path), password, options.recovery.is_some())
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    let file_size = match &source {
+        EncryptedSource::Binary => file_size,
+        // Base64 stores 3 bytes in 4 characters, so progress is measured against the decoded size
+        EncryptedSource::Armored => file_size / 4 * 3,
+        EncryptedSource::Volumes(paths) => paths
+            .iter()
+            .filter_map(|path| path.metadata().ok())
+            .map(|metadata| metadata.len() as usize)
+            .sum(),
+    };
+    progress.set_total(file_size);
+    // Split files are named after the joined file, without the volume number
+    let name_path = match &source {
+        EncryptedSource::Volumes(_) => volume::base_path(Path::new(file_path)).unwrap_or_else(|| PathBuf::from(file_path)),
+        _ => PathBuf::from(file_path),
+    };
+
+    // Read header (nonce only for legacy files)
+    let header = FileHeader::read(&mut reader).map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
     })?;
 
-    let key = create_key(password, nonce_bytes.clone()).map_err(|_| {
+    let cipher = session.cipher(&header, password).map_err(|_| {
         create_error_response(ResponseTextCode::KeyGenerationFailed, None)
     })?;
-    let nonce = Nonce::from_slice(&nonce_bytes).unwrap();
-
-    // Create output file with proper naming
-    let output_path = create_unique_output_path(Path::new(file_path), false)
-        .map_err(|_| create_error_response(ResponseTextCode::FileNameExtractionFailed, Some(file_path.to_string())))?;
-    let output_path_str = output_path.display().to_string();
+    // Checks the password before any output is created
+    let metadata = header.open_metadata(&cipher).map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
 
-    let mut writer = BufWriter::new(File::create(&output_path).map_err(|_| {
-        create_error_response(ResponseTextCode::FileCreationFailed, Some(output_path_str.clone()))
+    let fec = header.extension(EXT_ERROR_CORRECTION)
+        .map(|value| FecParams::from_extension(value).map(|params| (params, metadata.size.unwrap_or_default())))
+        .transpose()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    let signer_key = header.extension(EXT_SIGNER).map(<[u8]>::to_vec);
+    let mut manifest = signer_key.as_ref()
+        .map(|_| Manifest::new(&header))
+        .transpose()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    let mut content_hasher = header.extension(EXT_CONTENT_HASH)
+        .map(|_| ContentHasher::new(HashAlgorithm::Blake2b))
+        .transpose()
+        .map_err(|e| AppError::from(e).into_response(Some(file_path.to_string())))?;
+    // Parity, content hash and signature follow the chunks, so only the chunk section is streamed
+    let data_len = (fec.is_some() || signer_key.is_some() || content_hasher.is_some())
+        .then(|| fec::data_section_len(metadata.size.unwrap_or_default(), cipher.scheme()));
+    let mut chunks = (&mut reader).take(data_len.unwrap_or(u64::MAX));
+    // Repairs seek to the parity through a second handle, which only plain files provide
+    let mut repair_source = fec
+        .filter(|_| matches!(source, EncryptedSource::Binary))
+        .and_then(|_| File::open(file_path).ok());
+    let mut repaired_chunks = 0;
+
+    // Create output file with proper naming, preferring the name stored in the file
+    let output_name = match metadata.safe_name() {
+        Some(name) => name,
+        None => decrypted_name(&name_path)
+            .map_err(|e| e.into_response(Some(file_path.to_string())))?,
+    };
+    let (output_path, destination) = match resolve_output_path(&name_path, &output_name, &options.output, DEFAULT_DECRYPTION_TEMPLATE)
+        .map_err(|e| e.into_response(Some(file_path.to_string())))?
+    {
+        OutputTarget::Create(path) => (path.clone(), path),
+        OutputTarget::Replace { temp, destination } => (temp, destination),
+        OutputTarget::Skip(existing_path) => return Ok(create_skipped_response(&existing_path)),
+    };
+    let output_path_str = destination.display().to_string();
+
+    let mut writer = BufWriter::new(File::create(&output_path).map_err(|e| {
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
     })?);
 
     // Process file in chunks
-    let mut reader = BufReader::new(source_file);
-    let chunk_size = CHUNK_SIZE + CHACHA_KEYSIZE + POLY1305_OUTSIZE;
-    let mut buffer = vec![0u8; chunk_size];
+    let mut buffer = vec![0u8; cipher.scheme().encrypted_chunk_size()];
+    let mut chunk_index = 0;
+    let mut plaintext_len: u64 = 0;
+    let mut recovery = options.recovery.map(RecoveryTracker::new);
 
-    while let Ok(n) = reader.read(&mut buffer) {
-        if n == 0 { break; }
-        let chunk = &buffer[..n];
-
-        // Split chunk into auth tag and encrypted data
-        let (ad, encrypted_data) = chunk.split_at(CHACHA_KEYSIZE);
-        
-        // Decrypt chunk
-        let mut decrypted = vec![0u8; encrypted_data.len() - POLY1305_OUTSIZE];
-        if let Err(_) = open(&key, &nonce, encrypted_data, Some(ad), &mut decrypted) {
+    loop {
+        let n = read_chunk(&mut chunks, &mut buffer).map_err(|e| {
             let _ = remove_file(&output_path);
-            return Err(create_error_response(ResponseTextCode::InvalidPassword, Some(file_path.to_string())));
-        }
+            AppError::io(ResponseTextCode::FileReadFailed, e).into_response(Some(file_path.to_string()))
+        })?;
+        if n == 0 { break; }
+
+        // Decrypt chunk, rebuilding it from the parity if it fails to authenticate
+        let opened = cipher.open_chunk(chunk_index, &buffer[..n]).or_else(|e| match (fec, repair_source.as_mut()) {
+            (Some((params, size)), Some(source)) => {
+                let repaired = fec::repair_chunk(source, params, &cipher, header.encoded_len(), size, chunk_index)?;
+                repaired_chunks += 1;
+                Ok(repaired)
+            }
+            _ => Err(e),
+        });
+        // Plaintext size of chunks that fail to decrypt
+        let damaged_len = n.saturating_sub(cipher.scheme().overhead()) as u64;
+        let (decrypted, chunk_len) = match (opened, recovery.as_mut()) {
+            (Ok(decrypted), recovery) => {
+                if let Some(manifest) = manifest.as_mut() {
+                    manifest.push_chunk(&decrypted).map_err(|e| {
+                        let _ = remove_file(&output_path);
+                        AppError::from(e).into_response(Some(file_path.to_string()))
+                    })?;
+                }
+                if let Some(hasher) = content_hasher.as_mut() {
+                    hasher.update(&decrypted).map_err(|e| {
+                        let _ = remove_file(&output_path);
+                        AppError::from(e).into_response(Some(file_path.to_string()))
+                    })?;
+                }
+                let chunk_len = decrypted.len() as u64;
+                if let Some(recovery) = recovery {
+                    recovery.recovered(chunk_len);
+                }
+                (decrypted, chunk_len)
+            }
+            (Err(_), Some(recovery)) => {
+                (recovery.damaged_chunk(chunk_index, plaintext_len, damaged_len), damaged_len)
+            }
+            (Err(e), None) => {
+                let _ = remove_file(&output_path);
+                // A chunk that ends before the stored size says it should was cut off, not damaged
+                let is_cut = metadata.size.is_some_and(|size| {
+                    n < buffer.len() && ((bytes_processed + n) as u64) < fec::data_section_len(size, cipher.scheme())
+                });
+                let error = if is_cut { EncryptionError::TruncatedFile } else { e };
+                return Err(AppError::from(error).into_response(Some(file_path.to_string())));
+            }
+        };
+        chunk_index += 1;
+        plaintext_len += chunk_len;
 
         // Write decrypted chunk
-        writer.write_all(&decrypted).map_err(|_| {
+        writer.write_all(&decrypted).map_err(|e| {
             let _ = remove_file(&output_path);
-            create_error_response(ResponseTextCode::DecryptionFailed, Some(file_path.to_string()))
+            AppError::io(ResponseTextCode::DecryptionFailed, e).into_response(Some(file_path.to_string()))
         })?;
 
         bytes_processed += n;
 
-        if last_event.elapsed() >= MIN_EVENT_INTERVAL {
-            let progress = (bytes_processed as f64 / file_size as f64) * 100.0;
-            let elapsed = start_time.elapsed().as_secs_f64();
-            let speed = bytes_processed as f64 / (1024.0 * 1024.0) / elapsed;
-            
-            let event_name = format!("decryption_progress_{}", sanitize_path(file_path));
-            app.emit(&event_name, &ProgressInfo {
-                percentage: progress,
-                bytes_processed,
-                total_bytes: file_size,
-                speed_mbps: speed,
-                elapsed_seconds: elapsed,
-                estimated_remaining_seconds: if speed > 0.0 {
-                    (file_size - bytes_processed) as f64 / (speed * 1024.0 * 1024.0)
-                } else {
-                    0.0
-                },
-            }).unwrap();
-            last_event = Instant::now();
-        }
+        progress.update(bytes_processed);
     }
 
-    writer.flush().map_err(|_| {
+    let output_file = writer.into_inner().map_err(|_| {
         let _ = remove_file(&output_path);
         create_error_response(ResponseTextCode::DecryptionFailed, Some(output_path_str.clone()))
     })?;
 
+    if let Some(recovery) = recovery.as_mut() {
+        if let Some(size) = metadata.size {
+            recovery.missing_tail(plaintext_len, size);
+        }
+        // Nothing decrypted at all: for legacy files this is the only sign of a wrong password
+        if !recovery.has_recovered_data() && recovery.is_damaged() {
+            drop(output_file);
+            let _ = remove_file(&output_path);
+            let error = match cipher.scheme() {
+                ChunkScheme::Legacy => EncryptionError::WrongPassword,
+                ChunkScheme::V2 => EncryptionError::CorruptedChunk { index: 0 },
+            };
+            return Err(AppError::from(error).into_response(Some(file_path.to_string())));
+        }
+    }
+
+    // Trailing chunks missing at a chunk boundary would otherwise go unnoticed
+    if let Some(size) = metadata.size.filter(|size| recovery.is_none() && *size != plaintext_len) {
+        drop(output_file);
+        let _ = remove_file(&output_path);
+        let error = if plaintext_len < size {
+            AppError::from(EncryptionError::TruncatedFile)
+        } else {
+            AppError::Format(format!("Expected {} bytes but decrypted {}", size, plaintext_len))
+        };
+        return Err(error.into_response(Some(file_path.to_string())));
+    }
+
+    let is_damaged = recovery.as_ref().is_some_and(RecoveryTracker::is_damaged);
+
+    // Damaged chunks are missing from the hash and the manifest, so recovered files can't be
+    // verified; a signed file says so explicitly rather than looking unsigned
+    let signature_status = signer_key.as_ref().map(|_| {
+        if is_damaged { SignatureStatus::Unverified } else { SignatureStatus::Verified }
+    });
+    let verified = if is_damaged {
+        Ok((None, None))
+    } else {
+        let parity_len = fec.map_or(0, |(params, size)| fec::parity_len(size, cipher.scheme(), params));
+        verify_trailer(&mut reader, &cipher, parity_len, content_hasher, signer_key.zip(manifest))
+    };
+    let (content_hash, signer_fingerprint) = match verified {
+        Ok(verified) => verified,
+        Err(e) => {
+            drop(output_file);
+            let _ = remove_file(&output_path);
+            return Err(AppError::from(e).into_response(Some(file_path.to_string())));
+        }
+    };
+
+    if options.restore_metadata && metadata.apply(&output_file, &output_path).is_err() {
+        add_log_internal(LogLevel::Warning, ResponseTextCode::MetadataRestoreFailed, Some(output_path_str.clone())).ok();
+    }
+    drop(output_file);
+    finish_output(&output_path, &destination).map_err(|e| {
+        let _ = remove_file(&output_path);
+        AppError::io(ResponseTextCode::FileCreationFailed, e).into_response(Some(output_path_str.clone()))
+    })?;
+
     let total_time = start_time.elapsed().as_secs_f64();
     let average_speed = file_size as f64 / (1024.0 * 1024.0) / total_time;
 
-    if delete_original {
-        remove_file(file_path).map_err(|_| {
-            create_error_response(ResponseTextCode::FileDeleteFailed, Some(file_path.to_string()))
-        })?;
+    // A damaged source is the only copy of the lost regions, so it is never deleted
+    if delete_original && !is_damaged {
+        let source_paths = match source {
+            EncryptedSource::Volumes(paths) => paths,
+            _ => vec![PathBuf::from(file_path)],
+        };
+        for path in source_paths {
+            remove_file(&path).map_err(|e| {
+                AppError::io(ResponseTextCode::FileDeleteFailed, e).into_response(Some(path.display().to_string()))
+            })?;
+        }
     }
 
-    Ok(create_success_response(
-        ResponseTextCode::DecryptionSuccessful,
+    let code = if is_damaged {
+        ResponseTextCode::PartiallyRecovered
+    } else if repaired_chunks > 0 {
+        ResponseTextCode::ChunksRepaired
+    } else {
+        ResponseTextCode::DecryptionSuccessful
+    };
+    let mut response = create_success_response(
+        code,
         output_path_str,
         ProcessingStats {
             total_size_bytes: file_size,
             processing_time_seconds: total_time,
             average_speed_mbps: average_speed,
+            content_hash,
         }
-    ))
+    );
+    response.recovery = recovery.map(|recovery| Box::new(recovery.into_report()));
+    response.signer = signature_status.map(|signature| Box::new(SignerDetails { signer_fingerprint, signature }));
+    Ok(response)
+}
+
+/// Decrypts a byte range of an encrypted file without writing anything to disk
+/// 
+/// Only the chunks covering `offset..offset + length` are read and decrypted,
+/// which makes previewing the start of large files cheap. The bytes are
+/// returned as a raw IPC response (`ArrayBuffer` on the frontend).
+#[tauri::command]
+pub async fn decrypt_range(
+    app: AppHandle,
+    file_path: &str,
+    password: &str,
+    offset: u64,
+    length: usize,
+) -> Result<Response, AppResponse> {
+    let password = session_password(&app, password)?;
+    let password = password.as_str();
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+
+    if length > MAX_RANGE_SIZE {
+        return Err(create_error_response(ResponseTextCode::DecryptionFailed, Some(file_path.to_string())));
+    }
+    check_random_access(Path::new(file_path)).map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
+
+    let bytes = range::decrypt_range(
+        Path::new(file_path),
+        |header| app.state::<SessionState>().cipher(header, password),
+        offset,
+        length,
+    ).map_err(|e| {
+        AppError::from(e).into_response(Some(file_path.to_string()))
+    })?;
+
+    Ok(Response::new(bytes))
 }
@@ -0,0 +1,114 @@
+use chrono::Local;
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+use crate::{
+    encryption::helpers::EncryptionError,
+    keystore::store::is_unavailable,
+    log::add_log_internal,
+    types::{AppResponse, LogLevel, ResponseTextCode, Status},
+};
+
+/// Crate-wide error type for command paths
+///
+/// Every variant maps to a [`ResponseTextCode`] for the UI, and its message is
+/// sent along as `detail`. Messages describe what failed and where, but never
+/// include passwords, keys or decrypted content.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{code}: {source}")]
+    Io {
+        code: ResponseTextCode,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Invalid file format: {0}")]
+    Format(String),
+    #[error(transparent)]
+    Crypto(#[from] EncryptionError),
+    #[error("Database error: {0}")]
+    Db(#[from] duckdb::Error),
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("Invalid path {}: {reason}", path.display())]
+    Path { path: PathBuf, reason: String },
+    #[error("Output file already exists: {}", .0.display())]
+    OutputExists(PathBuf),
+    #[error("Invalid output name template: {0}")]
+    InvalidTemplate(String),
+    #[error("Invalid options: {0}")]
+    InvalidOptions(String),
+    #[error("No logs to download")]
+    NoLogs,
+    /// A thread panicked while holding the lock around this state
+    #[error("{0} is unavailable after an earlier failure")]
+    Poisoned(&'static str),
+}
+
+impl AppError {
+    /// Wraps an I/O error with the code describing the failed operation
+    pub fn io(code: ResponseTextCode, source: io::Error) -> Self {
+        AppError::Io { code, source }
+    }
+
+    /// Creates a path error
+    pub fn path(path: impl Into<PathBuf>, reason: &str) -> Self {
+        AppError::Path {
+            path: path.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The code shown to the user for this error
+    pub fn text_code(&self) -> ResponseTextCode {
+        match self {
+            AppError::Io { code, .. } => code.clone(),
+            AppError::Format(_) => ResponseTextCode::DecryptionFailed,
+            AppError::Crypto(e) => match e {
+                EncryptionError::PasswordValidation(_) => ResponseTextCode::InvalidPassword,
+                EncryptionError::WrongPassword => ResponseTextCode::WrongPassword,
+                EncryptionError::Decryption | EncryptionError::CorruptedChunk { .. } => ResponseTextCode::CorruptedChunk,
+                EncryptionError::TruncatedFile => ResponseTextCode::TruncatedFile,
+                EncryptionError::UnsupportedFormat(_) => ResponseTextCode::UnsupportedFormat,
+                EncryptionError::MissingVolume { .. } => ResponseTextCode::MissingVolume,
+                EncryptionError::CorruptedVolume { .. } => ResponseTextCode::CorruptedVolume,
+                EncryptionError::EntryNotFound(_) => ResponseTextCode::VaultEntryNotFound,
+                EncryptionError::PasswordCreation
+                | EncryptionError::SaltCreation
+                | EncryptionError::KeyDerivation
+                | EncryptionError::SecretKeyCreation
+                | EncryptionError::NonceCreation => ResponseTextCode::KeyGenerationFailed,
+                EncryptionError::Encryption => ResponseTextCode::EncryptionFailed,
+                EncryptionError::InvalidFormat(_) => ResponseTextCode::DecryptionFailed,
+                EncryptionError::InvalidSignature => ResponseTextCode::InvalidSignature,
+                EncryptionError::HashMismatch => ResponseTextCode::HashMismatch,
+                EncryptionError::Io(_) => ResponseTextCode::FileReadFailed,
+            },
+            AppError::Db(_) => ResponseTextCode::DbConnFailed,
+            AppError::Keyring(e) if is_unavailable(e) => ResponseTextCode::KeyringUnavailable,
+            AppError::Keyring(_) => ResponseTextCode::KeyringFailed,
+            AppError::Path { .. } => ResponseTextCode::FileNameExtractionFailed,
+            AppError::OutputExists(_) => ResponseTextCode::FileAlreadyExists,
+            AppError::InvalidTemplate(_) => ResponseTextCode::InvalidNameTemplate,
+            AppError::InvalidOptions(_) => ResponseTextCode::InvalidOptions,
+            AppError::NoLogs => ResponseTextCode::NoLogs,
+            AppError::Poisoned(_) => ResponseTextCode::StateUnavailable,
+        }
+    }
+
+    /// Creates the error response for this error with logging
+    pub fn into_response(self, file_path: Option<String>) -> AppResponse {
+        let code = self.text_code();
+        add_log_internal(LogLevel::Error, code.clone(), file_path.clone()).ok();
+        AppResponse {
+            status: Status::Error,
+            text_code: code,
+            file_path,
+            timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            stats: None,
+            recovery: None,
+            detail: Some(self.to_string()),
+            signer: None,
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod store;
+
+pub use store::{forget_key, init, remember_key, KeyStore};
@@ -0,0 +1,460 @@
+use keyring::Entry;
+use orion::util::secure_cmp;
+use rand_core::{OsRng, RngCore};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+use tauri::{AppHandle, Manager, State};
+use zeroize::Zeroizing;
+
+use crate::{
+    encryption::{
+        hash::to_hex,
+        helpers::validate_password,
+        xchacha20::{create_error_response, create_info_response},
+    },
+    error::AppError,
+    types::{AppResponse, ResponseTextCode},
+};
+
+/// Keyring service all entries are stored under
+const SERVICE: &str = "cipher";
+/// Entry holding the remembered scopes, a JSON object of paths to key ids
+const SCOPES_ENTRY: &str = "scopes";
+/// Length of the random key ids written to the `EXT_KEY_ID` header extension
+pub const KEY_ID_LEN: usize = 16;
+
+/// Storage for named secrets
+///
+/// [`OsKeyring`] is the real one. Tests use in-memory stand-ins.
+pub trait KeyBackend: Send + Sync {
+    /// The secret stored under `name`, `None` if there is none
+    fn get(&self, name: &str) -> keyring::Result<Option<Zeroizing<String>>>;
+
+    fn set(&self, name: &str, secret: &str) -> keyring::Result<()>;
+
+    /// Removes `name`, returning whether it existed
+    fn delete(&self, name: &str) -> keyring::Result<bool>;
+}
+
+/// True for errors meaning the keyring can't be reached at all, e.g. no Secret Service on the session bus
+pub(crate) fn is_unavailable(e: &keyring::Error) -> bool {
+    matches!(e, keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_))
+}
+
+/// The OS keyring: the Secret Service over D-Bus on Linux, the Keychain on
+/// macOS and the Credential Manager on Windows
+pub struct OsKeyring {
+    service: &'static str,
+}
+
+impl Default for OsKeyring {
+    fn default() -> Self {
+        Self { service: SERVICE }
+    }
+}
+
+impl KeyBackend for OsKeyring {
+    fn get(&self, name: &str) -> keyring::Result<Option<Zeroizing<String>>> {
+        match Entry::new(self.service, name)?.get_password() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn set(&self, name: &str, secret: &str) -> keyring::Result<()> {
+        Entry::new(self.service, name)?.set_password(secret)
+    }
+
+    fn delete(&self, name: &str) -> keyring::Result<bool> {
+        match Entry::new(self.service, name)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A password remembered in the keystore
+pub struct RememberedKey {
+    pub id: Vec<u8>,
+    pub password: Zeroizing<String>,
+}
+
+impl RememberedKey {
+    /// True if `password` is the remembered password
+    pub fn matches(&self, password: &str) -> bool {
+        secure_cmp(self.password.as_bytes(), password.as_bytes()).is_ok()
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn key_name(id: &[u8]) -> String {
+    format!("key:{}", to_hex(id))
+}
+
+/// Remembered scopes, by canonical path
+type Scopes = HashMap<PathBuf, String>;
+
+/// Passwords remembered for files and folders, managed as Tauri state
+///
+/// The keyring holds `key:<id>` entries with the passwords and one `scopes`
+/// entry mapping each remembered file or folder to the id of its key. Files
+/// encrypted within a scope carry the key id in their header, so they can be
+/// decrypted without the password as long as the key entry exists. Key ids are
+/// random and reveal nothing about the password or the path.
+///
+/// Passwords are only ever stored in the OS keyring; without one, remembering
+/// fails and every file needs its password. The scopes are read once and
+/// cached, so finding the key for a file costs one lookup of its password.
+///
+/// On Linux the keyring is the Secret Service on the D-Bus session bus, which
+/// can be a stand-in daemon such as a `gnome-keyring-daemon` started with
+/// `dbus-run-session`.
+pub struct KeyStore {
+    backend: Box<dyn KeyBackend>,
+    /// `None` until loaded
+    scopes: Mutex<Option<Scopes>>,
+}
+
+impl KeyStore {
+    pub fn new(backend: Box<dyn KeyBackend>) -> Self {
+        Self {
+            backend,
+            scopes: Mutex::new(None),
+        }
+    }
+
+    /// Locks the scope cache, loading it if needed
+    fn scopes(&self) -> keyring::Result<MutexGuard<'_, Option<Scopes>>> {
+        // A panic mid-update leaves the cache unknown, so it is reloaded
+        let mut cache = self.scopes.lock().unwrap_or_else(|poisoned| {
+            self.scopes.clear_poison();
+            let mut cache = poisoned.into_inner();
+            *cache = None;
+            cache
+        });
+        if cache.is_none() {
+            *cache = Some(read_scopes(self.backend.as_ref())?);
+        }
+        Ok(cache)
+    }
+
+    /// Looks up the password for a key id from a file header
+    ///
+    /// # Returns
+    /// `None` if the key was never remembered, was forgotten, or the keyring can't be reached
+    pub fn password_for(&self, id: &[u8]) -> Option<Zeroizing<String>> {
+        self.backend.get(&key_name(id)).ok().flatten()
+    }
+
+    /// Finds the key remembered for `path` or the closest folder above it
+    pub fn remembered_key(&self, path: &Path) -> Option<RememberedKey> {
+        let path = fs::canonicalize(path).ok()?;
+        let id = {
+            let cache = self.scopes().ok()?;
+            let scopes = cache.as_ref()?;
+            path.ancestors().find_map(|scope| scopes.get(scope).cloned())?
+        };
+        let id = from_hex(&id)?;
+        let password = self.password_for(&id)?;
+        Some(RememberedKey { id, password })
+    }
+
+    /// Stores `password` for `scope`, replacing the key remembered before
+    ///
+    /// Files encrypted under the previous key keep their id in the header; its entry
+    /// is removed, so they need the password again.
+    pub fn remember(&self, scope: &Path, password: &str) -> keyring::Result<()> {
+        let mut cache = self.scopes()?;
+        let result = self.forget_locked(&mut cache, scope).and_then(|_| {
+            let mut id = [0u8; KEY_ID_LEN];
+            OsRng.fill_bytes(&mut id);
+            store_key(self.backend.as_ref(), scope, &to_hex(&id), password)
+        });
+        // Reloaded on next use
+        *cache = None;
+        result
+    }
+
+    /// Removes the key remembered for `scope` and its scope entry
+    ///
+    /// # Returns
+    /// Whether anything was remembered for `scope`
+    pub fn forget(&self, scope: &Path) -> keyring::Result<bool> {
+        let mut cache = self.scopes()?;
+        let result = self.forget_locked(&mut cache, scope);
+        *cache = None;
+        result
+    }
+
+    fn forget_locked(&self, cache: &mut Option<Scopes>, scope: &Path) -> keyring::Result<bool> {
+        let Some(scopes) = cache.as_mut() else {
+            return Ok(false);
+        };
+        let Some(id) = scopes.remove(scope) else {
+            return Ok(false);
+        };
+        if let Some(id) = from_hex(&id) {
+            self.backend.delete(&key_name(&id))?;
+        }
+        write_scopes(self.backend.as_ref(), scopes)?;
+        Ok(true)
+    }
+}
+
+fn read_scopes(backend: &dyn KeyBackend) -> keyring::Result<Scopes> {
+    match backend.get(SCOPES_ENTRY)? {
+        Some(json) => serde_json::from_str(&json).map_err(|e| keyring::Error::BadEncoding(e.to_string().into_bytes())),
+        None => Ok(Scopes::new()),
+    }
+}
+
+fn write_scopes(backend: &dyn KeyBackend, scopes: &Scopes) -> keyring::Result<()> {
+    let json = serde_json::to_string(scopes).map_err(|e| keyring::Error::BadEncoding(e.to_string().into_bytes()))?;
+    backend.set(SCOPES_ENTRY, &json)
+}
+
+/// Stores the key, then adds the scope to the backend's current scopes
+fn store_key(backend: &dyn KeyBackend, scope: &Path, id: &str, password: &str) -> keyring::Result<()> {
+    let name = format!("key:{}", id);
+    backend.set(&name, password)?;
+    let result = read_scopes(backend).and_then(|mut scopes| {
+        scopes.insert(scope.to_path_buf(), id.to_string());
+        write_scopes(backend, &scopes)
+    });
+    if result.is_err() {
+        let _ = backend.delete(&name);
+    }
+    result
+}
+
+/// Manages the keystore on top of the OS keyring
+pub fn init(app: &AppHandle) {
+    app.manage(KeyStore::new(Box::<OsKeyring>::default()));
+}
+
+/// Resolves `path` to the canonical form scopes are stored under
+fn scope_path(path: &str) -> Result<PathBuf, AppResponse> {
+    fs::canonicalize(path)
+        .map_err(|e| AppError::io(ResponseTextCode::FileOpenFailed, e).into_response(Some(path.to_string())))
+}
+
+/// Remembers `password` for a file or every file in a folder
+///
+/// Files encrypted within the scope with this password get its key id, and
+/// `decrypt_file` then finds the password in the keystore when none is given.
+/// Encrypting within the scope without a password uses the remembered one.
+/// Without a reachable keyring nothing is stored and `keyring_unavailable` is
+/// returned.
+#[tauri::command]
+pub async fn remember_key(state: State<'_, KeyStore>, path: &str, password: &str) -> Result<AppResponse, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+    let scope = scope_path(path)?;
+    state
+        .remember(&scope, password)
+        .map_err(|e| AppError::from(e).into_response(Some(path.to_string())))?;
+    Ok(create_info_response(ResponseTextCode::KeyRemembered, path.to_string()))
+}
+
+/// Removes the password remembered for a file or folder from the keystore
+#[tauri::command]
+pub async fn forget_key(state: State<'_, KeyStore>, path: &str) -> Result<AppResponse, AppResponse> {
+    let scope = scope_path(path)?;
+    match state.forget(&scope) {
+        Ok(true) => Ok(create_info_response(ResponseTextCode::KeyForgotten, path.to_string())),
+        Ok(false) => Err(create_error_response(ResponseTextCode::KeyNotRemembered, Some(path.to_string()))),
+        Err(e) => Err(AppError::from(e).into_response(Some(path.to_string()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::{test_dir, TestDir, PASSWORD};
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    /// Stands in for the keyring daemon, counting lookups
+    #[derive(Default, Clone)]
+    struct MemoryKeyring {
+        entries: Arc<Mutex<HashMap<String, String>>>,
+        gets: Arc<AtomicUsize>,
+    }
+
+    impl KeyBackend for MemoryKeyring {
+        fn get(&self, name: &str) -> keyring::Result<Option<Zeroizing<String>>> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            Ok(self.entries.lock().unwrap().get(name).cloned().map(Zeroizing::new))
+        }
+
+        fn set(&self, name: &str, secret: &str) -> keyring::Result<()> {
+            self.entries.lock().unwrap().insert(name.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, name: &str) -> keyring::Result<bool> {
+            Ok(self.entries.lock().unwrap().remove(name).is_some())
+        }
+    }
+
+    /// A keyring whose daemon isn't running
+    struct UnavailableKeyring;
+
+    fn no_daemon() -> keyring::Error {
+        keyring::Error::NoStorageAccess(Box::new(io::Error::new(io::ErrorKind::NotConnected, "no session bus")))
+    }
+
+    impl KeyBackend for UnavailableKeyring {
+        fn get(&self, _: &str) -> keyring::Result<Option<Zeroizing<String>>> {
+            Err(no_daemon())
+        }
+
+        fn set(&self, _: &str, _: &str) -> keyring::Result<()> {
+            Err(no_daemon())
+        }
+
+        fn delete(&self, _: &str) -> keyring::Result<bool> {
+            Err(no_daemon())
+        }
+    }
+
+    /// A fresh directory with `folder/sub/file.enc`
+    ///
+    /// # Returns
+    /// The directory, and the folder and file paths made canonical
+    fn setup(name: &str) -> (TestDir, PathBuf, PathBuf) {
+        let dir = test_dir(name);
+        fs::create_dir_all(dir.join("folder/sub")).unwrap();
+        fs::write(dir.join("folder/sub/file.enc"), b"").unwrap();
+        let canonical = fs::canonicalize(&dir).unwrap();
+        (dir, canonical.join("folder"), canonical.join("folder/sub/file.enc"))
+    }
+
+    #[test]
+    fn stores_and_looks_up_keys_by_scope_and_id() {
+        let (_dir, folder, file) = setup("lookup");
+        let store = KeyStore::new(Box::new(MemoryKeyring::default()));
+
+        assert!(store.remembered_key(&file).is_none());
+        store.remember(&folder, PASSWORD).unwrap();
+
+        let key = store.remembered_key(&file).unwrap();
+        assert_eq!(key.id.len(), KEY_ID_LEN);
+        assert!(key.matches(PASSWORD));
+        assert!(!key.matches("Keystore-Test-2!"));
+        assert_eq!(store.password_for(&key.id).unwrap().as_str(), PASSWORD);
+        assert!(store.password_for(&[0u8; KEY_ID_LEN]).is_none());
+        assert!(store.remembered_key(folder.parent().unwrap()).is_none());
+    }
+
+    #[test]
+    fn the_closest_scope_wins() {
+        let (_dir, folder, file) = setup("closest");
+        let store = KeyStore::new(Box::new(MemoryKeyring::default()));
+        store.remember(&folder, PASSWORD).unwrap();
+        store.remember(file.parent().unwrap(), "Keystore-Test-2!").unwrap();
+
+        assert!(store.remembered_key(&file).unwrap().matches("Keystore-Test-2!"));
+        assert!(store.remembered_key(&folder).unwrap().matches(PASSWORD));
+    }
+
+    #[test]
+    fn forgets_the_key_and_its_scope() {
+        let (_dir, folder, file) = setup("forget");
+        let keyring = MemoryKeyring::default();
+        let store = KeyStore::new(Box::new(keyring.clone()));
+        store.remember(&folder, PASSWORD).unwrap();
+        let id = store.remembered_key(&file).unwrap().id;
+
+        assert!(store.forget(&folder).unwrap());
+        assert!(store.remembered_key(&file).is_none());
+        assert!(store.password_for(&id).is_none());
+        assert!(!store.forget(&folder).unwrap());
+        assert_eq!(keyring.entries.lock().unwrap().len(), 1, "only the empty scope list is left");
+    }
+
+    #[test]
+    fn remembering_again_replaces_the_old_key() {
+        let (_dir, folder, file) = setup("replace");
+        let store = KeyStore::new(Box::new(MemoryKeyring::default()));
+        store.remember(&folder, PASSWORD).unwrap();
+        let old_id = store.remembered_key(&file).unwrap().id;
+
+        store.remember(&folder, "Keystore-Test-2!").unwrap();
+
+        assert!(store.password_for(&old_id).is_none());
+        assert!(store.remembered_key(&file).unwrap().matches("Keystore-Test-2!"));
+    }
+
+    #[test]
+    fn reads_the_scopes_once() {
+        let (_dir, folder, file) = setup("cache");
+        let keyring = MemoryKeyring::default();
+        let store = KeyStore::new(Box::new(keyring.clone()));
+        store.remember(&folder, PASSWORD).unwrap();
+
+        store.remembered_key(&file).unwrap();
+        let gets = keyring.gets.load(Ordering::SeqCst);
+        store.remembered_key(&file).unwrap();
+        store.remembered_key(&folder).unwrap();
+
+        // One lookup of the password per call, none of the scopes
+        assert_eq!(keyring.gets.load(Ordering::SeqCst), gets + 2);
+    }
+
+    #[test]
+    fn reports_an_unavailable_keyring() {
+        let (_dir, folder, file) = setup("unavailable");
+        let store = KeyStore::new(Box::new(UnavailableKeyring));
+
+        let error = store.remember(&folder, PASSWORD).unwrap_err();
+        assert!(matches!(AppError::from(error).text_code(), ResponseTextCode::KeyringUnavailable));
+        assert!(matches!(store.forget(&folder), Err(keyring::Error::NoStorageAccess(_))));
+        assert!(store.remembered_key(&file).is_none());
+        assert!(store.password_for(&[1u8; KEY_ID_LEN]).is_none());
+    }
+
+    /// Runs against the Secret Service on the session bus, e.g.
+    /// `dbus-run-session -- sh -c 'echo | gnome-keyring-daemon --unlock && cargo test -- --ignored secret_service'`
+    ///
+    /// Entries go to a separate service, so a real keyring is left alone.
+    #[test]
+    #[ignore = "needs a Secret Service daemon on the session bus"]
+    fn remembers_keys_in_a_secret_service_daemon() {
+        let (_dir, folder, file) = setup("secret-service");
+        let store = KeyStore::new(Box::new(OsKeyring { service: "cipher-test" }));
+        store.remember(&folder, PASSWORD).unwrap();
+        let key = store.remembered_key(&file).unwrap();
+        assert!(key.matches(PASSWORD));
+
+        // A new store reads the daemon, like the next app start
+        let store = KeyStore::new(Box::new(OsKeyring { service: "cipher-test" }));
+        assert_eq!(store.password_for(&key.id).unwrap().as_str(), PASSWORD);
+
+        assert!(store.forget(&folder).unwrap());
+        assert!(store.remembered_key(&file).is_none());
+        assert!(store.password_for(&key.id).is_none());
+        OsKeyring { service: "cipher-test" }.delete(SCOPES_ENTRY).unwrap();
+    }
+}
@@ -1,23 +1,59 @@
 pub mod encryption;
+pub mod error;
+pub mod keystore;
 pub mod log;
 pub mod network;
+pub mod session;
 pub mod stripe;
 pub mod types;
+pub mod watch;
 
-use encryption::{decrypt_file, encrypt_file};
+use encryption::{
+    add_to_vault, create_vault, decrypt_clipboard, decrypt_file, decrypt_range, decrypt_text,
+    delete_from_vault, encrypt_clipboard, encrypt_file, encrypt_text, extract_from_vault, generate_signing_key,
+    hash_file, list_vault, preview_file, upgrade_file,
+};
+use keystore::{forget_key, remember_key};
 use log::duckdb::{add_log, clear_logs, download_logs, get_logs};
 use ::log::LevelFilter;
+use tauri::{Manager, WindowEvent};
 use network::check_network;
+use session::{cancel_blur_lock, get_session_status, lock_on_blur, lock_session, spawn_expiry_check, unlock_session, SessionState};
 use stripe::get_stripe_client_secret;
+use watch::{start_watch, stop_watch, WatchState};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(WatchState::default())
+        .manage(SessionState::default())
         .invoke_handler(tauri::generate_handler![
             encrypt_file,
             decrypt_file,
+            decrypt_range,
+            preview_file,
+            hash_file,
+            upgrade_file,
+            encrypt_text,
+            decrypt_text,
+            encrypt_clipboard,
+            decrypt_clipboard,
+            create_vault,
+            list_vault,
+            add_to_vault,
+            extract_from_vault,
+            delete_from_vault,
+            generate_signing_key,
+            start_watch,
+            stop_watch,
+            unlock_session,
+            lock_session,
+            get_session_status,
+            remember_key,
+            forget_key,
             get_stripe_client_secret,
             check_network,
             add_log,
@@ -25,6 +61,11 @@ pub fn run() {
             clear_logs,
             download_logs
         ])
+        .on_window_event(|window, event| match event {
+            WindowEvent::Focused(false) => lock_on_blur(window.app_handle()),
+            WindowEvent::Focused(true) => cancel_blur_lock(window.app_handle()),
+            _ => {}
+        })
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -33,6 +74,12 @@ pub fn run() {
                         .build(),
                 )?;
             }
+            // Without the log database, e.g. while another instance holds its lock, the app runs unlogged
+            if let Err(e) = log::init(app.handle()) {
+                ::log::error!("Running without the log database: {e}");
+            }
+            keystore::init(app.handle());
+            spawn_expiry_check(app.handle().clone());
             Ok(())
         })
         .run(tauri::generate_context!())
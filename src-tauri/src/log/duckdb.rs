@@ -1,46 +1,151 @@
-use duckdb::{params, Connection, Error};
-use crate::types::{AppResponse, ResponseTextCode, Status, Log, LogLevel, LogsResponse};
+use duckdb::{params, types::Type, Connection, Error, Row};
+use crate::{
+    error::AppError,
+    types::{AppResponse, ResponseTextCode, Status, Log, LogLevel, LogsResponse},
+};
 use chrono::{Utc, Local};
-use std::fs;
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+use tauri::{AppHandle, Manager, State};
+
+use super::schema::migrate;
+
+/// File name of the log database inside the app data directory
+const DATABASE_FILE: &str = "logs.db";
+
+/// Lets code without an `AppHandle` reach the managed [`LogDatabase`], set by [`init`]
+static LOG_APP: OnceLock<AppHandle> = OnceLock::new();
+
+/// The log database, opened once at startup and managed as Tauri state
+///
+/// DuckDB connections can't be shared between threads, so the single
+/// connection sits behind a mutex.
+pub struct LogDatabase {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl LogDatabase {
+    /// Opens or creates the database at `path` and migrates it to the current schema
+    pub fn open(path: PathBuf) -> Result<Self, AppError> {
+        let connection = Connection::open(&path)?;
+        migrate(&connection)?;
+        Ok(Self {
+            path,
+            connection: Mutex::new(connection),
+        })
+    }
 
-use super::schema::create_tables;
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>, AppError> {
+        self.connection.lock().map_err(|_| AppError::Poisoned("Log database"))
+    }
+
+    fn insert(&self, level: LogLevel, text_code: ResponseTextCode, file_path: Option<String>) -> Result<(), AppError> {
+        let current_time = Utc::now().timestamp();
+        self.connection()?.execute(
+            "INSERT INTO logs (timestamp, level, text_code, file_path) VALUES (?, ?, ?, ?)",
+            params![current_time, level.as_ref(), text_code.as_ref(), file_path],
+        )?;
+        Ok(())
+    }
 
-fn open_database() -> Result<Connection, Error> {
-    let connection = Connection::open("logs.db")?;
-    create_tables(&connection)?;
-    Ok(connection)
+    /// Size of the database file in bytes
+    fn size(&self) -> i64 {
+        fs::metadata(&self.path)
+            .map(|metadata| metadata.len() as i64)
+            .unwrap_or(0)
+    }
 }
 
-pub fn add_log_internal(level: LogLevel, text_code: ResponseTextCode, file_path: Option<String>) -> Result<(), String> {
-    let connection = open_database().map_err(|e| {
-        println!("Database error: {e}");
-        format!("Failed to open database: {e}")
-    })?;
-    
-    let current_time = Utc::now().timestamp();
-    connection.execute(
-        "INSERT INTO logs (timestamp, level, text_code, file_path) VALUES (?, ?, ?, ?)",
-        params![current_time, level.as_ref(), text_code.as_ref(), file_path],
-    ).map_err(|e| {
-        println!("Failed to insert log: {e}");
-        format!("Failed to insert log: {e}")
+/// Opens the log database in the app data directory and manages it as state
+///
+/// Runs once at startup, so logging works regardless of the directory the app
+/// was launched from.
+pub fn init(app: &AppHandle) -> Result<(), AppError> {
+    let data_dir = app.path().app_data_dir().map_err(|e| {
+        AppError::io(ResponseTextCode::DbConnFailed, io::Error::new(io::ErrorKind::NotFound, e.to_string()))
     })?;
-    
+    fs::create_dir_all(&data_dir).map_err(|e| AppError::io(ResponseTextCode::DbConnFailed, e))?;
+    app.manage(LogDatabase::open(data_dir.join(DATABASE_FILE))?);
+    LOG_APP.set(app.clone()).ok();
     Ok(())
 }
 
-#[tauri::command]
-pub fn add_log(level: LogLevel, text_code: ResponseTextCode) -> Result<AppResponse, AppResponse> {
-    let error_response = || AppResponse {
-        text_code: ResponseTextCode::DbConnFailed,
+/// Parses a stored enum column, reporting unknown values as conversion errors
+fn parse_column<T>(row: &Row, index: usize) -> Result<T, Error>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    row.get::<_, String>(index)?
+        .parse()
+        .map_err(|e| Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn read_logs(connection: &Connection) -> Result<Vec<Log>, Error> {
+    let mut stmt = connection
+        .prepare("SELECT timestamp, level, text_code, file_path FROM logs ORDER BY timestamp DESC, id DESC")?;
+
+    let logs = stmt
+        .query_map([], |row| {
+            Ok(Log {
+                timestamp: row.get(0)?,
+                level: parse_column(row, 1)?,
+                text_code: parse_column(row, 2)?,
+                file_path: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(logs)
+}
+
+/// The error for log access when the database didn't open at startup
+fn not_open() -> AppError {
+    AppError::io(
+        ResponseTextCode::DbConnFailed,
+        io::Error::new(io::ErrorKind::NotFound, "Log database is not open"),
+    )
+}
+
+/// The managed log database, which is missing if it failed to open at startup
+fn database(app: &AppHandle) -> Result<State<'_, LogDatabase>, AppResponse> {
+    app.try_state::<LogDatabase>().ok_or_else(|| db_error_response(not_open()))
+}
+
+pub fn add_log_internal(level: LogLevel, text_code: ResponseTextCode, file_path: Option<String>) -> Result<(), AppError> {
+    let database = LOG_APP.get().and_then(|app| app.try_state::<LogDatabase>()).ok_or_else(not_open)?;
+    database.insert(level, text_code, file_path).inspect_err(|e| {
+        ::log::error!("Failed to insert log: {e}");
+    })
+}
+
+/// Creates a database error response without logging it, since the log itself is what failed
+fn db_error_response(e: AppError) -> AppResponse {
+    ::log::error!("Log database: {e}");
+    AppResponse {
+        text_code: e.text_code(),
         status: Status::Error,
         file_path: None,
         timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
         stats: None,
-    };
+        recovery: None,
+        detail: Some(e.to_string()),
+        signer: None,
+    }
+}
 
-    add_log_internal(level, text_code.clone(), None)
-        .map_err(|_| error_response())?;
+#[tauri::command]
+pub fn add_log(
+    app: AppHandle,
+    level: LogLevel,
+    text_code: ResponseTextCode,
+) -> Result<AppResponse, AppResponse> {
+    database(&app)?
+        .insert(level, text_code.clone(), None)
+        .map_err(db_error_response)?;
 
     Ok(AppResponse {
         text_code,
@@ -48,69 +153,32 @@ pub fn add_log(level: LogLevel, text_code: ResponseTextCode) -> Result<AppRespon
         file_path: None,
         timestamp: Utc::now().to_rfc3339(),
         stats: None,
+        recovery: None,
+        detail: None,
+        signer: None,
     })
 }
 
 #[tauri::command]
-pub fn get_logs() -> Result<LogsResponse, AppResponse> {
-    let error_response = |e: &str| {
-        println!("{e}");
-        AppResponse {
-            text_code: ResponseTextCode::DbConnFailed,
-            status: Status::Error,
-            file_path: None,
-            timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
-            stats: None,
-        }
-    };
-
-    let connection = open_database().map_err(|e| error_response(&e.to_string()))?;
-
-    let mut stmt = connection
-        .prepare("SELECT timestamp, level, text_code, file_path FROM logs ORDER BY timestamp DESC")
-        .map_err(|e| error_response(&e.to_string()))?;
-
-    let logs = stmt
-        .query_map([], |row| {
-            Ok(Log {
-                timestamp: row.get(0)?,
-                level: row.get::<_, String>(1)?.parse().unwrap(),
-                text_code: row.get::<_, String>(2)?.parse().unwrap(),
-                file_path: row.get(3)?,
-            })
-        })
-        .map_err(|e| error_response(&e.to_string()))?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| error_response(&e.to_string()))?;
-
-    let db_size = fs::metadata("logs.db")
-        .map(|metadata| metadata.len() as i64)
-        .unwrap_or(0);
+pub fn get_logs(app: AppHandle) -> Result<LogsResponse, AppResponse> {
+    let database = database(&app)?;
+    let connection = database.connection().map_err(db_error_response)?;
+    let logs = read_logs(&connection).map_err(|e| db_error_response(e.into()))?;
+    drop(connection);
 
     Ok(LogsResponse {
         logs,
-        db_size,
+        db_size: database.size(),
     })
 }
 
 #[tauri::command]
-pub fn clear_logs() -> Result<AppResponse, AppResponse> {
-    let error_response = |e: &str| {
-        println!("{e}");
-        AppResponse {
-            text_code: ResponseTextCode::DbConnFailed,
-            status: Status::Error,
-            file_path: None,
-            timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
-            stats: None,
-        }
-    };
-
-    let connection = open_database().map_err(|e| error_response(&e.to_string()))?;
-
-    connection
+pub fn clear_logs(app: AppHandle) -> Result<AppResponse, AppResponse> {
+    database(&app)?
+        .connection()
+        .map_err(db_error_response)?
         .execute("DELETE FROM logs", [])
-        .map_err(|e| error_response(&e.to_string()))?;
+        .map_err(|e| db_error_response(e.into()))?;
 
     Ok(AppResponse {
         text_code: ResponseTextCode::FileCreationSuccessful,
@@ -118,64 +186,36 @@ pub fn clear_logs() -> Result<AppResponse, AppResponse> {
         file_path: None,
         timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
         stats: None,
+        recovery: None,
+        detail: None,
+        signer: None,
     })
 }
 
 #[tauri::command]
-pub fn download_logs(output_path: &str) -> Result<AppResponse, AppResponse> {
-    let error_response = |code: ResponseTextCode, e: &str| {
-        println!("{e}");
-        AppResponse {
-            text_code: code,
-            status: Status::Error,
-            file_path: None,
-            timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
-            stats: None,
-        }
-    };
-
-    let connection = open_database()
-        .map_err(|e| error_response(ResponseTextCode::DbConnFailed, &e.to_string()))?;
-
-    let mut stmt = connection
-        .prepare("SELECT timestamp, level, text_code, file_path FROM logs ORDER BY timestamp DESC")
-        .map_err(|e| error_response(ResponseTextCode::DbConnFailed, &e.to_string()))?;
-
-    let logs = stmt
-        .query_map([], |row| {
-            Ok(Log {
-                timestamp: row.get(0)?,
-                level: row.get::<_, String>(1)?.parse().unwrap(),
-                text_code: row.get::<_, String>(2)?.parse().unwrap(),
-                file_path: row.get(3)?,
-            })
-        })
-        .map_err(|e| error_response(ResponseTextCode::DbConnFailed, &e.to_string()))?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| error_response(ResponseTextCode::DbConnFailed, &e.to_string()))?;
+pub fn download_logs(app: AppHandle, output_path: &str) -> Result<AppResponse, AppResponse> {
+    let logs = read_logs(&*database(&app)?.connection().map_err(db_error_response)?)
+        .map_err(|e| db_error_response(e.into()))?;
 
     if logs.is_empty() {
-        return Err(error_response(
-            ResponseTextCode::FileCreationFailed,
-            "No logs to download",
-        ));
+        return Err(db_error_response(AppError::NoLogs));
     }
 
-    let json = serde_json::to_string_pretty(&logs)
-        .map_err(|e| error_response(ResponseTextCode::FileCreationFailed, &e.to_string()))?;
-
-    std::fs::write(output_path, json).map_err(|e| {
-        error_response(
-            ResponseTextCode::FileCreationFailed,
-            &format!("Failed to write logs: {}", e),
-        )
+    let json = serde_json::to_string_pretty(&logs).map_err(|e| {
+        db_error_response(AppError::io(ResponseTextCode::FileCreationFailed, e.into()))
     })?;
 
+    std::fs::write(output_path, json)
+        .map_err(|e| db_error_response(AppError::io(ResponseTextCode::FileCreationFailed, e)))?;
+
     Ok(AppResponse {
         text_code: ResponseTextCode::LogsDownloaded,
         status: Status::Success,
         file_path: Some(output_path.to_string()),
         timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
         stats: None,
+        recovery: None,
+        detail: None,
+        signer: None,
     })
 }
@@ -1,14 +1,166 @@
-use duckdb::{Connection, Error};
-
-pub fn create_tables(connection: &Connection) -> Result<(), Error> {
-    connection.execute(
-        "CREATE TABLE IF NOT EXISTS logs (
-              timestamp BIGINT PRIMARY KEY,
-              level VARCHAR NOT NULL,
-              text_code VARCHAR NOT NULL,
-              file_path VARCHAR
-          )",
-        [],
-    )?;
+use duckdb::{params, Connection, Error};
+use std::io;
+
+use crate::{error::AppError, types::ResponseTextCode};
+
+/// Holds the version of the schema, i.e. the number of migrations applied
+const VERSION_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_version (version BIGINT NOT NULL)";
+
+/// Schema changes in the order they were introduced; step `i` upgrades version `i` to `i + 1`
+///
+/// Released steps must never change, new columns or tables get a new step.
+const MIGRATIONS: &[&str] = &[
+    // 1: the original logs table. Databases created before migrations existed
+    // already have it and no version table, so they start at version 0 too.
+    "CREATE TABLE IF NOT EXISTS logs (
+        timestamp BIGINT PRIMARY KEY,
+        level VARCHAR NOT NULL,
+        text_code VARCHAR NOT NULL,
+        file_path VARCHAR
+    )",
+    // 2: timestamps are in seconds, so they can't be the primary key of a log
+    // that gets several entries per second
+    "CREATE TABLE logs_v1 AS SELECT * FROM logs;
+    DROP TABLE logs;
+    CREATE SEQUENCE log_id START 1;
+    CREATE TABLE logs (
+        id BIGINT PRIMARY KEY DEFAULT nextval('log_id'),
+        timestamp BIGINT NOT NULL,
+        level VARCHAR NOT NULL,
+        text_code VARCHAR NOT NULL,
+        file_path VARCHAR
+    );
+    INSERT INTO logs (timestamp, level, text_code, file_path)
+        SELECT timestamp, level, text_code, file_path FROM logs_v1 ORDER BY timestamp;
+    DROP TABLE logs_v1;",
+];
+
+/// Version of the schema this build writes
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Number of migrations applied to the database, 0 for a new one
+fn schema_version(connection: &Connection) -> Result<i64, Error> {
+    connection.query_row("SELECT max(version) FROM schema_version", [], |row| {
+        row.get::<_, Option<i64>>(0).map(Option::unwrap_or_default)
+    })
+}
+
+/// Runs `migration` and records `version` in one transaction
+fn apply(connection: &Connection, migration: &str, version: i64) -> Result<(), Error> {
+    connection.execute_batch("BEGIN TRANSACTION")?;
+    let result = connection
+        .execute_batch(migration)
+        .and_then(|_| connection.execute("DELETE FROM schema_version", []))
+        .and_then(|_| connection.execute("INSERT INTO schema_version (version) VALUES (?)", params![version]));
+    match result {
+        Ok(_) => connection.execute_batch("COMMIT"),
+        Err(e) => {
+            connection.execute_batch("ROLLBACK").ok();
+            Err(e)
+        }
+    }
+}
+
+/// Brings the schema up to [`SCHEMA_VERSION`], applying the missing migrations in order
+///
+/// A database written by a newer build is left alone and reported as an error,
+/// since this build can't know what its schema looks like.
+pub fn migrate(connection: &Connection) -> Result<(), AppError> {
+    connection.execute_batch(VERSION_TABLE)?;
+    let current = schema_version(connection)?;
+    if current > SCHEMA_VERSION {
+        return Err(AppError::io(
+            ResponseTextCode::DbConnFailed,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Log database has schema version {}, this build supports up to {}", current, SCHEMA_VERSION),
+            ),
+        ));
+    }
+
+    for (version, migration) in (1..).zip(MIGRATIONS).skip(current as usize) {
+        apply(connection, migration, version)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    /// SQL that recreates a database as version `version` left it, with some logs
+    fn fixture(version: i64) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/testdata/logs/v{}.sql", version));
+        fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    fn logs(connection: &Connection) -> Vec<(i64, String, String, Option<String>)> {
+        let mut stmt = connection
+            .prepare("SELECT timestamp, level, text_code, file_path FROM logs ORDER BY timestamp")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn creates_new_databases_at_the_current_version() {
+        let connection = Connection::open_in_memory().unwrap();
+        migrate(&connection).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+        assert!(logs(&connection).is_empty());
+    }
+
+    #[test]
+    fn upgrades_fixtures_from_every_prior_version() {
+        for version in 0..SCHEMA_VERSION {
+            let connection = Connection::open_in_memory().unwrap();
+            connection.execute_batch(&fixture(version)).unwrap();
+            let before = logs(&connection);
+            assert!(!before.is_empty(), "fixture v{version} has no logs");
+
+            migrate(&connection).unwrap_or_else(|e| panic!("v{version}: {e}"));
+            assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION, "v{version}");
+            assert_eq!(logs(&connection), before, "v{version}");
+        }
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(&fixture(0)).unwrap();
+        migrate(&connection).unwrap();
+        let before = logs(&connection);
+        migrate(&connection).unwrap();
+        assert_eq!(logs(&connection), before);
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn keeps_several_logs_per_second() {
+        let connection = Connection::open_in_memory().unwrap();
+        migrate(&connection).unwrap();
+        for text_code in ["encryption_successful", "decryption_successful"] {
+            connection
+                .execute(
+                    "INSERT INTO logs (timestamp, level, text_code, file_path) VALUES (?, ?, ?, ?)",
+                    params![1_700_000_000i64, "info", text_code, None::<String>],
+                )
+                .unwrap();
+        }
+        assert_eq!(logs(&connection).len(), 2);
+    }
+
+    #[test]
+    fn rejects_databases_from_newer_builds() {
+        let connection = Connection::open_in_memory().unwrap();
+        migrate(&connection).unwrap();
+        connection
+            .execute("UPDATE schema_version SET version = ?", params![SCHEMA_VERSION + 1])
+            .unwrap();
+        assert!(migrate(&connection).is_err());
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION + 1);
+    }
+}
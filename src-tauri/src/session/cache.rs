@@ -0,0 +1,484 @@
+use orion::{hazardous::aead::xchacha20poly1305::SecretKey, util::secure_cmp};
+use region::LockGuard;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+use zeroize::Zeroize;
+
+use crate::{
+    encryption::{
+        format::{ChunkCipher, FileHeader, EXT_KEY_SALT},
+        helpers::{create_key, generate_key_salt, validate_password, EncryptionError, KEY_SALT_SIZE},
+        xchacha20::create_error_response,
+    },
+    log::add_log_internal,
+    types::{AppResponse, LogLevel, ResponseTextCode, SessionLockReason, SessionOptions, SessionStatus},
+};
+
+/// Name of the event emitted when a session locks
+pub const SESSION_LOCKED_EVENT: &str = "session_locked";
+/// Files without a key salt derive a key each, so the cache is bounded for long sessions
+const MAX_CACHED_KEYS: usize = 256;
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the window may be out of focus before a blur lock, long enough to pick files in the app's own dialogs
+const BLUR_GRACE_PERIOD: Duration = Duration::from_secs(15);
+
+/// Secret bytes that are locked in RAM and zeroized on drop
+///
+/// Locking keeps the bytes out of swap. It can fail when the memlock limit is
+/// reached, in which case the bytes are still zeroized.
+struct LockedBytes {
+    bytes: Box<[u8]>,
+    lock: Option<LockGuard>,
+}
+
+impl LockedBytes {
+    fn new(source: &[u8]) -> Self {
+        let bytes: Box<[u8]> = source.into();
+        let lock = region::lock(bytes.as_ptr(), bytes.len()).ok();
+        Self { bytes, lock }
+    }
+
+    fn secret_key(&self) -> Result<SecretKey, EncryptionError> {
+        SecretKey::from_slice(&self.bytes).map_err(|_| EncryptionError::SecretKeyCreation)
+    }
+}
+
+/// The password a command runs with, either given to it or the session's
+///
+/// The session password isn't copied: this shares the session's locked buffer,
+/// which is zeroized once the session is locked and the last command holding
+/// it has finished.
+pub struct CommandPassword(Arc<LockedBytes>);
+
+impl CommandPassword {
+    pub fn new(password: &str) -> Self {
+        Self(Arc::new(LockedBytes::new(password.as_bytes())))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // The bytes always come from a `&str`
+        std::str::from_utf8(&self.0.bytes).unwrap_or_default()
+    }
+}
+
+impl Default for CommandPassword {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Deref for CommandPassword {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Drop for LockedBytes {
+    fn drop(&mut self) {
+        self.bytes.as_mut().zeroize();
+        // Unlock before the allocation is freed
+        self.lock.take();
+    }
+}
+
+struct Session {
+    password: Arc<LockedBytes>,
+    /// Keys derived from the password by KDF salt: root keys for files with a
+    /// key salt, file keys by nonce for files without one
+    keys: HashMap<Vec<u8>, LockedBytes>,
+    /// Key salt of the files encrypted with the session password
+    key_salt: [u8; KEY_SALT_SIZE],
+    idle_timeout: Duration,
+    lock_on_blur: bool,
+    /// When the window lost focus, `None` while it has focus
+    blurred_at: Option<Instant>,
+    last_used: Instant,
+}
+
+impl Session {
+    fn is_expired(&self) -> bool {
+        self.last_used.elapsed() >= self.idle_timeout
+    }
+
+    /// True once the window has been out of focus for `grace`, if the session locks on blur
+    fn blurred_for(&self, grace: Duration) -> bool {
+        self.lock_on_blur && self.blurred_at.is_some_and(|since| since.elapsed() >= grace)
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        secure_cmp(&self.password.bytes, password.as_bytes()).is_ok()
+    }
+}
+
+/// The unlocked session, managed as Tauri state
+///
+/// Holds the session password and every key derived with it, so commands given
+/// an empty password don't need it entered again.
+///
+/// Files encrypted with the session password share the session's key salt.
+/// Argon2 derives one root key from the password and that salt, and each file
+/// key comes from the root key and the file nonce through HKDF. Decrypting a
+/// batch of these files, in this session or a later one, runs Argon2 once for
+/// the batch. Files without a key salt, written by earlier versions, salt
+/// Argon2 with their nonce and still derive once per file.
+#[derive(Default)]
+pub struct SessionState(Mutex<Option<Session>>);
+
+impl SessionState {
+    /// Locks the mutex; a panic while it was held ends the session, as its state is unknown
+    fn lock(&self) -> MutexGuard<'_, Option<Session>> {
+        self.0.lock().unwrap_or_else(|poisoned| {
+            self.0.clear_poison();
+            let mut session = poisoned.into_inner();
+            *session = None;
+            session
+        })
+    }
+
+    /// Locks the mutex, dropping the session first if it has expired
+    fn current(&self) -> MutexGuard<'_, Option<Session>> {
+        let mut session = self.lock();
+        if session.as_ref().is_some_and(Session::is_expired) {
+            *session = None;
+        }
+        session
+    }
+
+    /// Like [`SessionState::current`], but also resets the idle timer
+    fn touch(&self) -> MutexGuard<'_, Option<Session>> {
+        let mut session = self.current();
+        if let Some(session) = session.as_mut() {
+            session.last_used = Instant::now();
+        }
+        session
+    }
+
+    fn unlock(&self, password: &str, options: SessionOptions) {
+        *self.lock() = Some(Session {
+            password: Arc::new(LockedBytes::new(password.as_bytes())),
+            keys: HashMap::new(),
+            key_salt: generate_key_salt(),
+            idle_timeout: Duration::from_secs(options.idle_timeout_secs),
+            lock_on_blur: options.lock_on_blur,
+            blurred_at: None,
+            last_used: Instant::now(),
+        });
+    }
+
+    /// Ends the session if `should_lock` holds; returns whether a live session was locked
+    fn lock_if(&self, should_lock: impl FnOnce(&Session) -> bool) -> bool {
+        let mut session = self.lock();
+        match session.as_ref() {
+            Some(current) if should_lock(current) => {
+                *session = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records whether the window has focus
+    ///
+    /// # Returns
+    /// Whether a blur lock is pending, i.e. the window is out of focus and the session locks on blur
+    fn set_focused(&self, focused: bool) -> bool {
+        match self.lock().as_mut() {
+            Some(session) => {
+                // Repeated blur events keep the time focus was first lost
+                session.blurred_at = if focused { None } else { session.blurred_at.or(Some(Instant::now())) };
+                session.blurred_at.is_some() && session.lock_on_blur
+            }
+            None => false,
+        }
+    }
+
+    fn status(&self) -> SessionStatus {
+        let session = self.current();
+        SessionStatus {
+            unlocked: session.is_some(),
+            remaining_seconds: session
+                .as_ref()
+                .map(|session| session.idle_timeout.saturating_sub(session.last_used.elapsed()).as_secs()),
+        }
+    }
+
+    /// True while a session is unlocked; unlike the commands, checking doesn't reset the idle timer
+    pub fn is_unlocked(&self) -> bool {
+        self.current().is_some()
+    }
+
+    /// Shares the session password with a command, if a session is unlocked
+    fn shared_password(&self) -> Option<CommandPassword> {
+        self.touch()
+            .as_ref()
+            .map(|session| CommandPassword(Arc::clone(&session.password)))
+    }
+
+    /// Sets the key salt of a new file: the session's if `password` is the session password, a fresh one otherwise
+    pub fn set_key_salt(&self, header: &mut FileHeader, password: &str) {
+        let salt = self
+            .touch()
+            .as_ref()
+            .filter(|session| session.matches(password))
+            .map_or_else(generate_key_salt, |session| session.key_salt);
+        header.set_extension(EXT_KEY_SALT, salt.to_vec());
+    }
+
+    /// Derives the cipher for `header`, using the key cache if `password` is the session password
+    ///
+    /// The first call for a key salt runs the KDF, see [`SessionState`].
+    pub fn cipher(&self, header: &FileHeader, password: &str) -> Result<ChunkCipher, EncryptionError> {
+        let salt = header.key_salt();
+        // None: not the session password, Some(None): not derived yet
+        let cached = {
+            let session = self.touch();
+            match session.as_ref() {
+                Some(session) if session.matches(password) => {
+                    Some(session.keys.get(salt).map(LockedBytes::secret_key))
+                }
+                _ => None,
+            }
+        };
+
+        match cached {
+            None => header.cipher(password),
+            Some(Some(key)) => header.cipher_with_password_key(&key?),
+            Some(None) => {
+                // The KDF runs without holding the lock
+                let key = create_key(password, salt.to_vec())?;
+                let mut session = self.current();
+                if let Some(session) = session.as_mut().filter(|session| session.matches(password)) {
+                    if session.keys.len() >= MAX_CACHED_KEYS {
+                        session.keys.clear();
+                    }
+                    session.keys.insert(salt.to_vec(), LockedBytes::new(key.unprotected_as_bytes()));
+                }
+                header.cipher_with_password_key(&key)
+            }
+        }
+    }
+}
+
+/// Resolves the password for a command: an empty `password` means the session password
+pub fn session_password(app: &AppHandle, password: &str) -> Result<CommandPassword, AppResponse> {
+    if !password.is_empty() {
+        return Ok(CommandPassword::new(password));
+    }
+    app.state::<SessionState>()
+        .shared_password()
+        .ok_or_else(|| create_error_response(ResponseTextCode::SessionLocked, None))
+}
+
+fn emit_locked(app: &AppHandle, reason: SessionLockReason) {
+    add_log_internal(LogLevel::Info, ResponseTextCode::SessionLocked, None).ok();
+    app.emit(SESSION_LOCKED_EVENT, reason).ok();
+}
+
+/// Locks the session if the window stays out of focus for `BLUR_GRACE_PERIOD`, if the session asked for it
+///
+/// The app's own file and save dialogs take the focus from the window too, so
+/// the lock waits for the grace period and is cancelled by [`cancel_blur_lock`]
+/// once the window is focused again.
+pub fn lock_on_blur(app: &AppHandle) {
+    if !app.state::<SessionState>().set_focused(false) {
+        return;
+    }
+    let app = app.clone();
+    thread::spawn(move || {
+        thread::sleep(BLUR_GRACE_PERIOD);
+        if app.state::<SessionState>().lock_if(|session| session.blurred_for(BLUR_GRACE_PERIOD)) {
+            emit_locked(&app, SessionLockReason::Blur);
+        }
+    });
+}
+
+/// Cancels a pending blur lock when the window gets the focus back, see [`lock_on_blur`]
+pub fn cancel_blur_lock(app: &AppHandle) {
+    app.state::<SessionState>().set_focused(true);
+}
+
+/// Periodically locks an idle session so `session_locked` fires without waiting for the next command
+pub fn spawn_expiry_check(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(EXPIRY_CHECK_INTERVAL);
+        if app.state::<SessionState>().lock_if(Session::is_expired) {
+            emit_locked(&app, SessionLockReason::Timeout);
+        }
+    });
+}
+
+/// Unlocks a session; commands given an empty password then use `password`
+///
+/// A running session is replaced, together with its cached keys.
+#[tauri::command]
+pub async fn unlock_session(
+    state: State<'_, SessionState>,
+    password: &str,
+    options: Option<SessionOptions>,
+) -> Result<SessionStatus, AppResponse> {
+    validate_password(password).map_err(|_| {
+        create_error_response(ResponseTextCode::InvalidPassword, None)
+    })?;
+    state.unlock(password, options.unwrap_or_default());
+    add_log_internal(LogLevel::Info, ResponseTextCode::SessionUnlocked, None).ok();
+    Ok(state.status())
+}
+
+/// Locks the session and zeroizes the password and all cached keys
+#[tauri::command]
+pub async fn lock_session(app: AppHandle, state: State<'_, SessionState>) -> Result<SessionStatus, AppResponse> {
+    if state.lock_if(|_| true) {
+        emit_locked(&app, SessionLockReason::Manual);
+    }
+    Ok(state.status())
+}
+
+#[tauri::command]
+pub async fn get_session_status(state: State<'_, SessionState>) -> Result<SessionStatus, AppResponse> {
+    Ok(state.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{helpers::NONCE_SIZE, test_util::PASSWORD};
+
+    fn unlocked(idle_timeout_secs: u64, lock_on_blur: bool) -> SessionState {
+        let state = SessionState::default();
+        state.unlock(PASSWORD, SessionOptions { idle_timeout_secs, lock_on_blur });
+        state
+    }
+
+    fn cached_keys(state: &SessionState) -> usize {
+        state.lock().as_ref().map_or(0, |session| session.keys.len())
+    }
+
+    #[test]
+    fn reuses_the_cached_key_for_the_same_file() {
+        let state = unlocked(300, false);
+        let header = FileHeader::new([4u8; NONCE_SIZE]);
+        let sealed = state.cipher(&header, PASSWORD).unwrap().seal_chunk(0, b"cached").unwrap();
+        assert_eq!(cached_keys(&state), 1);
+
+        // A planted key proves the second call reads the cache instead of deriving
+        let planted = [5u8; 32];
+        state.lock().as_mut().unwrap().keys.insert(header.nonce.to_vec(), LockedBytes::new(&planted));
+        let cipher = state.cipher(&header, PASSWORD).unwrap();
+        let expected = header.cipher_with_key(SecretKey::from_slice(&planted).unwrap());
+        assert_eq!(cipher.seal_chunk(0, b"cached").unwrap(), expected.seal_chunk(0, b"cached").unwrap());
+        assert_ne!(cipher.seal_chunk(0, b"cached").unwrap(), sealed);
+        assert_eq!(cached_keys(&state), 1);
+    }
+
+    #[test]
+    fn files_with_the_session_key_salt_share_one_root_key() {
+        let state = unlocked(300, false);
+        let mut first = FileHeader::new([1u8; NONCE_SIZE]);
+        let mut second = FileHeader::new([2u8; NONCE_SIZE]);
+        state.set_key_salt(&mut first, PASSWORD);
+        state.set_key_salt(&mut second, PASSWORD);
+        assert_eq!(first.key_salt(), second.key_salt());
+
+        let sealed = state.cipher(&first, PASSWORD).unwrap().seal_chunk(0, b"batch").unwrap();
+        state.cipher(&second, PASSWORD).unwrap();
+        assert_eq!(cached_keys(&state), 1);
+        // Without the session the same key comes from the password
+        assert_eq!(first.cipher(PASSWORD).unwrap().open_chunk(0, &sealed).unwrap(), b"batch");
+
+        // A later session decrypting the batch runs the KDF once
+        let later = unlocked(300, false);
+        later.cipher(&first, PASSWORD).unwrap();
+        later.cipher(&second, PASSWORD).unwrap();
+        assert_eq!(cached_keys(&later), 1);
+
+        // Files encrypted with another password get a salt of their own
+        let mut other = FileHeader::new([3u8; NONCE_SIZE]);
+        state.set_key_salt(&mut other, "Session-Test-2!");
+        assert_ne!(other.key_salt(), first.key_salt());
+    }
+
+    #[test]
+    fn derives_a_key_per_file_without_a_key_salt_and_ignores_other_passwords() {
+        let state = unlocked(300, false);
+        state.cipher(&FileHeader::new([1u8; NONCE_SIZE]), PASSWORD).unwrap();
+        state.cipher(&FileHeader::new([2u8; NONCE_SIZE]), PASSWORD).unwrap();
+        assert_eq!(cached_keys(&state), 2);
+
+        let header = FileHeader::new([3u8; NONCE_SIZE]);
+        let other = state.cipher(&header, "Session-Test-2!").unwrap();
+        assert_eq!(cached_keys(&state), 2);
+        let expected = header.cipher("Session-Test-2!").unwrap();
+        assert_eq!(other.seal_chunk(0, b"other").unwrap(), expected.seal_chunk(0, b"other").unwrap());
+    }
+
+    #[test]
+    fn locks_after_the_idle_timeout() {
+        let state = unlocked(0, false);
+        assert!(!state.is_unlocked());
+        assert!(state.shared_password().is_none());
+        assert!(!state.status().unlocked);
+
+        let state = unlocked(300, false);
+        assert!(!state.lock_if(Session::is_expired));
+        assert!(state.is_unlocked());
+        assert!(matches!(state.status().remaining_seconds, Some(secs) if secs >= 299));
+    }
+
+    #[test]
+    fn expiry_check_drops_the_password_and_keys() {
+        let state = unlocked(300, false);
+        state.cipher(&FileHeader::new([1u8; NONCE_SIZE]), PASSWORD).unwrap();
+        state.lock().as_mut().unwrap().idle_timeout = Duration::ZERO;
+
+        assert!(state.lock_if(Session::is_expired));
+        assert!(state.lock().is_none());
+        assert!(!state.lock_if(Session::is_expired));
+    }
+
+    #[test]
+    fn locks_on_blur_only_if_asked_to() {
+        let state = unlocked(300, false);
+        assert!(!state.set_focused(false));
+        assert!(!state.lock_if(|session| session.blurred_for(Duration::ZERO)));
+        assert!(state.is_unlocked());
+
+        let state = unlocked(300, true);
+        assert!(!state.lock_if(|session| session.blurred_for(Duration::ZERO)));
+        assert!(state.set_focused(false));
+        assert!(state.lock_if(|session| session.blurred_for(Duration::ZERO)));
+        assert!(!state.is_unlocked());
+    }
+
+    #[test]
+    fn focus_back_within_the_grace_period_cancels_the_blur_lock() {
+        let state = unlocked(300, true);
+        assert!(state.set_focused(false));
+        assert!(!state.lock_if(|session| session.blurred_for(Duration::from_secs(60))));
+        assert!(!state.set_focused(true));
+        assert!(!state.lock_if(|session| session.blurred_for(Duration::ZERO)));
+        assert!(state.is_unlocked());
+
+        // A second blur event doesn't restart the grace period
+        state.set_focused(false);
+        let since = state.lock().as_ref().unwrap().blurred_at;
+        state.set_focused(false);
+        assert_eq!(state.lock().as_ref().unwrap().blurred_at, since);
+    }
+
+    #[test]
+    fn shares_the_session_password_without_copying_it() {
+        let state = unlocked(300, false);
+        let password = state.shared_password().unwrap();
+        assert_eq!(password.as_str(), PASSWORD);
+        let session = state.lock();
+        assert!(Arc::ptr_eq(&password.0, &session.as_ref().unwrap().password));
+    }
+}
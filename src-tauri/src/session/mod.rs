@@ -0,0 +1,6 @@
+pub mod cache;
+
+pub use cache::{
+    cancel_blur_lock, get_session_status, lock_on_blur, lock_session, session_password, spawn_expiry_check, unlock_session,
+    CommandPassword, SessionState,
+};
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString, AsRefStr};
+use zeroize::Zeroizing;
 
 #[derive(Debug, Serialize)]
 pub struct ProgressInfo {
@@ -16,6 +17,9 @@ pub struct ProcessingStats {
     pub total_size_bytes: usize,
     pub processing_time_seconds: f64,
     pub average_speed_mbps: f64,
+    /// BLAKE2b-256 of the plaintext as hex, set once decryption verified it against the stored hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Display, EnumString, AsRefStr)]
@@ -52,8 +56,96 @@ pub enum ResponseTextCode {
     FileCreationSuccessful,
     #[strum(serialize = "logs_downloaded", to_string = "Logs downloaded")]
     LogsDownloaded,
+    #[strum(serialize = "no_logs", to_string = "No logs to download")]
+    NoLogs,
+    #[strum(serialize = "state_unavailable", to_string = "App state is unavailable after an earlier failure")]
+    StateUnavailable,
     #[strum(serialize = "file_delete_failed", to_string = "File delete failed")]
     FileDeleteFailed,
+    #[strum(serialize = "preview_too_large", to_string = "File too large to preview")]
+    PreviewTooLarge,
+    #[strum(serialize = "preview_unsupported", to_string = "File type cannot be previewed")]
+    PreviewUnsupported,
+    #[strum(serialize = "invalid_armor", to_string = "Invalid armored message")]
+    InvalidArmor,
+    #[strum(serialize = "clipboard_read_failed", to_string = "Clipboard read failed")]
+    ClipboardReadFailed,
+    #[strum(serialize = "clipboard_write_failed", to_string = "Clipboard write failed")]
+    ClipboardWriteFailed,
+    #[strum(serialize = "metadata_restore_failed", to_string = "Metadata restore failed")]
+    MetadataRestoreFailed,
+    #[strum(serialize = "file_skipped", to_string = "File skipped")]
+    FileSkipped,
+    #[strum(serialize = "file_already_exists", to_string = "File already exists")]
+    FileAlreadyExists,
+    #[strum(serialize = "invalid_name_template", to_string = "Invalid name template")]
+    InvalidNameTemplate,
+    #[strum(serialize = "wrong_password", to_string = "Wrong password")]
+    WrongPassword,
+    #[strum(serialize = "corrupted_chunk", to_string = "Encrypted data is corrupted")]
+    CorruptedChunk,
+    #[strum(serialize = "truncated_file", to_string = "Encrypted file is truncated")]
+    TruncatedFile,
+    #[strum(serialize = "unsupported_format", to_string = "Unsupported file format")]
+    UnsupportedFormat,
+    #[strum(serialize = "partially_recovered", to_string = "Decrypted with damaged regions")]
+    PartiallyRecovered,
+    #[strum(serialize = "chunks_repaired", to_string = "Damaged chunks were repaired")]
+    ChunksRepaired,
+    #[strum(serialize = "missing_volume", to_string = "Volume is missing")]
+    MissingVolume,
+    #[strum(serialize = "corrupted_volume", to_string = "Volume is corrupted")]
+    CorruptedVolume,
+    #[strum(serialize = "invalid_options", to_string = "Invalid options")]
+    InvalidOptions,
+    #[strum(serialize = "vault_created", to_string = "Vault created")]
+    VaultCreated,
+    #[strum(serialize = "vault_opened", to_string = "Vault opened")]
+    VaultOpened,
+    #[strum(serialize = "vault_entry_added", to_string = "Added to vault")]
+    VaultEntryAdded,
+    #[strum(serialize = "vault_entry_extracted", to_string = "Extracted from vault")]
+    VaultEntryExtracted,
+    #[strum(serialize = "vault_entry_deleted", to_string = "Deleted from vault")]
+    VaultEntryDeleted,
+    #[strum(serialize = "vault_entry_not_found", to_string = "Vault entry not found")]
+    VaultEntryNotFound,
+    #[strum(serialize = "watch_started", to_string = "Watching folder")]
+    WatchStarted,
+    #[strum(serialize = "watch_stopped", to_string = "Stopped watching folder")]
+    WatchStopped,
+    #[strum(serialize = "watch_not_active", to_string = "No folder is being watched")]
+    WatchNotActive,
+    #[strum(serialize = "watch_failed", to_string = "Folder watcher failed")]
+    WatchFailed,
+    #[strum(serialize = "file_shredded", to_string = "File shredded")]
+    FileShredded,
+    #[strum(serialize = "session_unlocked", to_string = "Session unlocked")]
+    SessionUnlocked,
+    #[strum(serialize = "session_locked", to_string = "Session locked")]
+    SessionLocked,
+    #[strum(serialize = "key_remembered", to_string = "Password remembered")]
+    KeyRemembered,
+    #[strum(serialize = "key_forgotten", to_string = "Password forgotten")]
+    KeyForgotten,
+    #[strum(serialize = "key_not_remembered", to_string = "No password remembered")]
+    KeyNotRemembered,
+    #[strum(serialize = "keyring_failed", to_string = "Keyring access failed")]
+    KeyringFailed,
+    #[strum(serialize = "keyring_unavailable", to_string = "No keyring is available to remember the password")]
+    KeyringUnavailable,
+    #[strum(serialize = "signing_key_created", to_string = "Signing key created")]
+    SigningKeyCreated,
+    #[strum(serialize = "invalid_signature", to_string = "Invalid signature")]
+    InvalidSignature,
+    #[strum(serialize = "hash_computed", to_string = "Hash computed")]
+    HashComputed,
+    #[strum(serialize = "hash_mismatch", to_string = "Decrypted content does not match the original")]
+    HashMismatch,
+    #[strum(serialize = "file_upgraded", to_string = "File upgraded to the current format")]
+    FileUpgraded,
+    #[strum(serialize = "already_upgraded", to_string = "File already uses the current format")]
+    AlreadyUpgraded,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Display, EnumString, AsRefStr)]
@@ -75,6 +167,177 @@ pub enum Status {
     Error,
 }
 
+// Request types
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    Overwrite,
+    Skip,
+    #[default]
+    Rename,
+    Fail,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct OutputOptions {
+    /// Directory to write to instead of the input's directory
+    pub output_dir: Option<String>,
+    /// Output name template, e.g. `{stem}.{ext}.enc` or `{date}/{name}`
+    pub name_template: Option<String>,
+    /// What to do when the output path already exists
+    pub conflict_policy: ConflictPolicy,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct ErrorCorrectionOptions {
+    /// Encrypted chunks per parity group
+    pub data_chunks: u8,
+    /// Parity chunks per group, i.e. how many damaged chunks per group can be repaired
+    ///
+    /// A group holds at most 64 chunks together, which bounds its memory use to about 64 MiB.
+    pub parity_chunks: u8,
+}
+
+impl Default for ErrorCorrectionOptions {
+    fn default() -> Self {
+        Self {
+            data_chunks: 10,
+            parity_chunks: 2,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    /// BLAKE2b-256
+    #[default]
+    Blake2b,
+    Sha256,
+}
+
+/// Container format written by `encrypt_file`
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    #[default]
+    Cipher,
+    /// age v1 (age-encryption.org/v1), readable by the age CLI
+    Age,
+    /// OpenPGP symmetric encryption (AES-256, SEIPD with MDC), readable by `gpg --decrypt`
+    #[serde(rename = "openpgp")]
+    OpenPgp,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningOptions {
+    /// Key file created by `generate_signing_key`
+    pub key_path: String,
+    /// Password the key file is encrypted with
+    pub password: Zeroizing<String>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct EncryptOptions {
+    /// Write the cipher format, age or OpenPGP; age and OpenPGP support only `armored` and the output options
+    pub format: FileFormat,
+    /// age X25519 recipients (`age1...`); without any, age files are encrypted with the password
+    pub age_recipients: Vec<String>,
+    /// Write base64 with BEGIN/END markers instead of raw binary
+    pub armored: bool,
+    /// Write the ciphertext under a random name; the real name is restored on decryption
+    pub encrypt_file_name: bool,
+    /// Store extended attributes alongside name, size, mtime and permissions
+    pub preserve_xattrs: bool,
+    /// Append Reed-Solomon parity so damaged chunks can be repaired
+    pub error_correction: Option<ErrorCorrectionOptions>,
+    /// Split the output into volumes of this many bytes (`.enc.001`, `.enc.002`, ...)
+    pub volume_size: Option<u64>,
+    /// Sign the header and content with this key before encrypting
+    pub signing_key: Option<SigningOptions>,
+    /// Store the BLAKE2b hash of the plaintext so decryption verifies the content end to end
+    pub record_hash: bool,
+    #[serde(flatten)]
+    pub output: OutputOptions,
+}
+
+impl EncryptOptions {
+    /// True if an option is set that only the cipher format can store
+    pub fn uses_cipher_features(&self) -> bool {
+        self.encrypt_file_name
+            || self.preserve_xattrs
+            || self.error_correction.is_some()
+            || self.volume_size.is_some()
+            || self.signing_key.is_some()
+            || self.record_hash
+    }
+}
+
+/// How damaged chunks are handled when recovering a file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryMode {
+    /// Write zeros in place of damaged chunks, keeping all other data at its offset
+    ZeroFill,
+    /// Leave damaged chunks out of the output
+    Skip,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DecryptOptions {
+    /// Reapply the stored mtime, permissions and extended attributes
+    pub restore_metadata: bool,
+    /// Keep going past damaged chunks instead of failing; `None` is strict decryption
+    pub recovery: Option<RecoveryMode>,
+    /// age X25519 identities (`AGE-SECRET-KEY-1...`); without any, age files are decrypted with the password
+    pub age_identities: Vec<String>,
+    #[serde(flatten)]
+    pub output: OutputOptions,
+}
+
+impl Default for DecryptOptions {
+    fn default() -> Self {
+        Self {
+            restore_metadata: true,
+            recovery: None,
+            age_identities: Vec::new(),
+            output: OutputOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct WatchOptions {
+    /// Overwrite each source file with random data before deleting it
+    pub shred_source: bool,
+    /// Applied to every file; the output directory is always the target folder
+    #[serde(flatten)]
+    pub encryption: EncryptOptions,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct SessionOptions {
+    /// Lock after this many seconds without a command using the session
+    pub idle_timeout_secs: u64,
+    /// Lock when the app window loses focus and doesn't get it back within a short grace period
+    pub lock_on_blur: bool,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 300,
+            lock_on_blur: true,
+        }
+    }
+}
+
 // Response types
 #[derive(Debug, Serialize)]
 pub struct AppResponse {
@@ -82,7 +345,149 @@ pub struct AppResponse {
     pub text_code: ResponseTextCode,
     pub file_path: Option<String>,
     pub timestamp: String,
-    pub stats: Option<ProcessingStats>,
+    pub stats: Option<Box<ProcessingStats>>,
+    /// Damaged regions found while decrypting in recovery mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<Box<RecoveryReport>>,
+    /// Context for errors, e.g. the underlying I/O error; never contains secrets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Signature details of a decrypted file, `None` for unsigned files
+    #[serde(flatten)]
+    pub signer: Option<Box<SignerDetails>>,
+}
+
+/// How the signature of a signed file turned out
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct SignerDetails {
+    /// Fingerprint of the key that signed the file, set once the signature is verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer_fingerprint: Option<String>,
+    /// Whether the signature was checked
+    pub signature: SignatureStatus,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// The signature matches the signer in the header
+    Verified,
+    /// Recovery lost damaged chunks, so the signature couldn't be checked
+    Unverified,
+}
+
+/// A region of the original plaintext that could not be recovered
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct DamagedRange {
+    /// Chunk the region belongs to, `None` for data missing at the end of the file
+    pub chunk_index: Option<u64>,
+    /// Offset in the original plaintext
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecoveryReport {
+    pub mode: RecoveryMode,
+    pub damaged_ranges: Vec<DamagedRange>,
+    pub recovered_bytes: u64,
+    pub lost_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextResponse {
+    pub status: Status,
+    pub text_code: ResponseTextCode,
+    pub text: Option<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewKind {
+    Text,
+    Image,
+    Pdf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewResponse {
+    pub kind: PreviewKind,
+    pub mime_type: String,
+    /// UTF-8 text for `Text`, base64 for everything else
+    pub data: String,
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultEntryInfo {
+    pub id: u64,
+    pub name: String,
+    pub size_bytes: u64,
+    /// Modification time of the original file in seconds since the Unix epoch
+    pub modified: Option<i64>,
+    /// When the entry was added in seconds since the Unix epoch
+    pub added: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultResponse {
+    pub status: Status,
+    pub text_code: ResponseTextCode,
+    pub entries: Vec<VaultEntryInfo>,
+    pub timestamp: String,
+}
+
+/// Emitted as `watch_event` for every file handled by the folder watcher
+#[derive(Debug, Serialize, Clone)]
+pub struct WatchEvent {
+    pub status: Status,
+    pub text_code: ResponseTextCode,
+    /// The file dropped into the watched folder
+    pub source_path: String,
+    /// The encrypted file, if one was written
+    pub output_path: Option<String>,
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashResponse {
+    pub status: Status,
+    pub text_code: ResponseTextCode,
+    pub algorithm: HashAlgorithm,
+    /// Lowercase hex
+    pub hash: String,
+    pub file_path: String,
+    pub stats: ProcessingStats,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SigningKeyResponse {
+    pub status: Status,
+    pub text_code: ResponseTextCode,
+    /// Ed25519 public key, base64
+    pub public_key: String,
+    pub fingerprint: String,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionStatus {
+    pub unlocked: bool,
+    /// Seconds until the session locks unless it is used again
+    pub remaining_seconds: Option<u64>,
+}
+
+/// Emitted as `session_locked` whenever an unlocked session ends
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLockReason {
+    Manual,
+    Timeout,
+    Blur,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,472 @@
+use chrono::Local;
+use notify::{
+    event::{AccessKind, AccessMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{
+    encryption::{
+        encrypt_file,
+        helpers::{shred_file, validate_password},
+        xchacha20::{create_error_response, create_info_response},
+    },
+    error::AppError,
+    keystore::KeyStore,
+    log::add_log_internal,
+    session::{CommandPassword, SessionState},
+    types::{AppResponse, LogLevel, ResponseTextCode, Status, WatchEvent, WatchOptions},
+};
+
+/// Name of the event emitted for every handled file
+pub const WATCH_EVENT: &str = "watch_event";
+/// A file is picked up once it hasn't changed for this long, so partial copies are left alone
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Suffixes of files that are still being written by browsers and editors
+const PARTIAL_SUFFIXES: &[&str] = &[".part", ".crdownload", ".tmp", ".swp", "~"];
+
+/// Tells watches apart, so a worker only stops its own
+static NEXT_WATCH_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The active folder watch, managed as Tauri state
+///
+/// Dropping the watch stops the watcher, which closes its channel and ends the worker thread.
+#[derive(Default)]
+pub struct WatchState(Mutex<Option<FolderWatch>>);
+
+struct FolderWatch {
+    id: u64,
+    _watcher: RecommendedWatcher,
+    source_dir: PathBuf,
+}
+
+/// Where the watch gets the password for each file
+enum WatchKey {
+    /// Given to `start_watch` and held until the watch stops
+    Password(CommandPassword),
+    /// Remembered in the keystore for the watched folder, looked up for every file
+    Remembered,
+    /// The unlocked session's; the watch stops when the session locks
+    Session,
+}
+
+/// Everything the worker needs to encrypt a dropped file
+struct WatchConfig {
+    id: u64,
+    source_dir: PathBuf,
+    key: WatchKey,
+    options: WatchOptions,
+}
+
+/// Size and modification time of a file, to tell real changes from events that changed nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok().filter(|metadata| metadata.is_file())?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Decides when a changed file is ready to be encrypted
+///
+/// A file is due once it hasn't changed for [`SETTLE_TIME`], and only if it
+/// differs from how it looked after the watch last handled it. That skips
+/// events caused by the watch itself, e.g. a shred whose delete failed, while
+/// a file changed later is encrypted again.
+#[derive(Default)]
+struct Debouncer {
+    /// When each file last changed
+    pending: HashMap<PathBuf, Instant>,
+    handled: HashMap<PathBuf, Fingerprint>,
+}
+
+impl Debouncer {
+    fn changed(&mut self, path: PathBuf, now: Instant) {
+        self.pending.insert(path, now);
+    }
+
+    fn removed(&mut self, path: &Path) {
+        self.pending.remove(path);
+        self.handled.remove(path);
+    }
+
+    /// Takes the files that are due at `now`
+    fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.saturating_duration_since(**changed) >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled
+            .into_iter()
+            .filter(|path| {
+                self.pending.remove(path);
+                // The file may have been moved away while settling
+                Fingerprint::of(path).is_some_and(|current| self.handled.get(path) != Some(&current))
+            })
+            .collect()
+    }
+
+    /// Records how `path` looks after handling it
+    fn handled(&mut self, path: &Path) {
+        match Fingerprint::of(path) {
+            Some(fingerprint) => self.handled.insert(path.to_path_buf(), fingerprint),
+            None => self.handled.remove(path),
+        };
+    }
+}
+
+/// Checks that `path` is an existing directory and returns its canonical form
+fn watch_dir(path: &str) -> Result<PathBuf, AppResponse> {
+    fs::canonicalize(path)
+        .ok()
+        .filter(|dir| dir.is_dir())
+        .ok_or_else(|| AppError::path(path, "Not a directory").into_response(Some(path.to_string())))
+}
+
+/// True for events that mean a file was created, moved in or written to
+fn is_write_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+/// True for regular files directly inside the watched folder that aren't hidden or partial
+fn is_candidate(source_dir: &Path, path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    path.parent() == Some(source_dir)
+        && path.is_file()
+        && !name.starts_with('.')
+        && !PARTIAL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+fn emit_watch_event(app: &AppHandle, event: WatchEvent) {
+    app.emit(WATCH_EVENT, &event).ok();
+}
+
+/// Encrypts one dropped file into the target folder and shreds it if configured
+fn handle_file(app: &AppHandle, config: &WatchConfig, path: &Path) {
+    let source_path = path.display().to_string();
+    // An empty password makes `encrypt_file` use the remembered key or the session
+    let password = match &config.key {
+        WatchKey::Password(password) => password.as_str(),
+        WatchKey::Remembered | WatchKey::Session => "",
+    };
+    let result = tauri::async_runtime::block_on(encrypt_file(
+        app.clone(),
+        &source_path,
+        password,
+        false,
+        Some(config.options.encryption.clone()),
+    ));
+    let response = match result {
+        Ok(response) | Err(response) => response,
+    };
+    let encrypted = matches!(response.text_code, ResponseTextCode::EncryptionSuccessful);
+    emit_watch_event(app, WatchEvent {
+        status: response.status,
+        text_code: response.text_code,
+        source_path: source_path.clone(),
+        output_path: encrypted.then(|| response.file_path.clone()).flatten(),
+        timestamp: response.timestamp,
+        detail: response.detail,
+    });
+
+    // Skipped and failed files keep their only copy
+    if !encrypted || !config.options.shred_source {
+        return;
+    }
+    let (status, text_code, detail) = match shred_file(path) {
+        Ok(()) => {
+            add_log_internal(LogLevel::Info, ResponseTextCode::FileShredded, Some(source_path.clone())).ok();
+            (Status::Success, ResponseTextCode::FileShredded, None)
+        }
+        Err(e) => {
+            add_log_internal(LogLevel::Error, ResponseTextCode::FileDeleteFailed, Some(source_path.clone())).ok();
+            (Status::Error, ResponseTextCode::FileDeleteFailed, Some(e.to_string()))
+        }
+    };
+    emit_watch_event(app, WatchEvent {
+        status,
+        text_code,
+        source_path,
+        output_path: None,
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        detail,
+    });
+}
+
+/// Ends the watch because the session it encrypts with has locked
+fn stop_locked_watch(app: &AppHandle, config: &WatchConfig) {
+    let state = app.state::<WatchState>();
+    let mut watch = state.0.lock().unwrap_or_else(PoisonError::into_inner);
+    // A newer watch may have replaced this one already
+    let stopped = match watch.as_ref() {
+        Some(current) if current.id == config.id => watch.take(),
+        _ => None,
+    };
+    drop(watch);
+    drop(stopped);
+
+    let source_path = config.source_dir.display().to_string();
+    add_log_internal(LogLevel::Info, ResponseTextCode::WatchStopped, Some(source_path.clone())).ok();
+    emit_watch_event(app, WatchEvent {
+        status: Status::Success,
+        text_code: ResponseTextCode::WatchStopped,
+        source_path,
+        output_path: None,
+        timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        detail: Some("The session locked".to_string()),
+    });
+}
+
+/// Collects file events and encrypts each file once it has settled
+///
+/// Returns when the watcher is dropped and the channel disconnects, or when
+/// the session of a session watch locks.
+fn run_worker(app: AppHandle, config: WatchConfig, events: Receiver<notify::Result<Event>>) {
+    let mut debouncer = Debouncer::default();
+    loop {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if is_write_event(&event.kind) => {
+                for path in event.paths {
+                    if is_candidate(&config.source_dir, &path) {
+                        debouncer.changed(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Remove(_)) => {
+                for path in &event.paths {
+                    debouncer.removed(path);
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => {
+                let source_path = config.source_dir.display().to_string();
+                add_log_internal(LogLevel::Error, ResponseTextCode::WatchFailed, Some(source_path.clone())).ok();
+                emit_watch_event(&app, WatchEvent {
+                    status: Status::Error,
+                    text_code: ResponseTextCode::WatchFailed,
+                    source_path,
+                    output_path: None,
+                    timestamp: Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+                    detail: Some(e.to_string()),
+                });
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if matches!(config.key, WatchKey::Session) && !app.state::<SessionState>().is_unlocked() {
+            stop_locked_watch(&app, &config);
+            break;
+        }
+        for path in debouncer.settled(Instant::now()) {
+            handle_file(&app, &config, &path);
+            debouncer.handled(&path);
+        }
+    }
+}
+
+/// Starts encrypting every file dropped into `source_dir` into `target_dir`
+///
+/// Only files added or changed after the watch starts are encrypted, and
+/// subfolders are ignored. A running watch is replaced.
+///
+/// A given password is held in memory until the watch stops. With an empty
+/// password, a key remembered in the keystore for the watched folder is used,
+/// otherwise the unlocked session's password; such a watch stops as soon as
+/// the session locks.
+///
+/// # Events
+/// `watch_event` with a [`WatchEvent`] for every encrypted, skipped, failed or shredded file
+#[tauri::command]
+pub async fn start_watch(
+    app: AppHandle,
+    state: State<'_, WatchState>,
+    source_dir: &str,
+    target_dir: &str,
+    password: &str,
+    options: Option<WatchOptions>,
+) -> Result<AppResponse, AppResponse> {
+    let source = watch_dir(source_dir)?;
+    let key = if !password.is_empty() {
+        validate_password(password).map_err(|_| {
+            create_error_response(ResponseTextCode::InvalidPassword, None)
+        })?;
+        WatchKey::Password(CommandPassword::new(password))
+    } else if app.state::<KeyStore>().remembered_key(&source).is_some() {
+        WatchKey::Remembered
+    } else if app.state::<SessionState>().is_unlocked() {
+        WatchKey::Session
+    } else {
+        return Err(create_error_response(ResponseTextCode::SessionLocked, None));
+    };
+    let target = watch_dir(target_dir)?;
+    // Output written into the watched folder would be encrypted again
+    if source == target {
+        return Err(AppError::InvalidOptions("The target folder must differ from the watched folder".to_string())
+            .into_response(Some(source_dir.to_string())));
+    }
+
+    let mut options = options.unwrap_or_default();
+    options.encryption.output.output_dir = Some(target.display().to_string());
+
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|e| AppError::InvalidOptions(e.to_string()).into_response(Some(source_dir.to_string())))?;
+    watcher
+        .watch(&source, RecursiveMode::NonRecursive)
+        .map_err(|e| AppError::InvalidOptions(e.to_string()).into_response(Some(source_dir.to_string())))?;
+
+    let id = NEXT_WATCH_ID.fetch_add(1, Ordering::Relaxed);
+    let config = WatchConfig {
+        id,
+        source_dir: source.clone(),
+        key,
+        options,
+    };
+    let worker_app = app.clone();
+    thread::spawn(move || run_worker(worker_app, config, events));
+
+    let previous = state.0.lock().unwrap_or_else(PoisonError::into_inner).replace(FolderWatch {
+        id,
+        _watcher: watcher,
+        source_dir: source,
+    });
+    drop(previous);
+
+    Ok(create_info_response(ResponseTextCode::WatchStarted, source_dir.to_string()))
+}
+
+/// Stops the folder watch; files already being encrypted are finished
+#[tauri::command]
+pub async fn stop_watch(state: State<'_, WatchState>) -> Result<AppResponse, AppResponse> {
+    let watch = state.0.lock().unwrap_or_else(PoisonError::into_inner).take();
+    match watch {
+        Some(watch) => Ok(create_info_response(
+            ResponseTextCode::WatchStopped,
+            watch.source_dir.display().to_string(),
+        )),
+        None => Err(create_error_response(ResponseTextCode::WatchNotActive, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_util::{test_dir, TestDir};
+
+    /// A fresh watched folder holding `dropped.txt`
+    ///
+    /// # Returns
+    /// The directory, the folder made canonical and the file
+    fn setup(name: &str) -> (TestDir, PathBuf, PathBuf) {
+        let dir = test_dir(name);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let folder = fs::canonicalize(&dir).unwrap();
+        let file = folder.join("dropped.txt");
+        fs::write(&file, b"dropped").unwrap();
+        (dir, folder, file)
+    }
+
+    #[test]
+    fn waits_until_a_file_settles() {
+        let (_dir, _, file) = setup("debounce");
+        let start = Instant::now();
+        let mut debouncer = Debouncer::default();
+
+        debouncer.changed(file.clone(), start);
+        assert!(debouncer.settled(start + SETTLE_TIME / 2).is_empty());
+        // Still being written
+        debouncer.changed(file.clone(), start + SETTLE_TIME / 2);
+        assert!(debouncer.settled(start + SETTLE_TIME).is_empty());
+
+        assert_eq!(debouncer.settled(start + SETTLE_TIME * 3 / 2), vec![file.clone()]);
+        assert!(debouncer.settled(start + SETTLE_TIME * 2).is_empty());
+    }
+
+    #[test]
+    fn drops_files_removed_while_settling() {
+        let (_dir, _, file) = setup("removed");
+        let start = Instant::now();
+        let mut debouncer = Debouncer::default();
+
+        debouncer.changed(file.clone(), start);
+        debouncer.removed(&file);
+        assert!(debouncer.settled(start + SETTLE_TIME).is_empty());
+
+        // Moved away without an event
+        debouncer.changed(file.clone(), start);
+        fs::remove_file(&file).unwrap();
+        assert!(debouncer.settled(start + SETTLE_TIME).is_empty());
+    }
+
+    #[test]
+    fn ignores_events_from_its_own_writes() {
+        let (_dir, _, file) = setup("own-writes");
+        let start = Instant::now();
+        let mut debouncer = Debouncer::default();
+        debouncer.changed(file.clone(), start);
+        assert_eq!(debouncer.settled(start + SETTLE_TIME), vec![file.clone()]);
+
+        // A shred that overwrote the file but couldn't delete it
+        fs::write(&file, b"shredded").unwrap();
+        debouncer.handled(&file);
+        debouncer.changed(file.clone(), start + SETTLE_TIME);
+
+        assert!(debouncer.settled(start + SETTLE_TIME * 2).is_empty());
+    }
+
+    #[test]
+    fn encrypts_a_handled_file_again_once_it_changes() {
+        let (_dir, _, file) = setup("changed");
+        let start = Instant::now();
+        let mut debouncer = Debouncer::default();
+        debouncer.changed(file.clone(), start);
+        assert_eq!(debouncer.settled(start + SETTLE_TIME), vec![file.clone()]);
+        debouncer.handled(&file);
+
+        fs::write(&file, b"dropped, then edited").unwrap();
+        debouncer.changed(file.clone(), start + SETTLE_TIME);
+
+        assert_eq!(debouncer.settled(start + SETTLE_TIME * 2), vec![file.clone()]);
+    }
+
+    #[test]
+    fn only_takes_visible_files_directly_in_the_folder() {
+        let (_dir, folder, file) = setup("candidates");
+        for name in [".hidden", "download.part", "notes.txt~", "sub/nested.txt"] {
+            fs::write(folder.join(name), b"").unwrap();
+        }
+
+        assert!(is_candidate(&folder, &file));
+        for name in [".hidden", "download.part", "notes.txt~", "sub/nested.txt", "sub", "missing.txt"] {
+            assert!(!is_candidate(&folder, &folder.join(name)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod folder;
+
+pub use folder::{start_watch, stop_watch, WatchState};
@@ -0,0 +1,11 @@
+-- Logs database as created before migrations existed: no schema_version table
+CREATE TABLE logs (
+    timestamp BIGINT PRIMARY KEY,
+    level VARCHAR NOT NULL,
+    text_code VARCHAR NOT NULL,
+    file_path VARCHAR
+);
+INSERT INTO logs VALUES
+    (1700000000, 'info', 'encryption_successful', '/home/user/report.pdf.enc'),
+    (1700000060, 'error', 'wrong_password', '/home/user/report.pdf.enc'),
+    (1700000120, 'info', 'logs_downloaded', NULL);
@@ -0,0 +1,13 @@
+-- Logs database at schema version 1: the original logs table, recorded in schema_version
+CREATE TABLE schema_version (version BIGINT NOT NULL);
+INSERT INTO schema_version VALUES (1);
+CREATE TABLE logs (
+    timestamp BIGINT PRIMARY KEY,
+    level VARCHAR NOT NULL,
+    text_code VARCHAR NOT NULL,
+    file_path VARCHAR
+);
+INSERT INTO logs VALUES
+    (1700000000, 'info', 'encryption_successful', '/home/user/report.pdf.enc'),
+    (1700000060, 'error', 'wrong_password', '/home/user/report.pdf.enc'),
+    (1700000120, 'info', 'logs_downloaded', NULL);
@@ -12,6 +12,53 @@ export enum ResponseTextCode {
   ParentDirectoryRetrieveFailed = 'parent_directory_retrieve_failed',
   FileNameExtractionFailed = 'file_name_extraction_failed',
   FileExtensionExtractionFailed = 'file_extension_extraction_failed',
+  FileCreationSuccessful = 'file_creation_successful',
+  LogsDownloaded = 'logs_downloaded',
+  NoLogs = 'no_logs',
+  StateUnavailable = 'state_unavailable',
+  FileDeleteFailed = 'file_delete_failed',
+  PreviewTooLarge = 'preview_too_large',
+  PreviewUnsupported = 'preview_unsupported',
+  InvalidArmor = 'invalid_armor',
+  ClipboardReadFailed = 'clipboard_read_failed',
+  ClipboardWriteFailed = 'clipboard_write_failed',
+  MetadataRestoreFailed = 'metadata_restore_failed',
+  FileSkipped = 'file_skipped',
+  FileAlreadyExists = 'file_already_exists',
+  InvalidNameTemplate = 'invalid_name_template',
+  WrongPassword = 'wrong_password',
+  CorruptedChunk = 'corrupted_chunk',
+  TruncatedFile = 'truncated_file',
+  UnsupportedFormat = 'unsupported_format',
+  PartiallyRecovered = 'partially_recovered',
+  ChunksRepaired = 'chunks_repaired',
+  MissingVolume = 'missing_volume',
+  CorruptedVolume = 'corrupted_volume',
+  InvalidOptions = 'invalid_options',
+  VaultCreated = 'vault_created',
+  VaultOpened = 'vault_opened',
+  VaultEntryAdded = 'vault_entry_added',
+  VaultEntryExtracted = 'vault_entry_extracted',
+  VaultEntryDeleted = 'vault_entry_deleted',
+  VaultEntryNotFound = 'vault_entry_not_found',
+  WatchStarted = 'watch_started',
+  WatchStopped = 'watch_stopped',
+  WatchNotActive = 'watch_not_active',
+  WatchFailed = 'watch_failed',
+  FileShredded = 'file_shredded',
+  SessionUnlocked = 'session_unlocked',
+  SessionLocked = 'session_locked',
+  KeyRemembered = 'key_remembered',
+  KeyForgotten = 'key_forgotten',
+  KeyNotRemembered = 'key_not_remembered',
+  KeyringFailed = 'keyring_failed',
+  KeyringUnavailable = 'keyring_unavailable',
+  SigningKeyCreated = 'signing_key_created',
+  InvalidSignature = 'invalid_signature',
+  HashComputed = 'hash_computed',
+  HashMismatch = 'hash_mismatch',
+  FileUpgraded = 'file_upgraded',
+  AlreadyUpgraded = 'already_upgraded',
 }
 
 export enum Status {
@@ -23,14 +70,37 @@ export type ProcessingStats = {
   total_size_bytes: number
   processing_time_seconds: number
   average_speed_mbps: number
+  content_hash?: string
 }
 
+export type RecoveryMode = 'zero_fill' | 'skip'
+
+export type DamagedRange = {
+  /** null for data cut off at the end of the file */
+  chunk_index: number | null
+  offset: number
+  length: number
+}
+
+export type RecoveryReport = {
+  mode: RecoveryMode
+  damaged_ranges: DamagedRange[]
+  recovered_bytes: number
+  lost_bytes: number
+}
+
+export type SignatureStatus = 'verified' | 'unverified'
+
 export type AppResponse = {
   status: Status
   text_code: ResponseTextCode
   file_path?: string
   timestamp: string
   stats?: ProcessingStats
+  recovery?: RecoveryReport
+  detail?: string
+  signer_fingerprint?: string
+  signature?: SignatureStatus
 }
 
 export type LogLevel = 'info' | 'error' | 'warning' | 'debug' | 'critical'